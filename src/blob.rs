@@ -0,0 +1,234 @@
+//! 图片数据按内容保存在磁盘上
+//!
+//! 文件名是数据的 sha256，相同的图片只保存一份，缩略图保存在同名的 `.thumb` 文件中。
+//! 内存中只保留缩略图和尺寸，复制、预览等需要原图时再读取。
+//! 删除记录时不会马上删除文件，保存历史记录后再清理没有记录引用的文件，
+//! 避免程序中途退出时历史记录文件引用的图片已经被删除
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
+
+use sha2::{Digest, Sha256};
+
+/// 缩略图的最大边长
+pub(crate) const THUMB: u32 = 256;
+
+/// 图片的元数据
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Image {
+    /// sha256，十六进制
+    pub hash: String,
+    /// 原图的字节数
+    pub size: usize,
+    pub width: u32,
+    pub height: u32,
+}
+
+pub(crate) fn hash(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// 生成缩略图，返回 (缩略图, 原图宽, 原图高)。
+/// 原图不超过缩略图大小时直接使用原图，无法解码时缩略图为空
+pub(crate) fn thumbnail(data: &[u8]) -> (Vec<u8>, u32, u32) {
+    let Ok(img) = image::load_from_memory(data) else {
+        return (Vec::new(), 0, 0);
+    };
+    let (width, height) = (img.width(), img.height());
+    if width <= THUMB && height <= THUMB {
+        return (data.to_vec(), width, height);
+    }
+    let thumb =
+        crate::imaging::encode(&img.thumbnail(THUMB, THUMB), crate::imaging::Format::Png, 0)
+            .unwrap_or_default();
+    (thumb, width, height)
+}
+
+/// 是否是 blob 的文件名，清理时只处理这些文件
+fn is_blob_name(name: &str) -> bool {
+    let stem = name.strip_suffix(".thumb").unwrap_or(name);
+    stem.len() == 64 && stem.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// 读取磁盘上的原图，不需要持有 store，用于在其他线程中按需读取
+#[derive(Clone)]
+pub(crate) struct Reader {
+    dir: PathBuf,
+}
+
+impl Reader {
+    pub fn get(&self, hash: &str) -> Result<Vec<u8>, String> {
+        if !is_blob_name(hash) {
+            return Err(format!("invalid blob {}", hash));
+        }
+        std::fs::read(self.dir.join(hash)).map_err(|e| format!("{} {}", hash, e))
+    }
+}
+
+/// blob 存储，没有数据目录时保存在内存中
+pub(crate) struct Blobs {
+    dir: Option<PathBuf>,
+    memory: HashMap<String, Vec<u8>>,
+}
+
+impl Blobs {
+    pub fn memory() -> Self {
+        Blobs {
+            dir: None,
+            memory: HashMap::new(),
+        }
+    }
+
+    pub fn disk(dir: PathBuf) -> Self {
+        Blobs {
+            dir: Some(dir),
+            memory: HashMap::new(),
+        }
+    }
+
+    /// 写入数据，已存在时跳过
+    fn write(&mut self, name: String, data: &[u8]) -> Result<(), String> {
+        let Some(dir) = &self.dir else {
+            self.memory.entry(name).or_insert_with(|| data.to_vec());
+            return Ok(());
+        };
+        let path = dir.join(&name);
+        if path.exists() {
+            return Ok(());
+        }
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        // 先写临时文件再替换，避免留下不完整的文件
+        let tmp = dir.join(format!("{}.tmp", name));
+        std::fs::write(&tmp, data).map_err(|e| e.to_string())?;
+        std::fs::rename(&tmp, &path).map_err(|e| e.to_string())
+    }
+
+    fn read(&self, name: &str) -> Result<Vec<u8>, String> {
+        match &self.dir {
+            Some(dir) => std::fs::read(dir.join(name)).map_err(|e| format!("{} {}", name, e)),
+            None => self
+                .memory
+                .get(name)
+                .cloned()
+                .ok_or_else(|| format!("{} not found", name)),
+        }
+    }
+
+    /// 保存原图和缩略图
+    pub fn put(&mut self, image: &Image, data: &[u8], thumb: &[u8]) -> Result<(), String> {
+        self.write(image.hash.clone(), data)?;
+        self.write(format!("{}.thumb", image.hash), thumb)
+    }
+
+    /// 读取原图
+    pub fn get(&self, hash: &str) -> Result<Vec<u8>, String> {
+        self.read(hash)
+    }
+
+    /// 保存在磁盘上时返回读取原图的 Reader
+    pub fn reader(&self) -> Option<Reader> {
+        self.dir.clone().map(|dir| Reader { dir })
+    }
+
+    pub fn contains(&self, hash: &str) -> bool {
+        match &self.dir {
+            Some(dir) => dir.join(hash).is_file(),
+            None => self.memory.contains_key(hash),
+        }
+    }
+
+    /// 读取缩略图，缩略图丢失时从原图重新生成
+    pub fn thumb(&mut self, hash: &str) -> Result<Vec<u8>, String> {
+        let name = format!("{}.thumb", hash);
+        if let Ok(thumb) = self.read(&name) {
+            return Ok(thumb);
+        }
+        let (thumb, _, _) = thumbnail(&self.get(hash)?);
+        self.write(name, &thumb)?;
+        Ok(thumb)
+    }
+
+    /// 删除不在 live 中的 blob，返回删除的数量
+    pub fn gc(&mut self, live: &HashSet<&str>) -> usize {
+        let keep = |name: &str| {
+            let stem = name.strip_suffix(".thumb").unwrap_or(name);
+            live.contains(stem)
+        };
+        let Some(dir) = &self.dir else {
+            let before = self.memory.len();
+            self.memory.retain(|name, _| keep(name));
+            return before - self.memory.len();
+        };
+        let Ok(files) = std::fs::read_dir(dir) else {
+            return 0;
+        };
+        let mut n = 0;
+        for f in files.flatten() {
+            let name = f.file_name();
+            let Some(name) = name.to_str() else {
+                continue;
+            };
+            // 写到一半的临时文件同样清理
+            let (stem, tmp) = match name.strip_suffix(".tmp") {
+                Some(stem) => (stem, true),
+                None => (name, false),
+            };
+            if !is_blob_name(stem) || !tmp && keep(stem) {
+                continue;
+            }
+            match std::fs::remove_file(f.path()) {
+                Ok(()) => n += 1,
+                Err(e) => {
+                    s_error!("remove blob {} fail {}", name, e);
+                }
+            }
+        }
+        n
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disk_gc() {
+        let dir = crate::testdir::TempDir::new("blob");
+        let mut blobs = Blobs::disk(dir.join("blobs"));
+
+        let img = image::DynamicImage::ImageRgb8(image::RgbImage::new(600, 300));
+        let png = crate::imaging::encode(&img, crate::imaging::Format::Png, 0).unwrap();
+        let (thumb, width, height) = thumbnail(&png);
+        assert_eq!((width, height), (600, 300));
+        let small = image::load_from_memory(&thumb).unwrap();
+        assert_eq!((small.width(), small.height()), (THUMB, THUMB / 2));
+
+        let image = Image {
+            hash: hash(&png),
+            size: png.len(),
+            width,
+            height,
+        };
+        blobs.put(&image, &png, &thumb).unwrap();
+        // 相同内容不会重复写入
+        blobs.put(&image, &png, &thumb).unwrap();
+        assert_eq!(blobs.get(&image.hash).unwrap(), png);
+        // 缩略图丢失时重新生成
+        std::fs::remove_file(dir.join("blobs").join(format!("{}.thumb", image.hash))).unwrap();
+        assert_eq!(blobs.thumb(&image.hash).unwrap(), thumb);
+
+        let blob_dir = dir.join("blobs");
+        std::fs::write(blob_dir.join(format!("{}.tmp", image.hash)), b"partial").unwrap();
+        std::fs::write(blob_dir.join("other.txt"), b"keep").unwrap();
+        assert_eq!(blobs.gc(&HashSet::from([image.hash.as_str()])), 1);
+        assert!(blobs.contains(&image.hash));
+        assert_eq!(blobs.gc(&HashSet::new()), 2);
+        assert!(!blobs.contains(&image.hash));
+        assert!(blob_dir.join("other.txt").exists());
+    }
+}
@@ -0,0 +1,277 @@
+//! 内容类型识别
+//!
+//! 单行的内容依次尝试 uuid、颜色、日期、数字、邮箱、url、路径，
+//! 多行的内容尝试 json 和代码，都不是时为普通文本
+
+/// json 解析的大小上限，超过时不识别为 json
+const JSON_LIMIT: usize = 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Kind {
+    Text,
+    Image,
+    Url,
+    Email,
+    Path,
+    /// rgba
+    Color([u8; 4]),
+    Json,
+    Number,
+    Date,
+    Uuid,
+    /// 代码，附带语言
+    Code(&'static str),
+}
+
+impl Kind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Kind::Text => "text",
+            Kind::Image => "image",
+            Kind::Url => "url",
+            Kind::Email => "email",
+            Kind::Path => "path",
+            Kind::Color(_) => "color",
+            Kind::Json => "json",
+            Kind::Number => "number",
+            Kind::Date => "date",
+            Kind::Uuid => "uuid",
+            Kind::Code(lang) => lang,
+        }
+    }
+}
+
+pub(crate) fn classify(text: &str) -> Kind {
+    let t = text.trim();
+    if t.is_empty() {
+        return Kind::Text;
+    }
+    if !t.contains('\n') {
+        if is_uuid(t) {
+            return Kind::Uuid;
+        }
+        if let Some((c, _)) = crate::color::parse(t) {
+            return Kind::Color(c);
+        }
+        if is_date(t) {
+            return Kind::Date;
+        }
+        if is_number(t) {
+            return Kind::Number;
+        }
+        if is_email(t) {
+            return Kind::Email;
+        }
+        if domain(t).is_some() {
+            return Kind::Url;
+        }
+        if is_path(t) {
+            return Kind::Path;
+        }
+    }
+    if (t.starts_with('{') || t.starts_with('['))
+        && t.len() <= JSON_LIMIT
+        && serde_json::from_str::<serde::de::IgnoredAny>(t).is_ok()
+    {
+        return Kind::Json;
+    }
+    match crate::preview::detect_language(t) {
+        // 看起来像 json 但是无法解析
+        "" | "json" => Kind::Text,
+        lang => Kind::Code(lang),
+    }
+}
+
+fn is_hex(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+fn is_digits(s: &str, len: std::ops::RangeInclusive<usize>) -> bool {
+    len.contains(&s.len()) && s.bytes().all(|b| b.is_ascii_digit())
+}
+
+fn is_uuid(t: &str) -> bool {
+    let parts: Vec<&str> = t.split('-').collect();
+    parts.len() == 5
+        && parts
+            .iter()
+            .zip([8, 4, 4, 4, 12])
+            .all(|(p, n)| p.len() == n && is_hex(p))
+}
+
+/// `YYYY-MM-DD` 或 `YYYY/MM/DD`，后面可以带时间和时区
+fn is_date(t: &str) -> bool {
+    let (date, time) = match t.find(['T', ' ']) {
+        Some(i) => (&t[..i], Some(&t[i + 1..])),
+        None => (t, None),
+    };
+    let parts: Vec<&str> = date.split(['-', '/']).collect();
+    let ok = parts.len() == 3
+        && is_digits(parts[0], 4..=4)
+        && is_digits(parts[1], 1..=2)
+        && is_digits(parts[2], 1..=2)
+        && (1..=12).contains(&parts[1].parse::<u32>().unwrap_or(0))
+        && (1..=31).contains(&parts[2].parse::<u32>().unwrap_or(0));
+    if !ok {
+        return false;
+    }
+    let Some(time) = time else {
+        return true;
+    };
+    let time = time.trim_end_matches('Z');
+    // 去掉时区
+    let time = match time.rfind(['+', '-']) {
+        Some(i) => &time[..i],
+        None => time,
+    };
+    let time = time.split('.').next().unwrap_or(time);
+    let parts: Vec<&str> = time.split(':').collect();
+    (parts.len() == 2 || parts.len() == 3) && parts.iter().all(|p| is_digits(p, 2..=2))
+}
+
+/// 整数、小数、科学计数法、十六进制，允许千分位和下划线
+fn is_number(t: &str) -> bool {
+    let t = t.strip_prefix(['+', '-']).unwrap_or(t);
+    if let Some(hex) = t.strip_prefix("0x").or_else(|| t.strip_prefix("0X")) {
+        return is_hex(hex);
+    }
+    let t: String = t.chars().filter(|c| *c != '_' && *c != ',').collect();
+    t.starts_with(|c: char| c.is_ascii_digit()) && t.parse::<f64>().is_ok()
+}
+
+fn is_email(t: &str) -> bool {
+    let Some((local, domain)) = t.split_once('@') else {
+        return false;
+    };
+    !local.is_empty()
+        && local
+            .chars()
+            .all(|c| c.is_alphanumeric() || "._%+-".contains(c))
+        && domain.contains('.')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+        && domain
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '.' || c == '-')
+}
+
+/// url 的域名，不是 url 时返回 None
+pub(crate) fn domain(t: &str) -> Option<&str> {
+    if t.contains(char::is_whitespace) {
+        return None;
+    }
+    let rest = match t.split_once("://") {
+        Some((scheme, rest))
+            if !scheme.is_empty()
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "+.-".contains(c)) =>
+        {
+            rest
+        }
+        _ => t.strip_prefix("www.").map(|_| t)?,
+    };
+    let host = rest.split(['/', '?', '#']).next()?;
+    let host = host.rsplit_once('@').map_or(host, |(_, h)| h);
+    (!host.is_empty()).then_some(host)
+}
+
+/// 打开 url 时使用的地址，省略了协议的补上 https
+pub(crate) fn url_target(t: &str) -> String {
+    let t = t.trim();
+    if t.contains("://") {
+        t.to_string()
+    } else {
+        format!("https://{}", t)
+    }
+}
+
+/// 展开 `~/` 后的本地路径
+pub(crate) fn local_path(t: &str) -> std::path::PathBuf {
+    let t = t.trim();
+    match (t.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => std::path::PathBuf::from(t),
+    }
+}
+
+fn is_path(t: &str) -> bool {
+    let bytes = t.as_bytes();
+    t.starts_with('/')
+        || t.starts_with("~/")
+        || t.starts_with("./")
+        || t.starts_with("../")
+        || (bytes.len() >= 3
+            && bytes[0].is_ascii_alphabetic()
+            && bytes[1] == b':'
+            && (bytes[2] == b'\\' || bytes[2] == b'/'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Kind::*, *};
+
+    #[test]
+    fn table() {
+        let cases: &[(&str, Kind)] = &[
+            ("", Text),
+            ("hello world", Text),
+            ("https://example.com/a?b=1", Url),
+            ("  http://localhost:8080  ", Url),
+            ("www.rust-lang.org", Url),
+            ("ftp://user@files.example.com/pub", Url),
+            ("https://example.com and more", Text),
+            ("someone@example.com", Email),
+            ("first.last+tag@sub.example.org", Email),
+            ("not@domain", Text),
+            ("@example.com", Text),
+            ("/usr/local/bin", Path),
+            ("~/Documents/a.txt", Path),
+            ("../src/main.rs", Path),
+            ("C:\\Windows\\System32", Path),
+            ("#fff", Color([255, 255, 255, 255])),
+            ("#ff000080", Color([255, 0, 0, 128])),
+            ("#1e90ff", Color([30, 144, 255, 255])),
+            ("rgb(1, 2, 3)", Color([1, 2, 3, 255])),
+            ("rgba(255 0 0 / 0.5)", Color([255, 0, 0, 128])),
+            ("hsl(0, 100%, 50%)", Color([255, 0, 0, 255])),
+            ("Tomato", Color([255, 99, 71, 255])),
+            ("#xyz", Text),
+            ("rgb(300, 0, 0)", Text),
+            ("{\"a\": [1, 2]}", Json),
+            ("[1, 2, 3]", Json),
+            ("{not json}", Text),
+            ("[1, 2,]", Text),
+            ("42", Number),
+            ("-3.14", Number),
+            ("1,234,567", Number),
+            ("6.02e23", Number),
+            ("0xFF", Number),
+            ("1_000", Number),
+            ("12abc", Text),
+            ("2024-02-29", Date),
+            ("2024/1/5", Date),
+            ("2023-09-28T09:32:24Z", Date),
+            ("2023-09-28 09:32:24.123+08:00", Date),
+            ("2023-13-01", Text),
+            ("550e8400-e29b-41d4-a716-446655440000", Uuid),
+            ("550e8400-e29b-41d4-a716-44665544000", Text),
+            ("fn main() {\n    println!(\"hi\");\n}", Code("rs")),
+            ("#!/bin/sh\necho hi", Code("sh")),
+            ("line one\nline two", Text),
+        ];
+        for (text, kind) in cases {
+            assert_eq!(classify(text), *kind, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn url_domain() {
+        assert_eq!(
+            domain("https://user:pw@example.com:443/x"),
+            Some("example.com:443")
+        );
+        assert_eq!(domain("www.example.com/a"), Some("www.example.com"));
+        assert_eq!(domain("example.com"), None);
+    }
+}
@@ -0,0 +1,387 @@
+//! 颜色的解析和格式转换
+//!
+//! 支持 `#rgb` `#rrggbb` 等十六进制写法、`rgb()` `hsl()` 以及 css 颜色名，
+//! 颜色统一保存为 rgba
+
+/// 颜色的写法
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Notation {
+    Hex,
+    Rgb,
+    Hsl,
+    Name,
+}
+
+impl Notation {
+    pub const ALL: [Notation; 4] = [Notation::Hex, Notation::Rgb, Notation::Hsl, Notation::Name];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Notation::Hex => "hex",
+            Notation::Rgb => "rgb",
+            Notation::Hsl => "hsl",
+            Notation::Name => "name",
+        }
+    }
+}
+
+/// 解析颜色，返回 rgba 和原来的写法
+pub(crate) fn parse(t: &str) -> Option<([u8; 4], Notation)> {
+    let t = t.trim();
+    if let Some(hex) = t.strip_prefix('#') {
+        return parse_hex(hex).map(|c| (c, Notation::Hex));
+    }
+    let lower = t.to_ascii_lowercase();
+    if let Some(args) = function(&lower, "rgb") {
+        return parse_rgb(&args).map(|c| (c, Notation::Rgb));
+    }
+    if let Some(args) = function(&lower, "hsl") {
+        return parse_hsl(&args).map(|c| (c, Notation::Hsl));
+    }
+    NAMED
+        .iter()
+        .find(|(n, _)| *n == lower)
+        .map(|(_, [r, g, b])| ([*r, *g, *b, 255], Notation::Name))
+}
+
+/// 按指定写法输出，颜色名只在完全匹配且不透明时存在
+pub(crate) fn format(c: [u8; 4], notation: Notation) -> Option<String> {
+    let [r, g, b, a] = c;
+    Some(match notation {
+        Notation::Hex if a == 255 => format!("#{:02x}{:02x}{:02x}", r, g, b),
+        Notation::Hex => format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a),
+        Notation::Rgb if a == 255 => format!("rgb({}, {}, {})", r, g, b),
+        Notation::Rgb => format!("rgba({}, {}, {}, {})", r, g, b, alpha(a)),
+        Notation::Hsl => {
+            let (h, s, l) = to_hsl([r, g, b]);
+            let (h, s, l) = (
+                h.round() as u32 % 360,
+                (s * 100.0).round(),
+                (l * 100.0).round(),
+            );
+            if a == 255 {
+                format!("hsl({}, {}%, {}%)", h, s, l)
+            } else {
+                format!("hsla({}, {}%, {}%, {})", h, s, l, alpha(a))
+            }
+        }
+        Notation::Name => {
+            if a != 255 {
+                return None;
+            }
+            NAMED.iter().find(|(_, v)| *v == [r, g, b])?.0.to_string()
+        }
+    })
+}
+
+/// 透明度保留两位小数
+fn alpha(a: u8) -> String {
+    let v = (a as f32 / 255.0 * 100.0).round() / 100.0;
+    v.to_string()
+}
+
+/// `name(...)` 或 `namea(...)` 的参数，按逗号、空格、斜杠拆分
+fn function(t: &str, name: &str) -> Option<Vec<String>> {
+    let rest = t.strip_prefix(name)?;
+    let rest = rest.strip_prefix('a').unwrap_or(rest);
+    let args = rest.strip_prefix('(')?.strip_suffix(')')?;
+    let parts: Vec<String> = args
+        .split([',', ' ', '/'])
+        .filter(|p| !p.is_empty())
+        .map(str::to_string)
+        .collect();
+    (parts.len() == 3 || parts.len() == 4).then_some(parts)
+}
+
+fn parse_hex(hex: &str) -> Option<[u8; 4]> {
+    if hex.is_empty() || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let digit = |i: usize| u8::from_str_radix(&hex[i..i + 1], 16).ok().map(|v| v * 17);
+    let byte = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    match hex.len() {
+        3 => Some([digit(0)?, digit(1)?, digit(2)?, 255]),
+        4 => Some([digit(0)?, digit(1)?, digit(2)?, digit(3)?]),
+        6 => Some([byte(0)?, byte(2)?, byte(4)?, 255]),
+        8 => Some([byte(0)?, byte(2)?, byte(4)?, byte(6)?]),
+        _ => None,
+    }
+}
+
+/// 透明度，可以是 0-1 的小数或百分比
+fn parse_alpha(p: Option<&String>) -> Option<u8> {
+    let Some(p) = p else {
+        return Some(255);
+    };
+    let v = match p.strip_suffix('%') {
+        Some(v) => v.parse::<f32>().ok()? / 100.0,
+        None => p.parse::<f32>().ok()?,
+    };
+    Some((v.clamp(0.0, 1.0) * 255.0).round() as u8)
+}
+
+fn parse_rgb(args: &[String]) -> Option<[u8; 4]> {
+    let channel = |p: &String| -> Option<u8> {
+        match p.strip_suffix('%') {
+            Some(v) => {
+                let v: f32 = v.parse().ok()?;
+                Some((v.clamp(0.0, 100.0) * 2.55).round() as u8)
+            }
+            None => p.parse().ok(),
+        }
+    };
+    Some([
+        channel(&args[0])?,
+        channel(&args[1])?,
+        channel(&args[2])?,
+        parse_alpha(args.get(3))?,
+    ])
+}
+
+fn parse_hsl(args: &[String]) -> Option<[u8; 4]> {
+    let h: f32 = args[0]
+        .strip_suffix("deg")
+        .unwrap_or(&args[0])
+        .parse()
+        .ok()?;
+    let percent = |p: &String| -> Option<f32> {
+        let v: f32 = p.strip_suffix('%')?.parse().ok()?;
+        Some(v.clamp(0.0, 100.0) / 100.0)
+    };
+    let [r, g, b] = from_hsl(h, percent(&args[1])?, percent(&args[2])?);
+    Some([r, g, b, parse_alpha(args.get(3))?])
+}
+
+/// 返回 (色相 0-360, 饱和度 0-1, 亮度 0-1)
+fn to_hsl([r, g, b]: [u8; 3]) -> (f32, f32, f32) {
+    let (r, g, b) = (r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let l = (max + min) / 2.0;
+    let d = max - min;
+    if d == 0.0 {
+        return (0.0, 0.0, l);
+    }
+    let s = d / (1.0 - (2.0 * l - 1.0).abs());
+    let h = if max == r {
+        60.0 * ((g - b) / d).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / d + 2.0)
+    } else {
+        60.0 * ((r - g) / d + 4.0)
+    };
+    (h, s, l)
+}
+
+fn from_hsl(h: f32, s: f32, l: f32) -> [u8; 3] {
+    let h = h.rem_euclid(360.0);
+    let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+    let x = c * (1.0 - ((h / 60.0) % 2.0 - 1.0).abs());
+    let m = l - c / 2.0;
+    let (r, g, b) = match (h / 60.0) as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let v = |v: f32| ((v + m) * 255.0).round().clamp(0.0, 255.0) as u8;
+    [v(r), v(g), v(b)]
+}
+
+/// css 颜色名，同一个值有多个名字时前面的优先
+const NAMED: &[(&str, [u8; 3])] = &[
+    ("black", [0, 0, 0]),
+    ("silver", [192, 192, 192]),
+    ("gray", [128, 128, 128]),
+    ("white", [255, 255, 255]),
+    ("maroon", [128, 0, 0]),
+    ("red", [255, 0, 0]),
+    ("purple", [128, 0, 128]),
+    ("fuchsia", [255, 0, 255]),
+    ("green", [0, 128, 0]),
+    ("lime", [0, 255, 0]),
+    ("olive", [128, 128, 0]),
+    ("yellow", [255, 255, 0]),
+    ("navy", [0, 0, 128]),
+    ("blue", [0, 0, 255]),
+    ("teal", [0, 128, 128]),
+    ("aqua", [0, 255, 255]),
+    ("aliceblue", [240, 248, 255]),
+    ("antiquewhite", [250, 235, 215]),
+    ("aquamarine", [127, 255, 212]),
+    ("azure", [240, 255, 255]),
+    ("beige", [245, 245, 220]),
+    ("bisque", [255, 228, 196]),
+    ("blanchedalmond", [255, 235, 205]),
+    ("blueviolet", [138, 43, 226]),
+    ("brown", [165, 42, 42]),
+    ("burlywood", [222, 184, 135]),
+    ("cadetblue", [95, 158, 160]),
+    ("chartreuse", [127, 255, 0]),
+    ("chocolate", [210, 105, 30]),
+    ("coral", [255, 127, 80]),
+    ("cornflowerblue", [100, 149, 237]),
+    ("cornsilk", [255, 248, 220]),
+    ("crimson", [220, 20, 60]),
+    ("cyan", [0, 255, 255]),
+    ("darkblue", [0, 0, 139]),
+    ("darkcyan", [0, 139, 139]),
+    ("darkgoldenrod", [184, 134, 11]),
+    ("darkgray", [169, 169, 169]),
+    ("darkgreen", [0, 100, 0]),
+    ("darkgrey", [169, 169, 169]),
+    ("darkkhaki", [189, 183, 107]),
+    ("darkmagenta", [139, 0, 139]),
+    ("darkolivegreen", [85, 107, 47]),
+    ("darkorange", [255, 140, 0]),
+    ("darkorchid", [153, 50, 204]),
+    ("darkred", [139, 0, 0]),
+    ("darksalmon", [233, 150, 122]),
+    ("darkseagreen", [143, 188, 143]),
+    ("darkslateblue", [72, 61, 139]),
+    ("darkslategray", [47, 79, 79]),
+    ("darkslategrey", [47, 79, 79]),
+    ("darkturquoise", [0, 206, 209]),
+    ("darkviolet", [148, 0, 211]),
+    ("deeppink", [255, 20, 147]),
+    ("deepskyblue", [0, 191, 255]),
+    ("dimgray", [105, 105, 105]),
+    ("dimgrey", [105, 105, 105]),
+    ("dodgerblue", [30, 144, 255]),
+    ("firebrick", [178, 34, 34]),
+    ("floralwhite", [255, 250, 240]),
+    ("forestgreen", [34, 139, 34]),
+    ("gainsboro", [220, 220, 220]),
+    ("ghostwhite", [248, 248, 255]),
+    ("gold", [255, 215, 0]),
+    ("goldenrod", [218, 165, 32]),
+    ("greenyellow", [173, 255, 47]),
+    ("grey", [128, 128, 128]),
+    ("honeydew", [240, 255, 240]),
+    ("hotpink", [255, 105, 180]),
+    ("indianred", [205, 92, 92]),
+    ("indigo", [75, 0, 130]),
+    ("ivory", [255, 255, 240]),
+    ("khaki", [240, 230, 140]),
+    ("lavender", [230, 230, 250]),
+    ("lavenderblush", [255, 240, 245]),
+    ("lawngreen", [124, 252, 0]),
+    ("lemonchiffon", [255, 250, 205]),
+    ("lightblue", [173, 216, 230]),
+    ("lightcoral", [240, 128, 128]),
+    ("lightcyan", [224, 255, 255]),
+    ("lightgoldenrodyellow", [250, 250, 210]),
+    ("lightgray", [211, 211, 211]),
+    ("lightgreen", [144, 238, 144]),
+    ("lightgrey", [211, 211, 211]),
+    ("lightpink", [255, 182, 193]),
+    ("lightsalmon", [255, 160, 122]),
+    ("lightseagreen", [32, 178, 170]),
+    ("lightskyblue", [135, 206, 250]),
+    ("lightslategray", [119, 136, 153]),
+    ("lightslategrey", [119, 136, 153]),
+    ("lightsteelblue", [176, 196, 222]),
+    ("lightyellow", [255, 255, 224]),
+    ("limegreen", [50, 205, 50]),
+    ("linen", [250, 240, 230]),
+    ("magenta", [255, 0, 255]),
+    ("mediumaquamarine", [102, 205, 170]),
+    ("mediumblue", [0, 0, 205]),
+    ("mediumorchid", [186, 85, 211]),
+    ("mediumpurple", [147, 112, 219]),
+    ("mediumseagreen", [60, 179, 113]),
+    ("mediumslateblue", [123, 104, 238]),
+    ("mediumspringgreen", [0, 250, 154]),
+    ("mediumturquoise", [72, 209, 204]),
+    ("mediumvioletred", [199, 21, 133]),
+    ("midnightblue", [25, 25, 112]),
+    ("mintcream", [245, 255, 250]),
+    ("mistyrose", [255, 228, 225]),
+    ("moccasin", [255, 228, 181]),
+    ("navajowhite", [255, 222, 173]),
+    ("oldlace", [253, 245, 230]),
+    ("olivedrab", [107, 142, 35]),
+    ("orange", [255, 165, 0]),
+    ("orangered", [255, 69, 0]),
+    ("orchid", [218, 112, 214]),
+    ("palegoldenrod", [238, 232, 170]),
+    ("palegreen", [152, 251, 152]),
+    ("paleturquoise", [175, 238, 238]),
+    ("palevioletred", [219, 112, 147]),
+    ("papayawhip", [255, 239, 213]),
+    ("peachpuff", [255, 218, 185]),
+    ("peru", [205, 133, 63]),
+    ("pink", [255, 192, 203]),
+    ("plum", [221, 160, 221]),
+    ("powderblue", [176, 224, 230]),
+    ("rebeccapurple", [102, 51, 153]),
+    ("rosybrown", [188, 143, 143]),
+    ("royalblue", [65, 105, 225]),
+    ("saddlebrown", [139, 69, 19]),
+    ("salmon", [250, 128, 114]),
+    ("sandybrown", [244, 164, 96]),
+    ("seagreen", [46, 139, 87]),
+    ("seashell", [255, 245, 238]),
+    ("sienna", [160, 82, 45]),
+    ("skyblue", [135, 206, 235]),
+    ("slateblue", [106, 90, 205]),
+    ("slategray", [112, 128, 144]),
+    ("slategrey", [112, 128, 144]),
+    ("snow", [255, 250, 250]),
+    ("springgreen", [0, 255, 127]),
+    ("steelblue", [70, 130, 180]),
+    ("tan", [210, 180, 140]),
+    ("thistle", [216, 191, 216]),
+    ("tomato", [255, 99, 71]),
+    ("turquoise", [64, 224, 208]),
+    ("violet", [238, 130, 238]),
+    ("wheat", [245, 222, 179]),
+    ("whitesmoke", [245, 245, 245]),
+    ("yellowgreen", [154, 205, 50]),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn convert() {
+        let cases = [
+            ("#1e90ff", [30, 144, 255, 255]),
+            ("rgb(30, 144, 255)", [30, 144, 255, 255]),
+            ("hsl(210, 100%, 56%)", [30, 144, 255, 255]),
+            ("DodgerBlue", [30, 144, 255, 255]),
+            ("rgba(255 0 0 / 50%)", [255, 0, 0, 128]),
+            ("hsla(120deg, 100%, 25%, 1)", [0, 128, 0, 255]),
+        ];
+        for (text, rgba) in cases {
+            let (c, _) = parse(text).unwrap();
+            // hsl 取整后允许有 1 的误差
+            assert!(
+                c.iter().zip(rgba).all(|(a, b)| a.abs_diff(b) <= 1),
+                "{} {:?}",
+                text,
+                c
+            );
+        }
+        let c = [30, 144, 255, 255];
+        assert_eq!(format(c, Notation::Hex).unwrap(), "#1e90ff");
+        assert_eq!(format(c, Notation::Rgb).unwrap(), "rgb(30, 144, 255)");
+        assert_eq!(format(c, Notation::Hsl).unwrap(), "hsl(210, 100%, 56%)");
+        assert_eq!(format(c, Notation::Name).unwrap(), "dodgerblue");
+        assert_eq!(format([1, 2, 3, 255], Notation::Name), None);
+        assert_eq!(
+            format([255, 0, 0, 128], Notation::Rgb).unwrap(),
+            "rgba(255, 0, 0, 0.5)"
+        );
+        assert_eq!(
+            format([255, 0, 0, 128], Notation::Hex).unwrap(),
+            "#ff000080"
+        );
+        assert!(parse("rgb(1, 2)").is_none());
+        assert!(parse("hsl(1, 2, 3)").is_none());
+        assert!(parse("notacolor").is_none());
+    }
+}
@@ -0,0 +1,211 @@
+//! 图片对比
+//!
+//! 两张图片并排显示、叠加显示或者显示逐像素差异的热力图。
+//! 尺寸不同时按左上角对齐，只在一张图片中存在的像素视为不同
+
+use eframe::egui::{self, Color32, Pos2, Rect, Vec2};
+use image::{DynamicImage, Rgba, RgbaImage};
+
+/// 预览纹理的最大边长
+const PREVIEW_MAX: u32 = 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Mode {
+    SideBySide,
+    Overlay,
+    Diff,
+}
+
+impl Mode {
+    const ALL: [Mode; 3] = [Mode::SideBySide, Mode::Overlay, Mode::Diff];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Mode::SideBySide => "side by side",
+            Mode::Overlay => "overlay",
+            Mode::Diff => "diff",
+        }
+    }
+}
+
+/// 逐像素比较，返回热力图和不同的像素数。
+/// 相同的像素显示为变暗的原图，不同的像素从黄到红表示差异的大小
+pub(crate) fn diff(a: &RgbaImage, b: &RgbaImage) -> (RgbaImage, usize) {
+    let (w, h) = (a.width().max(b.width()), a.height().max(b.height()));
+    let mut res = RgbaImage::new(w, h);
+    let mut count = 0;
+    for (x, y, p) in res.enumerate_pixels_mut() {
+        let pa = a.get_pixel_checked(x, y);
+        let pb = b.get_pixel_checked(x, y);
+        let d = match (pa, pb) {
+            (Some(pa), Some(pb)) => {
+                pa.0.iter()
+                    .zip(pb.0.iter())
+                    .map(|(x, y)| x.abs_diff(*y))
+                    .max()
+                    .unwrap_or(0)
+            }
+            _ => 255,
+        };
+        *p = if d == 0 {
+            let [r, g, b, _] = pa.map_or([0; 4], |p| p.0);
+            let gray = ((r as u32 * 3 + g as u32 * 6 + b as u32) / 10 / 3) as u8;
+            Rgba([gray, gray, gray, 255])
+        } else {
+            count += 1;
+            Rgba([255, 255 - d, 0, 255])
+        };
+    }
+    (res, count)
+}
+
+fn texture(ctx: &egui::Context, name: &str, img: &DynamicImage) -> egui::TextureHandle {
+    let preview = if img.width() > PREVIEW_MAX || img.height() > PREVIEW_MAX {
+        img.thumbnail(PREVIEW_MAX, PREVIEW_MAX)
+    } else {
+        img.clone()
+    };
+    let rgba = preview.to_rgba8();
+    let size = [rgba.width() as usize, rgba.height() as usize];
+    ctx.load_texture(
+        name,
+        egui::ColorImage::from_rgba_unmultiplied(size, rgba.as_raw()),
+        egui::TextureOptions::NEAREST,
+    )
+}
+
+/// 对比窗口
+pub(crate) struct Compare {
+    a: DynamicImage,
+    b: DynamicImage,
+    /// 不同的像素数
+    count: usize,
+    heatmap: DynamicImage,
+    /// a, b, 热力图
+    textures: Option<[egui::TextureHandle; 3]>,
+    mode: Mode,
+    /// 叠加时上层图片的不透明度
+    opacity: f32,
+}
+
+impl Compare {
+    pub fn new(a: &[u8], b: &[u8]) -> Result<Self, String> {
+        let a = image::load_from_memory(a).map_err(|e| e.to_string())?;
+        let b = image::load_from_memory(b).map_err(|e| e.to_string())?;
+        let (heatmap, count) = diff(&a.to_rgba8(), &b.to_rgba8());
+        Ok(Compare {
+            a,
+            b,
+            count,
+            heatmap: DynamicImage::ImageRgba8(heatmap),
+            textures: None,
+            mode: Mode::SideBySide,
+            opacity: 0.5,
+        })
+    }
+
+    /// 返回是否继续显示
+    pub fn show(&mut self, ctx: &egui::Context) -> bool {
+        let mut open = true;
+        let textures = self.textures.get_or_insert_with(|| {
+            [
+                texture(ctx, "compare a", &self.a),
+                texture(ctx, "compare b", &self.b),
+                texture(ctx, "compare diff", &self.heatmap),
+            ]
+        });
+        egui::Window::new("compare")
+            .collapsible(false)
+            .default_size([640.0, 480.0])
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    for m in Mode::ALL {
+                        ui.selectable_value(&mut self.mode, m, m.name());
+                    }
+                    if self.mode == Mode::Overlay {
+                        ui.add(egui::Slider::new(&mut self.opacity, 0.0..=1.0).text("opacity"));
+                    }
+                });
+                let total = (self.heatmap.width() as usize * self.heatmap.height() as usize).max(1);
+                ui.label(format!(
+                    "{} × {} · {} × {} · {} pixels differ ({:.2}%)",
+                    self.a.width(),
+                    self.a.height(),
+                    self.b.width(),
+                    self.b.height(),
+                    self.count,
+                    self.count as f32 * 100.0 / total as f32
+                ));
+                let full = Vec2::new(self.heatmap.width() as f32, self.heatmap.height() as f32);
+                let size_a = Vec2::new(self.a.width() as f32, self.a.height() as f32);
+                let size_b = Vec2::new(self.b.width() as f32, self.b.height() as f32);
+                let uv = Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0));
+                egui::ScrollArea::both().auto_shrink(false).show(ui, |ui| {
+                    let avail = ui.available_width();
+                    match self.mode {
+                        Mode::SideBySide => {
+                            // 两张图片使用相同的缩放，方便比较大小
+                            let scale = ((avail - ui.spacing().item_spacing.x)
+                                / 2.0
+                                / size_a.x.max(size_b.x))
+                            .min(1.0);
+                            ui.horizontal_top(|ui| {
+                                ui.image((textures[0].id(), size_a * scale));
+                                ui.image((textures[1].id(), size_b * scale));
+                            });
+                        }
+                        Mode::Overlay => {
+                            let scale = (avail / full.x).min(1.0);
+                            let (rect, _) =
+                                ui.allocate_exact_size(full * scale, egui::Sense::hover());
+                            let painter = ui.painter_at(rect);
+                            painter.image(
+                                textures[0].id(),
+                                Rect::from_min_size(rect.min, size_a * scale),
+                                uv,
+                                Color32::WHITE,
+                            );
+                            painter.image(
+                                textures[1].id(),
+                                Rect::from_min_size(rect.min, size_b * scale),
+                                uv,
+                                Color32::WHITE.gamma_multiply(self.opacity),
+                            );
+                        }
+                        Mode::Diff => {
+                            let scale = (avail / full.x).min(1.0);
+                            ui.image((textures[2].id(), full * scale));
+                        }
+                    }
+                });
+            });
+        open
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixel_diff() {
+        let a = RgbaImage::from_pixel(4, 3, Rgba([10, 20, 30, 255]));
+        let (_, count) = diff(&a, &a);
+        assert_eq!(count, 0);
+
+        let mut b = a.clone();
+        b.put_pixel(1, 1, Rgba([10, 20, 31, 255]));
+        b.put_pixel(2, 2, Rgba([250, 20, 30, 255]));
+        let (heat, count) = diff(&a, &b);
+        assert_eq!(count, 2);
+        // 差异越大越红
+        assert!(heat.get_pixel(2, 2).0[1] < heat.get_pixel(1, 1).0[1]);
+
+        // 尺寸不同时多出的部分都算不同
+        let c = RgbaImage::from_pixel(5, 3, Rgba([10, 20, 30, 255]));
+        let (heat, count) = diff(&a, &c);
+        assert_eq!((heat.width(), heat.height()), (5, 3));
+        assert_eq!(count, 3);
+    }
+}
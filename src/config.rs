@@ -0,0 +1,267 @@
+//! 配置
+//!
+//! 保存在配置目录下的 config.json，缺少的字段使用默认值
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::store::Limits;
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct Config {
+    /// 在 hotkey 弹出的窗口中选择后自动粘贴到之前的窗口
+    pub paste_on_select: bool,
+    /// 粘贴按键
+    pub paste_keys: String,
+    /// 按窗口类名覆盖粘贴按键，类名包含 key 即生效，不区分大小写
+    pub paste_overrides: Vec<(String, String)>,
+    /// 复制为纯文本时的处理
+    pub plain_text: PlainText,
+    /// 合并多条记录时的分隔符
+    pub join_separator: Separator,
+    /// 打开路径使用的编辑器命令，可以带参数，为空时使用系统默认程序
+    pub editor: String,
+    /// 保存图片的目录，为空时使用图片目录下的 clip
+    pub screenshots: String,
+    pub ocr: Ocr,
+    /// 保存历史记录，重启后恢复，默认关闭，修改后重启生效
+    pub history: bool,
+    /// 历史记录的上限，修改后重启生效
+    pub limits: Limits,
+    pub sync: Sync,
+    /// 读取配置文件时的错误，有错误时不写入配置文件，避免覆盖用户的设置
+    #[serde(skip)]
+    pub error: Option<String>,
+}
+
+/// 图片文字识别
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct Ocr {
+    /// tesseract 命令
+    pub command: String,
+    /// 识别的语言，多个语言用 + 连接，例如 `chi_sim+eng`
+    pub lang: String,
+}
+
+impl Default for Ocr {
+    fn default() -> Self {
+        Ocr {
+            command: "tesseract".to_string(),
+            lang: "eng".to_string(),
+        }
+    }
+}
+
+/// 同步
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct Sync {
+    /// 本机的唯一标识，第一次运行时生成
+    pub device: String,
+    /// 局域网同步，修改后重启生效
+    pub lan: bool,
+    /// 共享密钥，密钥相同的设备才会互相同步
+    pub secret: String,
+    /// tcp 和 udp 广播使用的端口
+    pub port: u16,
+    /// 共享目录，为空时不同步，修改后重启生效
+    pub folder: String,
+}
+
+impl Default for Sync {
+    fn default() -> Self {
+        Sync {
+            device: String::new(),
+            lan: false,
+            secret: String::new(),
+            port: 47815,
+            folder: String::new(),
+        }
+    }
+}
+
+/// 分隔符
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Separator {
+    #[default]
+    Newline,
+    Comma,
+    Space,
+    /// 自定义，`\n` 和 `\t` 会被转义
+    Custom(String),
+}
+
+impl Separator {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Separator::Newline => "newline",
+            Separator::Comma => "comma",
+            Separator::Space => "space",
+            Separator::Custom(_) => "custom",
+        }
+    }
+
+    pub fn value(&self) -> String {
+        match self {
+            Separator::Newline => "\n".to_string(),
+            Separator::Comma => ",".to_string(),
+            Separator::Space => " ".to_string(),
+            Separator::Custom(s) => s.replace("\\n", "\n").replace("\\t", "\t"),
+        }
+    }
+}
+
+/// 换行符
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum LineEnding {
+    /// 保持原样
+    Keep,
+    Lf,
+    Crlf,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct PlainText {
+    pub line_ending: LineEnding,
+    /// 去掉每行末尾的空白
+    pub trim_lines: bool,
+    /// 连续的空格和制表符合并成一个空格
+    pub collapse_spaces: bool,
+    /// 去掉首尾的空白
+    pub trim: bool,
+}
+
+impl Default for PlainText {
+    fn default() -> Self {
+        PlainText {
+            line_ending: if cfg!(target_os = "windows") {
+                LineEnding::Crlf
+            } else {
+                LineEnding::Lf
+            },
+            trim_lines: true,
+            collapse_spaces: false,
+            trim: false,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let terminal = "ctrl+shift+v".to_string();
+        Config {
+            paste_on_select: false,
+            paste_keys: if cfg!(target_os = "macos") {
+                "cmd+v"
+            } else {
+                "ctrl+v"
+            }
+            .to_string(),
+            paste_overrides: [
+                "gnome-terminal",
+                "konsole",
+                "xfce4-terminal",
+                "terminator",
+                "tilix",
+                "alacritty",
+                "kitty",
+                "wezterm",
+                "foot",
+            ]
+            .iter()
+            .map(|c| (c.to_string(), terminal.clone()))
+            .collect(),
+            plain_text: PlainText::default(),
+            join_separator: Separator::default(),
+            editor: String::new(),
+            screenshots: String::new(),
+            ocr: Ocr::default(),
+            history: false,
+            limits: Limits::default(),
+            sync: Sync::default(),
+            error: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn screenshots_dir(&self) -> PathBuf {
+        if !self.screenshots.is_empty() {
+            return PathBuf::from(&self.screenshots);
+        }
+        dirs::picture_dir()
+            .or_else(dirs::home_dir)
+            .unwrap_or_default()
+            .join("clip")
+    }
+
+    fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|d| d.join("clip").join("config.json"))
+    }
+
+    /// 读取配置，文件不存在时使用默认值并写入生成的设备标识。
+    /// 读取或者解析失败时只在内存中使用默认值，错误保存在 error 中，不写入文件
+    pub fn load() -> Self {
+        let Some(path) = Self::path() else {
+            return Config::default();
+        };
+        let mut config = match std::fs::read_to_string(&path) {
+            Ok(s) => match serde_json::from_str::<Config>(&s) {
+                Ok(c) => c,
+                Err(e) => return Self::fallback(format!("config error {}", e)),
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Config::default(),
+            Err(e) => return Self::fallback(format!("read config fail {}", e)),
+        };
+        if config.sync.device.is_empty() {
+            config.sync.device = crate::snippet::uuid_v4();
+            if let Err(e) = config.save() {
+                s_error!("save config fail {}", e);
+            }
+        }
+        config
+    }
+
+    /// 配置文件有错误时使用的默认值，设备标识只在本次运行中使用
+    fn fallback(error: String) -> Self {
+        s_error!("{}", error);
+        let mut config = Config::default();
+        config.sync.device = crate::snippet::uuid_v4();
+        config.error = Some(error);
+        config
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        if let Some(e) = &self.error {
+            return Err(format!(
+                "{}, fix config.json and restart before changing settings",
+                e
+            ));
+        }
+        let path = Self::path().ok_or("no config dir")?;
+        let s = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        // 配置中有同步的密钥，只允许当前用户读写
+        crate::persist::write_private(&path, s.as_bytes())
+    }
+
+    /// 根据窗口类名获取粘贴按键
+    pub fn paste_keys_for(&self, class: Option<&str>) -> &str {
+        if let Some(class) = class {
+            let class = class.to_lowercase();
+            if let Some((_, keys)) = self
+                .paste_overrides
+                .iter()
+                .find(|(c, _)| class.contains(&c.to_lowercase()))
+            {
+                return keys;
+            }
+        }
+        &self.paste_keys
+    }
+}
@@ -0,0 +1,92 @@
+//! 路径记录是否存在
+//!
+//! 网络磁盘或者自动挂载的路径可能卡住很久，所以在后台线程中检查，
+//! 界面只读取缓存的结果，没有结果时按存在处理，检查完成后刷新界面
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::{
+        Arc, Mutex,
+        mpsc::{Sender, channel},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use eframe::egui;
+
+/// 结果的缓存时间
+const INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Default)]
+struct State {
+    /// id -> (存在, 检查时间)
+    results: HashMap<u64, (bool, Instant)>,
+    /// 已经交给后台线程的 id
+    pending: HashSet<u64>,
+}
+
+pub(crate) struct Checker {
+    state: Arc<Mutex<State>>,
+    tx: Sender<(u64, PathBuf)>,
+}
+
+impl Checker {
+    pub fn new(ctx: egui::Context) -> Self {
+        let state = Arc::new(Mutex::new(State::default()));
+        let (tx, rx) = channel::<(u64, PathBuf)>();
+        let s = Arc::clone(&state);
+        // 只用一个线程，某个路径卡住时不会不断创建新线程
+        thread::spawn(move || {
+            for (id, path) in rx {
+                let exists = path.exists();
+                if let Ok(mut s) = s.lock() {
+                    s.pending.remove(&id);
+                    s.results.insert(id, (exists, Instant::now()));
+                }
+                ctx.request_repaint();
+            }
+        });
+        Checker { state, tx }
+    }
+
+    /// 缓存的结果，过期或者没有时在后台重新检查
+    pub fn exists(&self, id: u64, path: impl FnOnce() -> PathBuf) -> bool {
+        let Ok(mut s) = self.state.lock() else {
+            return true;
+        };
+        let cached = s.results.get(&id).copied();
+        if cached.is_none_or(|(_, at)| at.elapsed() >= INTERVAL)
+            && s.pending.insert(id)
+            && self.tx.send((id, path())).is_err()
+        {
+            s.pending.remove(&id);
+        }
+        s.results.retain(|_, (_, at)| at.elapsed() < INTERVAL * 2);
+        cached.is_none_or(|(exists, _)| exists)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn background() {
+        let checker = Checker::new(egui::Context::default());
+        let dir = crate::testdir::TempDir::new("exists");
+        let missing = dir.join("missing");
+        // 还没有结果时按存在处理
+        assert!(checker.exists(1, || missing.clone()));
+        assert!(checker.exists(2, || dir.to_path_buf()));
+        for _ in 0..100 {
+            if checker.state.lock().unwrap().pending.is_empty() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(!checker.exists(1, || unreachable!()));
+        assert!(checker.exists(2, || unreachable!()));
+    }
+}
@@ -0,0 +1,613 @@
+//! 导入导出
+//!
+//! json lines 和历史记录文件的格式相同，包括图片和元数据；
+//! 纯文本中记录之间使用单独一行的分隔符，内容中和分隔符相同的行前面加上 `\\`，
+//! 图片会被跳过
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    path::Path,
+};
+
+use eframe::egui;
+
+use crate::{
+    Clip,
+    migrate::{self, Source},
+    persist::{self, Record, Report},
+    store::{Entry, Store},
+};
+
+/// 纯文本中记录之间的分隔行
+pub(crate) const SEPARATOR: &str = "-----8<-----";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Format {
+    Jsonl,
+    Text,
+}
+
+impl Format {
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Jsonl => "jsonl",
+            Format::Text => "text",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Format> {
+        match s {
+            "jsonl" | "json" => Some(Format::Jsonl),
+            "text" | "txt" => Some(Format::Text),
+            _ => None,
+        }
+    }
+
+    /// 根据扩展名判断，.txt 为纯文本，其他为 json lines
+    pub fn from_path(path: &Path) -> Format {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("txt") => Format::Text,
+            _ => Format::Jsonl,
+        }
+    }
+}
+
+/// 导出，返回导出的数量
+pub(crate) fn export<'a>(
+    w: &mut impl Write,
+    store: &Store,
+    entries: impl Iterator<Item = &'a Entry>,
+    format: Format,
+) -> Result<usize, String> {
+    if format == Format::Jsonl {
+        return persist::write(w, entries.filter_map(|e| Record::from_entry(e, store)));
+    }
+    let mut n = 0;
+    for e in entries {
+        if let Clip::Text(t) = &e.clip {
+            if n > 0 {
+                write!(w, "\n{}\n", SEPARATOR).map_err(|e| e.to_string())?;
+            }
+            for line in t.split_inclusive('\n') {
+                if is_separator(line.trim_start_matches('\\')) {
+                    w.write_all(b"\\").map_err(|e| e.to_string())?;
+                }
+                w.write_all(line.as_bytes()).map_err(|e| e.to_string())?;
+            }
+            n += 1;
+        }
+    }
+    Ok(n)
+}
+
+/// 去掉换行符后是否是分隔行
+fn is_separator(line: &str) -> bool {
+    let line = line.strip_suffix('\n').unwrap_or(line);
+    line.strip_suffix('\r').unwrap_or(line) == SEPARATOR
+}
+
+/// 读取导出的文件，返回记录和无法解析的数量。换行符保持原样
+pub(crate) fn read(mut r: impl BufRead, format: Format) -> Result<(Vec<Record>, usize), String> {
+    if format == Format::Jsonl {
+        return persist::read(r);
+    }
+    let mut s = String::new();
+    r.read_to_string(&mut s).map_err(|e| e.to_string())?;
+    let mut texts = Vec::new();
+    let mut text = String::new();
+    for line in s.split_inclusive('\n') {
+        if is_separator(line) {
+            // 分隔行前的换行属于分隔符，文件被转换成 crlf 时同样去掉 \r
+            let mut end = text.strip_suffix('\n').unwrap_or(&text);
+            if line.ends_with("\r\n") {
+                end = end.strip_suffix('\r').unwrap_or(end);
+            }
+            text.truncate(end.len());
+            texts.push(std::mem::take(&mut text));
+        } else if let Some(escaped) = line.strip_prefix('\\')
+            && is_separator(escaped.trim_start_matches('\\'))
+        {
+            text.push_str(escaped);
+        } else {
+            text.push_str(line);
+        }
+    }
+    texts.push(text);
+    let records = texts
+        .into_iter()
+        .filter(|t| !t.is_empty())
+        .map(|t| Record::new(Clip::Text(t), 0))
+        .collect();
+    Ok((records, 0))
+}
+
+/// 导出到文件，ids 为 None 时导出全部
+pub(crate) fn export_file(
+    path: &Path,
+    store: &Store,
+    ids: Option<&[u64]>,
+    format: Format,
+) -> Result<usize, String> {
+    let mut f = std::io::BufWriter::new(std::fs::File::create(path).map_err(|e| e.to_string())?);
+    let n = match ids {
+        Some(ids) => export(
+            &mut f,
+            store,
+            ids.iter().filter_map(|id| store.get(*id)),
+            format,
+        )?,
+        None => export(&mut f, store, store.iter(), format)?,
+    };
+    f.flush().map_err(|e| e.to_string())?;
+    Ok(n)
+}
+
+/// 从文件导入并合并
+pub(crate) fn import_file(
+    path: &Path,
+    store: &mut Store,
+    format: Format,
+) -> Result<Report, String> {
+    let f = std::fs::File::open(path).map_err(|e| e.to_string())?;
+    let (records, invalid) = read(BufReader::new(f), format)?;
+    let mut report = persist::import(store, records);
+    report.invalid += invalid;
+    Ok(report)
+}
+
+/// 命令行读写的历史记录文件，配置文件有错误或者没有开启保存历史记录时报错
+fn history_file() -> Result<std::path::PathBuf, String> {
+    let config = crate::config::Config::load();
+    if let Some(e) = config.error {
+        return Err(e);
+    }
+    if !config.history {
+        return Err("history is disabled, set `history` to true in config".to_string());
+    }
+    persist::history_path().ok_or("no data dir".to_string())
+}
+
+/// 使用数据目录中图片的 store。
+/// 不在命令行中清理图片，运行中的程序可能有还没有保存到历史记录文件的图片
+fn open_store() -> Store {
+    let mut store = Store::new(crate::config::Config::load().limits);
+    if let Some(dir) = persist::blob_dir() {
+        store.set_blob_dir(dir);
+    }
+    store
+}
+
+/// 命令行，直接操作历史记录文件。
+/// 导入会修改历史记录文件，程序运行时拒绝导入，避免被运行中的程序下次保存时覆盖；
+/// 导出读取已经保存的内容
+///
+/// `clip export <file> [--format jsonl|text] [--search text]`
+///
+/// `clip import <file> [--format jsonl|text]`
+///
+/// `clip migrate <copyq|clipman|gpaste|klipper> [file]`
+pub(crate) fn cli(args: &[String]) -> Result<String, String> {
+    const USAGE: &str = "usage: clip export|import <file> [--format jsonl|text] [--search text]\n       clip migrate <copyq|clipman|gpaste|klipper> [file]";
+    if let [cmd, source, rest @ ..] = args
+        && cmd == "migrate"
+    {
+        let source = Source::parse(source).ok_or(USAGE)?;
+        let file = match rest {
+            [] => source.default_path().ok_or("no default path")?,
+            [file] => file.into(),
+            _ => return Err(USAGE.to_string()),
+        };
+        let history = history_file()?;
+        let _lock = persist::lock()?;
+        let mut store = open_store();
+        if history.exists() {
+            persist::restore(&history, &mut store)?;
+        }
+        let report = migrate::import(source, &file, &mut store)?;
+        persist::save(&history, &persist::snapshot(&store))?;
+        return Ok(format!("imported {}", report));
+    }
+    let (cmd, file) = match args {
+        [cmd, file, ..] => (cmd.as_str(), Path::new(file)),
+        _ => return Err(USAGE.to_string()),
+    };
+    let mut format = Format::from_path(file);
+    let mut search = None;
+    let mut rest = args[2..].iter();
+    while let Some(a) = rest.next() {
+        match (a.as_str(), rest.next()) {
+            ("--format", Some(f)) => format = Format::parse(f).ok_or(USAGE)?,
+            ("--search", Some(s)) => search = Some(s.to_lowercase()),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+
+    let history = history_file()?;
+    let _lock = match cmd {
+        "import" => Some(persist::lock()?),
+        _ => None,
+    };
+    let mut store = open_store();
+    if history.exists() {
+        persist::restore(&history, &mut store)?;
+    }
+    match cmd {
+        "export" => {
+            let ids = search.map(|q| {
+                store
+                    .iter()
+                    .filter(|e| matches!(&e.clip, Clip::Text(t) if t.to_lowercase().contains(&q)))
+                    .map(|e| e.id)
+                    .collect::<Vec<_>>()
+            });
+            let n = export_file(file, &store, ids.as_deref(), format)?;
+            Ok(format!("exported {}", n))
+        }
+        "import" => {
+            let report = import_file(file, &mut store, format)?;
+            persist::save(&history, &persist::snapshot(&store))?;
+            Ok(format!("imported {}", report))
+        }
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+/// 界面中导出使用的文件，文件已经存在时加上序号，不覆盖已有的文件
+fn free_path(path: &Path, format: Format) -> std::path::PathBuf {
+    if !path.exists() {
+        return path.to_path_buf();
+    }
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "clip-export".to_string());
+    let ext = match path.extension() {
+        Some(e) => e.to_string_lossy().into_owned(),
+        None => match format {
+            Format::Jsonl => "jsonl".to_string(),
+            Format::Text => "txt".to_string(),
+        },
+    };
+    crate::files::unique_path(path.parent().unwrap_or(Path::new(".")), &stem, &ext)
+}
+
+/// 导入导出窗口
+pub(crate) struct Dialog {
+    pub open: bool,
+    path: String,
+    format: Format,
+    /// 从其他程序导入
+    source: Source,
+    message: Option<String>,
+}
+
+impl Default for Dialog {
+    fn default() -> Self {
+        Dialog {
+            open: false,
+            path: dirs::home_dir()
+                .map(|d| d.join("clip-export.jsonl").display().to_string())
+                .unwrap_or_default(),
+            format: Format::Jsonl,
+            source: Source::CopyQ,
+            message: None,
+        }
+    }
+}
+
+impl Dialog {
+    /// subset 为多选或搜索的结果，返回是否导入了记录
+    pub fn show(&mut self, ctx: &egui::Context, store: &mut Store, subset: Option<&[u64]>) -> bool {
+        let mut changed = false;
+        let mut open = self.open;
+        egui::Window::new("export / import")
+            .collapsible(false)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("file");
+                    ui.text_edit_singleline(&mut self.path);
+                });
+                ui.horizontal(|ui| {
+                    for f in [Format::Jsonl, Format::Text] {
+                        ui.radio_value(&mut self.format, f, f.name());
+                    }
+                });
+                ui.horizontal(|ui| {
+                    let path = Path::new(&self.path);
+                    let label = match subset {
+                        Some(ids) => format!("export {}", ids.len()),
+                        None => "export all".to_string(),
+                    };
+                    if ui
+                        .button(label)
+                        .on_hover_text("existing files are kept, a numbered name is used instead")
+                        .clicked()
+                    {
+                        let path = free_path(path, self.format);
+                        self.message = Some(match export_file(&path, store, subset, self.format) {
+                            Ok(n) => format!("exported {} to {}", n, path.display()),
+                            Err(e) => e,
+                        });
+                    }
+                    if ui.button("import").clicked() {
+                        self.message = Some(match import_file(path, store, self.format) {
+                            Ok(r) => {
+                                changed = r.added > 0;
+                                r.to_string()
+                            }
+                            Err(e) => e,
+                        });
+                    }
+                });
+                ui.separator();
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_id_salt("migrate_source")
+                        .selected_text(self.source.name())
+                        .show_ui(ui, |ui| {
+                            for s in Source::ALL {
+                                ui.selectable_value(&mut self.source, s, s.name());
+                            }
+                        });
+                    let path = self.source.default_path();
+                    let hover = path
+                        .as_ref()
+                        .map(|p| p.display().to_string())
+                        .unwrap_or_default();
+                    if ui.button("import history").on_hover_text(hover).clicked() {
+                        self.message = Some(
+                            match path
+                                .ok_or_else(|| "no default path".to_string())
+                                .and_then(|p| migrate::import(self.source, &p, store))
+                            {
+                                Ok(r) => {
+                                    changed = r.added > 0;
+                                    r.to_string()
+                                }
+                                Err(e) => e,
+                            },
+                        );
+                    }
+                });
+                if let Some(m) = &self.message {
+                    ui.label(m);
+                }
+            });
+        self.open = open;
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::Limits;
+
+    fn store() -> Store {
+        let mut s = Store::new(Limits::default());
+        s.push(Clip::Text("hello\nworld".to_string()));
+        if let crate::store::Push::Added(id) = s.push(Clip::Img(vec![1, 2, 3, 255])) {
+            s.set_ocr(id, "hello".to_string());
+        }
+        // 更早的时间，排在最前面
+        if let crate::store::Push::Added(id) =
+            s.insert(Clip::Text("first".to_string()), 1_700_000_000, 0)
+        {
+            s.edit(id, "second".to_string()).unwrap();
+            s.set_copies(id, 2);
+        }
+        s
+    }
+
+    fn round_trip(from: &Store, format: Format) -> (Store, Report) {
+        let mut buf = Vec::new();
+        export(&mut buf, from, from.iter(), format).unwrap();
+        let (records, invalid) = read(buf.as_slice(), format).unwrap();
+        assert_eq!(invalid, 0);
+        let mut to = Store::new(Limits::default());
+        let report = persist::merge(&mut to, records);
+        (to, report)
+    }
+
+    #[test]
+    fn jsonl() {
+        let from = store();
+        let (to, report) = round_trip(&from, Format::Jsonl);
+        assert_eq!(report.added, 3);
+        assert_eq!(to.len(), from.len());
+        for (a, b) in from.iter().zip(to.iter()) {
+            assert!(a.clip == b.clip);
+            assert!(from.load(a.id).unwrap() == to.load(b.id).unwrap());
+            assert_eq!(a.revisions, b.revisions);
+            assert_eq!(a.time, b.time);
+            assert_eq!(a.copies, b.copies);
+            assert_eq!(a.ocr, b.ocr);
+        }
+    }
+
+    #[test]
+    fn text() {
+        let from = store();
+        let (to, report) = round_trip(&from, Format::Text);
+        // 图片不会导出
+        assert_eq!(report.added, 2);
+        let texts: Vec<_> = to
+            .iter()
+            .map(|e| match &e.clip {
+                Clip::Text(t) => t.as_str(),
+                _ => "",
+            })
+            .collect();
+        assert_eq!(texts, ["second", "hello\nworld"]);
+    }
+
+    #[test]
+    fn text_escape() {
+        let mut from = Store::new(Limits::default());
+        let texts = [
+            "a\r\nb\r\n",
+            "-----8<-----",
+            "x\n\\-----8<-----\r\n-----8<-----\ny",
+            "\\\\-----8<-----",
+            "end\r",
+        ];
+        for t in texts {
+            from.push(Clip::Text(t.to_string()));
+        }
+        let (to, report) = round_trip(&from, Format::Text);
+        assert_eq!(report.added, texts.len());
+        assert!(to.iter().map(|e| &e.clip).eq(from.iter().map(|e| &e.clip)));
+
+        // 编辑器转换成 crlf 后仍然可以拆分
+        let (records, _) = read("a\r\n-----8<-----\r\nb".as_bytes(), Format::Text).unwrap();
+        let texts: Vec<_> = records
+            .into_iter()
+            .map(|r| match r.content {
+                persist::Content::Text { text } => text,
+                _ => String::new(),
+            })
+            .collect();
+        assert_eq!(texts, ["a", "b"]);
+    }
+
+    #[test]
+    fn import_newest() {
+        let mut from = Store::new(Limits::default());
+        for i in 0..3 {
+            from.push(Clip::Text(i.to_string()));
+        }
+        let mut buf = Vec::new();
+        export(&mut buf, &from, from.iter(), Format::Jsonl).unwrap();
+        let (mut records, _) = read(buf.as_slice(), Format::Jsonl).unwrap();
+        for (i, r) in records.iter_mut().enumerate() {
+            r.time = 1_000 + i as u64;
+        }
+
+        let store = |max_entries| {
+            let mut s = Store::new(Limits {
+                max_entries,
+                ..Limits::default()
+            });
+            s.push(Clip::Text("current".to_string()));
+            s.push(Clip::Text("0".to_string()));
+            s
+        };
+        let texts = |s: &Store| {
+            s.iter()
+                .map(|e| match &e.clip {
+                    Clip::Text(t) => t.clone(),
+                    _ => String::new(),
+                })
+                .collect::<Vec<_>>()
+        };
+        // 只剩一个位置，导入最新的一条，按时间排在已有的记录之前，不淘汰已有的记录
+        let mut to = store(3);
+        let report = persist::import(&mut to, records.clone());
+        assert_eq!(
+            (
+                report.added,
+                report.duplicate,
+                report.skipped,
+                report.evicted
+            ),
+            (1, 1, 1, 0)
+        );
+        assert_eq!(texts(&to), ["2", "current", "0"]);
+        // 已存在的内容不计入
+        let mut to = store(4);
+        let report = persist::import(&mut to, records);
+        assert_eq!(
+            (
+                report.added,
+                report.duplicate,
+                report.skipped,
+                report.evicted
+            ),
+            (2, 1, 0, 0)
+        );
+        assert_eq!(to.len(), 4);
+    }
+
+    #[test]
+    fn merge_report() {
+        let mut to = store();
+        let mut buf = Vec::new();
+        export(&mut buf, &to, to.iter(), Format::Jsonl).unwrap();
+        let mut other = Store::new(Limits::default());
+        if let crate::store::Push::Added(id) = other.push(Clip::Text("zero".to_string())) {
+            other.edit(id, "second".to_string()).unwrap();
+        }
+        other.push(Clip::Text("new".to_string()));
+        export(&mut buf, &other, other.iter(), Format::Jsonl).unwrap();
+        buf.extend_from_slice(b"not json\n");
+
+        let (records, invalid) = read(buf.as_slice(), Format::Jsonl).unwrap();
+        let mut report = persist::merge(&mut to, records);
+        report.invalid += invalid;
+        assert_eq!(
+            report,
+            Report {
+                added: 1,
+                duplicate: 4,
+                conflict: 1,
+                rejected: 0,
+                stale: 0,
+                skipped: 0,
+                invalid: 1,
+                evicted: 0,
+            }
+        );
+        assert_eq!(to.len(), 4);
+    }
+
+    #[test]
+    fn stats_file() {
+        let dir = crate::testdir::TempDir::new("stats");
+        // 数据目录还不存在
+        let path = dir.join("data").join("stats.json");
+        let mut stats = crate::store::Stats::default();
+        stats.added.insert(1, 2);
+        persist::save_stats(&path, &stats).unwrap();
+        assert_eq!(persist::load_stats(&path).added, stats.added);
+        assert!(!path.with_extension("tmp").exists());
+    }
+
+    #[test]
+    fn private_file() {
+        let dir = crate::testdir::TempDir::new("private");
+        // 目录还不存在，旧版本写入的文件所有人可读
+        let path = dir.join("clip").join("config.json");
+        persist::write_private(&path, b"{}").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let public = std::fs::Permissions::from_mode(0o644);
+            std::fs::set_permissions(&path, public).unwrap();
+        }
+        std::fs::write(path.with_extension("tmp"), "partial").unwrap();
+        persist::write_private(&path, b"new").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"new");
+        assert!(!path.with_extension("tmp").exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+
+    #[test]
+    fn export_keeps_existing() {
+        let dir = crate::testdir::TempDir::new("export");
+        let path = dir.join("clip-export.jsonl");
+        assert_eq!(free_path(&path, Format::Jsonl), path);
+        std::fs::write(&path, "old").unwrap();
+        assert_eq!(
+            free_path(&path, Format::Jsonl),
+            dir.join("clip-export-1.jsonl")
+        );
+        let bare = dir.join("notes");
+        std::fs::write(&bare, "old").unwrap();
+        assert_eq!(free_path(&bare, Format::Text), dir.join("notes.txt"));
+    }
+}
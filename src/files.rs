@@ -0,0 +1,240 @@
+//! 记录和文件之间的转换
+//!
+//! 图片另存为文件，记录作为文件写入剪切板，拖入窗口的文件作为新记录。
+//! 不支持从窗口向外拖动记录，这部分已经从需求中移出：winit 和 egui 只支持拖入，
+//! 向外拖动需要分别接入 X11、Wayland、macOS 和 Windows 的原生拖放接口，Wayland 上还需要
+//! winit 内部的输入序号，无法在外部实现。需要放到其他程序中时使用 `copy as file` 后粘贴
+
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use eframe::egui;
+
+use crate::{Clip, imaging::Format, time::file_stamp};
+
+/// 拖入的非图片文件超过这个大小时只记录路径
+const TEXT_LIMIT: u64 = 1024 * 1024;
+/// 复制为文件的临时文件保留的时间，粘贴的程序可能过一段时间才读取
+const TEMP_KEEP: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// 目录下不存在的文件名，重名时加上序号
+pub(crate) fn unique_path(dir: &Path, stem: &str, ext: &str) -> PathBuf {
+    let mut path = dir.join(format!("{}.{}", stem, ext));
+    let mut n = 1;
+    while path.exists() {
+        path = dir.join(format!("{}-{}.{}", stem, n, ext));
+        n += 1;
+    }
+    path
+}
+
+/// 按格式编码，格式相同时直接使用原数据
+fn convert(data: &[u8], format: Format, quality: u8) -> Result<Vec<u8>, String> {
+    if Format::detect(data) == format {
+        return Ok(data.to_vec());
+    }
+    let img = image::load_from_memory(data).map_err(|e| e.to_string())?;
+    crate::imaging::encode(&img, format, quality)
+}
+
+/// 写入当前用户私有的临时目录，用于复制为文件，同时清理之前的临时文件
+pub(crate) fn temp_file(clip: &Clip, time: u64) -> Result<PathBuf, String> {
+    let dir = crate::persist::private_dir("files")?;
+    clean_dir(&dir, TEMP_KEEP);
+    let stem = format!("clip-{}", file_stamp(time));
+    let (path, data) = match clip {
+        Clip::Img(d) => (
+            unique_path(&dir, &stem, Format::detect(d).ext()),
+            d.as_slice(),
+        ),
+        Clip::Text(t) => (unique_path(&dir, &stem, "txt"), t.as_bytes()),
+        Clip::Quit => return Err("nothing to write".to_string()),
+    };
+    crate::persist::write_new(&path, data)?;
+    Ok(path)
+}
+
+/// 删除目录下修改时间超过 keep 的文件，返回删除的数量
+fn clean_dir(dir: &Path, keep: Duration) -> usize {
+    let Ok(files) = std::fs::read_dir(dir) else {
+        return 0;
+    };
+    let mut n = 0;
+    for f in files.flatten() {
+        let old = f
+            .metadata()
+            .and_then(|m| m.modified())
+            .is_ok_and(|t| t.elapsed().is_ok_and(|e| e >= keep));
+        if old && std::fs::remove_file(f.path()).is_ok() {
+            n += 1;
+        }
+    }
+    n
+}
+
+/// 拖入的文件，图片作为图片记录，小的文本文件作为文本记录，其他文件记录路径
+pub(crate) fn dropped(file: &egui::DroppedFile) -> Result<Clip, String> {
+    let data = match (&file.path, &file.bytes) {
+        (Some(path), _) => {
+            let len = std::fs::metadata(path).map_err(|e| e.to_string())?.len();
+            if path.is_dir() || len > TEXT_LIMIT && !is_image_path(path) {
+                return Ok(Clip::Text(path.display().to_string()));
+            }
+            std::fs::read(path).map_err(|e| e.to_string())?
+        }
+        (None, Some(bytes)) => bytes.to_vec(),
+        (None, None) => return Err(format!("empty drop {}", file.name)),
+    };
+    if image::guess_format(&data).is_ok() && image::load_from_memory(&data).is_ok() {
+        return Ok(Clip::Img(data));
+    }
+    match (String::from_utf8(data), &file.path) {
+        (Ok(text), _) => Ok(Clip::Text(text)),
+        (Err(_), Some(path)) => Ok(Clip::Text(path.display().to_string())),
+        (Err(_), None) => Err(format!("unsupported file {}", file.name)),
+    }
+}
+
+fn is_image_path(path: &Path) -> bool {
+    image::ImageFormat::from_path(path).is_ok()
+}
+
+/// 图片另存为
+pub(crate) struct SaveDialog {
+    data: Vec<u8>,
+    dir: String,
+    /// 不带扩展名
+    stem: String,
+    format: Format,
+    quality: u8,
+    message: Option<String>,
+}
+
+impl SaveDialog {
+    pub fn new(data: Vec<u8>, time: u64, dir: &Path) -> Self {
+        SaveDialog {
+            format: Format::detect(&data),
+            data,
+            dir: dir.display().to_string(),
+            stem: format!("clip-{}", file_stamp(time)),
+            quality: 85,
+            message: None,
+        }
+    }
+
+    /// 返回 (是否继续显示, 保存成功时使用的目录)
+    pub fn show(&mut self, ctx: &egui::Context) -> (bool, Option<String>) {
+        let mut open = true;
+        let mut save = false;
+        egui::Window::new("save image")
+            .collapsible(false)
+            .resizable(false)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                egui::Grid::new("save image").num_columns(2).show(ui, |ui| {
+                    ui.label("folder");
+                    ui.text_edit_singleline(&mut self.dir);
+                    ui.end_row();
+                    ui.label("name");
+                    ui.horizontal(|ui| {
+                        ui.text_edit_singleline(&mut self.stem);
+                        ui.label(format!(".{}", self.format.ext()));
+                    });
+                    ui.end_row();
+                });
+                ui.horizontal(|ui| {
+                    for f in Format::ALL {
+                        ui.radio_value(&mut self.format, f, f.name());
+                    }
+                    if self.format == Format::Jpeg {
+                        ui.add(egui::Slider::new(&mut self.quality, 1..=100).text("quality"));
+                    }
+                });
+                ui.horizontal(|ui| {
+                    save = ui.button("save").clicked();
+                    if let Some(m) = &self.message {
+                        ui.label(m);
+                    }
+                });
+            });
+        if !save {
+            return (open, None);
+        }
+        match self.save() {
+            Ok(path) => {
+                s_info!("save image {}", path.display());
+                // 保存后关闭
+                (false, Some(self.dir.clone()))
+            }
+            Err(e) => {
+                self.message = Some(e);
+                (open, None)
+            }
+        }
+    }
+
+    fn save(&self) -> Result<PathBuf, String> {
+        let stem = self.stem.trim();
+        if stem.is_empty() || stem.contains(['/', '\\']) {
+            return Err("invalid file name".to_string());
+        }
+        let dir = Path::new(&self.dir);
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        let data = convert(&self.data, self.format, self.quality)?;
+        let path = unique_path(dir, stem, self.format.ext());
+        std::fs::write(&path, data).map_err(|e| e.to_string())?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_and_drop() {
+        let tmp = crate::testdir::TempDir::new("files");
+        // 保存时会创建目录
+        let dir = tmp.join("out");
+        let img = image::DynamicImage::ImageRgb8(image::RgbImage::new(8, 8));
+        let png = crate::imaging::encode(&img, Format::Png, 0).unwrap();
+
+        let mut dialog = SaveDialog::new(png.clone(), 1_700_000_000, &dir);
+        assert_eq!(dialog.stem, format!("clip-{}", file_stamp(1_700_000_000)));
+        let first = dialog.save().unwrap();
+        assert_eq!(std::fs::read(&first).unwrap(), png);
+        // 重名时加序号，转换格式
+        dialog.format = Format::Jpeg;
+        dialog.save().unwrap();
+        let second = dialog.save().unwrap();
+        assert_eq!(
+            second.file_name().unwrap().to_string_lossy(),
+            format!("{}-1.jpg", dialog.stem)
+        );
+        assert_eq!(
+            Format::detect(&std::fs::read(&second).unwrap()),
+            Format::Jpeg
+        );
+
+        let drop = |path: &Path| {
+            dropped(&egui::DroppedFile {
+                path: Some(path.to_path_buf()),
+                ..Default::default()
+            })
+            .unwrap()
+        };
+        assert!(matches!(drop(&first), Clip::Img(d) if d == png));
+        let text = dir.join("a.txt");
+        std::fs::write(&text, "hello").unwrap();
+        assert!(matches!(drop(&text), Clip::Text(t) if t == "hello"));
+        assert!(matches!(drop(&dir), Clip::Text(t) if t == dir.display().to_string()));
+
+        // 清理旧的临时文件，不处理目录
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        assert_eq!(clean_dir(&dir, TEMP_KEEP), 0);
+        assert_eq!(clean_dir(&dir, Duration::ZERO), 4);
+        assert!(dir.join("sub").exists());
+    }
+}
@@ -0,0 +1,474 @@
+//! 通过共享目录同步
+//!
+//! 每个设备只追加写自己的 `<设备>.<序号>.seg`，每行是一条记录的 json，
+//! 使用和局域网同步相同的密钥加密后 base64 编码，没有密钥时不同步。
+//! 定时读取其他设备文件中新增的完整行，没有换行结尾的行可能还在写入，留到下次读取。
+//! 文件超过大小上限时只保留最近的行写入下一个序号的文件，再删除旧文件，
+//! 其他设备按新文件从头读取。
+//! 写入在单独的线程中进行，共享目录可能是很慢的网络磁盘。
+//! 合并按去重规则进行，重复读取同一条记录不会产生新记录
+
+use std::{
+    collections::HashMap,
+    fs::OpenOptions,
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        mpsc::{Sender, channel},
+    },
+    thread,
+    time::Duration,
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    blob::Reader,
+    persist::Record,
+    sync::{Cipher, OnReceive},
+};
+
+/// 检查间隔
+const POLL_INTERVAL: Duration = Duration::from_secs(3);
+/// 本机文件的大小上限，超过时压缩
+const MAX_SEGMENT: u64 = 64 * 1024 * 1024;
+/// 压缩后最多保留的记录数
+const KEEP: usize = 200;
+/// 写入缓冲的大小，超过时先写入文件，避免一批图片同时在内存中
+const FLUSH: usize = 4 * 1024 * 1024;
+
+/// 已经读取的位置，保存在数据目录下，目录变化时重新读取
+#[derive(Default, Serialize, Deserialize)]
+struct Offsets {
+    dir: PathBuf,
+    /// 文件名 -> 已读取的字节数
+    files: HashMap<String, u64>,
+}
+
+/// 解析文件名，返回 (设备, 序号)
+fn parse_name(name: &str) -> Option<(&str, u64)> {
+    let (device, generation) = name.strip_suffix(".seg")?.rsplit_once('.')?;
+    Some((device, generation.parse().ok()?))
+}
+
+/// 本机文件的最大序号
+fn own_generation(dir: &Path, device: &str) -> Option<u64> {
+    std::fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .filter_map(|e| {
+            let name = e.file_name();
+            let (d, generation) = parse_name(name.to_str()?)?;
+            (d == device).then_some(generation)
+        })
+        .max()
+}
+
+/// 加密一条记录，返回完整的一行
+fn encode(cipher: &Cipher, record: &Record) -> Result<Vec<u8>, String> {
+    let json = serde_json::to_vec(record).map_err(|e| e.to_string())?;
+    let mut line = STANDARD.encode(cipher.encrypt(&json)?).into_bytes();
+    line.push(b'\n');
+    Ok(line)
+}
+
+fn decode(cipher: &Cipher, line: &[u8]) -> Result<Record, String> {
+    let data = STANDARD.decode(line).map_err(|e| e.to_string())?;
+    serde_json::from_slice(&cipher.decrypt_data(&data)?).map_err(|e| e.to_string())
+}
+
+/// 写入本机的文件，只在写入线程中使用
+struct Writer {
+    dir: PathBuf,
+    device: String,
+    cipher: Arc<Cipher>,
+    /// 写入时读取图片
+    reader: Option<Reader>,
+    generation: u64,
+    /// 超过这个大小时压缩
+    max: u64,
+}
+
+impl Writer {
+    fn new(dir: PathBuf, device: String, cipher: Arc<Cipher>, reader: Option<Reader>) -> Self {
+        let generation = own_generation(&dir, &device).unwrap_or(0);
+        Writer {
+            dir,
+            device,
+            cipher,
+            reader,
+            generation,
+            max: MAX_SEGMENT,
+        }
+    }
+
+    fn path(&self, generation: u64) -> PathBuf {
+        self.dir.join(format!("{}.{}.seg", self.device, generation))
+    }
+
+    /// 追加记录，图片在这里才读取，读取失败的记录跳过
+    fn write(&mut self, records: Vec<Record>) -> Result<(), String> {
+        let mut buf = Vec::new();
+        for r in records {
+            match r.resolve(self.reader.as_ref()) {
+                Ok(r) => buf.extend(encode(&self.cipher, &r)?),
+                Err(e) => {
+                    s_error!("skip sync folder record {}", e);
+                }
+            }
+            if buf.len() >= FLUSH {
+                self.flush(&buf)?;
+                buf.clear();
+            }
+        }
+        self.flush(&buf)
+    }
+
+    /// 一次写入完整的行
+    fn flush(&mut self, buf: &[u8]) -> Result<(), String> {
+        if buf.is_empty() {
+            return Ok(());
+        }
+        std::fs::create_dir_all(&self.dir).map_err(|e| e.to_string())?;
+        let len = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.path(self.generation))
+            .and_then(|mut f| {
+                f.write_all(buf)?;
+                f.metadata()
+            })
+            .map_err(|e| e.to_string())?
+            .len();
+        if len > self.max {
+            self.compact()?;
+        }
+        Ok(())
+    }
+
+    /// 最近的行写入新的文件，删除旧文件
+    fn compact(&mut self) -> Result<(), String> {
+        let old = self.path(self.generation);
+        let data = std::fs::read(&old).map_err(|e| e.to_string())?;
+        let mut keep = Vec::new();
+        let mut size = 0;
+        for line in data.split_inclusive(|b| *b == b'\n').rev() {
+            // 只保留完整的行，最多占用上限的一半
+            if !line.ends_with(b"\n") {
+                continue;
+            }
+            if keep.len() >= KEEP || size + line.len() as u64 > self.max / 2 {
+                break;
+            }
+            size += line.len() as u64;
+            keep.push(line);
+        }
+        let next = self.path(self.generation + 1);
+        let tmp = next.with_extension("tmp");
+        let buf: Vec<u8> = keep.into_iter().rev().flatten().copied().collect();
+        std::fs::write(&tmp, buf)
+            .and_then(|_| std::fs::rename(&tmp, &next))
+            .map_err(|e| e.to_string())?;
+        self.generation += 1;
+        if let Err(e) = std::fs::remove_file(&old) {
+            s_error!("remove old segment fail {}", e);
+        }
+        s_info!("compact sync segment {}", next.display());
+        Ok(())
+    }
+}
+
+pub(crate) struct Folder {
+    dir: PathBuf,
+    device: String,
+    cipher: Arc<Cipher>,
+    offsets: Mutex<Offsets>,
+    /// 为 None 时不保存读取位置
+    offsets_path: Option<PathBuf>,
+    /// 发送给写入线程
+    writer: Sender<Vec<Record>>,
+}
+
+impl Folder {
+    /// 追加的记录可以只带图片引用，写入时用 reader 读取
+    pub fn new(
+        dir: PathBuf,
+        device: String,
+        secret: &str,
+        reader: Option<Reader>,
+        offsets_path: Option<PathBuf>,
+    ) -> Result<Self, String> {
+        if secret.is_empty() {
+            return Err("empty sync secret".to_string());
+        }
+        let cipher = Arc::new(Cipher::new(secret));
+        let offsets = offsets_path
+            .as_ref()
+            .and_then(|p| std::fs::read_to_string(p).ok())
+            .and_then(|s| serde_json::from_str::<Offsets>(&s).ok())
+            .filter(|o| o.dir == dir)
+            .unwrap_or_else(|| Offsets {
+                dir: dir.clone(),
+                files: HashMap::new(),
+            });
+        let (writer, rx) = channel::<Vec<Record>>();
+        let mut w = Writer::new(dir.clone(), device.clone(), Arc::clone(&cipher), reader);
+        // Folder 释放后线程退出
+        thread::spawn(move || {
+            for records in rx {
+                if let Err(e) = w.write(records) {
+                    s_error!("append sync folder fail {}", e);
+                }
+            }
+        });
+        Ok(Folder {
+            dir,
+            device,
+            cipher,
+            offsets: Mutex::new(offsets),
+            offsets_path,
+            writer,
+        })
+    }
+
+    /// 本机的文件是否已经存在
+    pub fn has_segment(&self) -> bool {
+        own_generation(&self.dir, &self.device).is_some()
+    }
+
+    /// 交给写入线程追加到本机的文件
+    pub fn append(&self, records: Vec<Record>) -> Result<(), String> {
+        self.writer
+            .send(records)
+            .map_err(|_| "sync folder writer stopped".to_string())
+    }
+
+    /// 读取其他设备新增的记录
+    pub fn poll(&self) -> Result<Vec<Record>, String> {
+        let mut res = Vec::new();
+        let mut offsets = self.offsets.lock().map_err(|e| e.to_string())?;
+        let mut changed = false;
+        let mut names = Vec::new();
+        for entry in std::fs::read_dir(&self.dir).map_err(|e| e.to_string())? {
+            let path = entry.map_err(|e| e.to_string())?.path();
+            let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            if parse_name(name).is_none_or(|(device, _)| device == self.device) {
+                continue;
+            }
+            names.push(name.to_string());
+            let offset = offsets.files.get(name).copied().unwrap_or(0);
+            match read_lines(&path, offset, &self.cipher) {
+                Ok((records, next)) => {
+                    if next != offset {
+                        offsets.files.insert(name.to_string(), next);
+                        changed = true;
+                    }
+                    res.extend(records);
+                }
+                Err(e) => {
+                    s_error!("read segment {} fail {}", name, e);
+                }
+            }
+        }
+        // 压缩后删除的文件不再记录位置
+        let before = offsets.files.len();
+        offsets.files.retain(|name, _| names.contains(name));
+        changed |= offsets.files.len() != before;
+        if changed
+            && let Some(p) = &self.offsets_path
+            && let Ok(s) = serde_json::to_string(&*offsets)
+            && let Err(e) = crate::persist::write_atomic(p, s.as_bytes())
+        {
+            s_error!("save sync offsets fail {}", e);
+        }
+        Ok(res)
+    }
+
+    /// 在新线程中定时读取
+    pub fn watch(self: Arc<Self>, on_receive: OnReceive) {
+        thread::spawn(move || {
+            loop {
+                match self.poll() {
+                    Ok(records) if !records.is_empty() => on_receive(records),
+                    Ok(_) => {}
+                    Err(e) => {
+                        s_error!("poll sync folder fail {}", e);
+                    }
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+        });
+    }
+}
+
+/// 从 offset 开始读取完整的行，返回记录和新的位置；文件变短说明被替换，从头读取
+fn read_lines(path: &Path, offset: u64, cipher: &Cipher) -> Result<(Vec<Record>, u64), String> {
+    let mut f = std::fs::File::open(path).map_err(|e| e.to_string())?;
+    let len = f.metadata().map_err(|e| e.to_string())?.len();
+    let offset = if len < offset { 0 } else { offset };
+    f.seek(SeekFrom::Start(offset)).map_err(|e| e.to_string())?;
+    let mut buf = Vec::new();
+    f.read_to_end(&mut buf).map_err(|e| e.to_string())?;
+    // 只处理到最后一个换行
+    let Some(end) = buf.iter().rposition(|b| *b == b'\n') else {
+        return Ok((Vec::new(), offset));
+    };
+    let mut res = Vec::new();
+    for line in buf[..end].split(|b| *b == b'\n') {
+        let line = line.trim_ascii();
+        if line.is_empty() {
+            continue;
+        }
+        // 密钥不同的设备写入的行无法解密，直接跳过
+        match decode(cipher, line) {
+            Ok(r) => res.push(r),
+            Err(e) => {
+                s_error!("skip segment line {}", e);
+            }
+        }
+    }
+    Ok((res, offset + end as u64 + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Clip,
+        persist::merge,
+        store::{Limits, Store},
+        testdir::TempDir,
+    };
+
+    fn text(t: &str) -> Record {
+        Record::new(Clip::Text(t.to_string()), 1)
+    }
+
+    fn folder(dir: &Path, device: &str, offsets: Option<PathBuf>) -> Folder {
+        Folder::new(
+            dir.to_path_buf(),
+            device.to_string(),
+            "secret",
+            None,
+            offsets,
+        )
+        .unwrap()
+    }
+
+    fn writer(dir: &Path, device: &str) -> Writer {
+        Writer::new(
+            dir.to_path_buf(),
+            device.to_string(),
+            Arc::new(Cipher::new("secret")),
+            None,
+        )
+    }
+
+    #[test]
+    fn partial_line() {
+        let dir = TempDir::new("folder-partial");
+        let mut a = writer(&dir, "a");
+        let b = folder(&dir, "b", None);
+        a.write(vec![text("one")]).unwrap();
+        // 写了一半的行
+        let line = encode(&a.cipher, &text("two")).unwrap();
+        let (head, tail) = line.split_at(10);
+        let mut f = OpenOptions::new()
+            .append(true)
+            .open(dir.join("a.0.seg"))
+            .unwrap();
+        f.write_all(head).unwrap();
+        assert_eq!(b.poll().unwrap().len(), 1);
+        assert_eq!(b.poll().unwrap().len(), 0);
+        f.write_all(tail).unwrap();
+        assert_eq!(b.poll().unwrap().len(), 1);
+        // 自己的文件不会读取
+        assert_eq!(folder(&dir, "a", None).poll().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn idempotent() {
+        let dir = TempDir::new("folder-idempotent");
+        let offsets = dir.join("offsets.json");
+        let mut a = writer(&dir, "a");
+        a.write(vec![text("one"), text("two")]).unwrap();
+
+        let mut store = Store::new(Limits::default());
+        let b = folder(&dir, "b", Some(offsets.clone()));
+        merge(&mut store, b.poll().unwrap());
+        assert!(offsets.exists() && !dir.join("offsets.tmp").exists());
+        // 没有保存位置时重新读取全部，合并结果不变
+        let again = folder(&dir, "c", None);
+        let report = merge(&mut store, again.poll().unwrap());
+        assert_eq!((report.added, report.duplicate), (0, 2));
+        assert_eq!(store.len(), 2);
+        // 保存了位置时不会重复读取
+        let b = folder(&dir, "b", Some(offsets));
+        assert_eq!(b.poll().unwrap().len(), 0);
+
+        // 文件被替换成更短的内容时从头读取
+        std::fs::write(dir.join("a.0.seg"), "").unwrap();
+        a.write(vec![text("three")]).unwrap();
+        let report = merge(&mut store, b.poll().unwrap());
+        assert_eq!(report.added, 1);
+    }
+
+    #[test]
+    fn encrypted() {
+        let dir = TempDir::new("folder-encrypted");
+        let a = folder(&dir, "a", None);
+        assert!(!a.has_segment());
+        a.append(vec![text("private")]).unwrap();
+        // 等待写入线程
+        let path = dir.join("a.0.seg");
+        for _ in 0..100 {
+            if std::fs::metadata(&path).is_ok_and(|m| m.len() > 0) {
+                break;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        assert!(a.has_segment());
+        let data = std::fs::read_to_string(&path).unwrap();
+        assert!(!data.contains("private"));
+
+        assert_eq!(folder(&dir, "b", None).poll().unwrap().len(), 1);
+        let other = Folder::new(dir.to_path_buf(), "c".to_string(), "other", None, None).unwrap();
+        assert!(other.poll().unwrap().is_empty());
+        assert!(Folder::new(dir.to_path_buf(), "d".to_string(), "", None, None).is_err());
+    }
+
+    #[test]
+    fn compact() {
+        let dir = TempDir::new("folder-compact");
+        let b = folder(&dir, "b", None);
+        let mut a = writer(&dir, "a");
+        a.max = 2000;
+        a.write(vec![text("first")]).unwrap();
+        assert_eq!(b.poll().unwrap().len(), 1);
+        for i in 0..20 {
+            a.write(vec![text(&i.to_string())]).unwrap();
+        }
+        assert!(a.generation > 0);
+        assert!(!dir.join("a.0.seg").exists());
+        assert!(std::fs::metadata(a.path(a.generation)).unwrap().len() <= 2000);
+        // 新文件从头读取，最旧的记录已经丢弃
+        let got: Vec<String> = b
+            .poll()
+            .unwrap()
+            .into_iter()
+            .filter_map(|r| match r.into_clip() {
+                Ok((Clip::Text(t), _)) => Some(t),
+                _ => None,
+            })
+            .collect();
+        assert!(!got.is_empty() && !got.contains(&"first".to_string()));
+        assert_eq!(got.last().map(String::as_str), Some("19"));
+        // 重新打开时继续写最新的文件
+        assert_eq!(writer(&dir, "a").generation, a.generation);
+        assert_eq!(b.offsets.lock().unwrap().files.len(), 1);
+    }
+}
@@ -0,0 +1,351 @@
+//! 图片编辑
+//!
+//! 裁剪、缩放、旋转和格式转换，每次操作的结果作为新记录并写入剪切板。
+//! 重新编码时不会写入 exif 等元数据，去除元数据就是按原格式重新编码
+
+use eframe::egui::{self, Color32, Pos2, Rect, Sense, Stroke, Vec2};
+use image::{DynamicImage, ImageFormat, codecs, imageops::FilterType};
+
+/// 缩放的预设宽度
+pub(crate) const WIDTHS: [u32; 6] = [320, 640, 800, 1024, 1280, 1920];
+/// 预览纹理的最大边长
+const PREVIEW_MAX: u32 = 1024;
+/// 画布的最大尺寸
+const CANVAS: Vec2 = Vec2::new(480.0, 360.0);
+
+/// 输出格式
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Format {
+    Png,
+    Jpeg,
+    /// 只支持无损编码
+    WebP,
+}
+
+impl Format {
+    pub const ALL: [Format; 3] = [Format::Png, Format::Jpeg, Format::WebP];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Format::Png => "png",
+            Format::Jpeg => "jpeg",
+            Format::WebP => "webp",
+        }
+    }
+
+    /// 文件扩展名
+    pub fn ext(&self) -> &'static str {
+        match self {
+            Format::Png => "png",
+            Format::Jpeg => "jpg",
+            Format::WebP => "webp",
+        }
+    }
+
+    /// 图片数据的格式，不支持的格式按 png 处理
+    pub fn detect(data: &[u8]) -> Format {
+        match image::guess_format(data) {
+            Ok(ImageFormat::Jpeg) => Format::Jpeg,
+            Ok(ImageFormat::WebP) => Format::WebP,
+            _ => Format::Png,
+        }
+    }
+}
+
+/// 编辑操作
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Op {
+    /// x, y, 宽, 高
+    Crop(u32, u32, u32, u32),
+    /// 按宽度等比缩放
+    Resize(u32),
+    /// 顺时针旋转的角度，90 的倍数
+    Rotate(u32),
+    /// 不修改内容，只重新编码
+    Encode,
+}
+
+pub(crate) fn apply(img: &DynamicImage, op: Op) -> DynamicImage {
+    match op {
+        Op::Crop(x, y, w, h) => img.crop_imm(x, y, w.max(1), h.max(1)),
+        Op::Resize(width) => {
+            let height = (img.height() as u64 * width as u64 / img.width().max(1) as u64).max(1);
+            img.resize_exact(width, height as u32, FilterType::Lanczos3)
+        }
+        Op::Rotate(deg) => match deg % 360 {
+            90 => img.rotate90(),
+            180 => img.rotate180(),
+            270 => img.rotate270(),
+            _ => img.clone(),
+        },
+        Op::Encode => img.clone(),
+    }
+}
+
+/// 编码，quality 只对 jpeg 有效
+pub(crate) fn encode(img: &DynamicImage, format: Format, quality: u8) -> Result<Vec<u8>, String> {
+    let mut res = Vec::new();
+    match format {
+        Format::Png => img.write_with_encoder(codecs::png::PngEncoder::new(&mut res)),
+        Format::Jpeg => DynamicImage::ImageRgb8(img.to_rgb8()).write_with_encoder(
+            codecs::jpeg::JpegEncoder::new_with_quality(&mut res, quality.clamp(1, 100)),
+        ),
+        Format::WebP => DynamicImage::ImageRgba8(img.to_rgba8())
+            .write_with_encoder(codecs::webp::WebPEncoder::new_lossless(&mut res)),
+    }
+    .map_err(|e| e.to_string())?;
+    Ok(res)
+}
+
+/// 图片编辑窗口，连续的操作基于上一次的结果
+pub(crate) struct Toolbox {
+    image: DynamicImage,
+    texture: Option<egui::TextureHandle>,
+    format: Format,
+    quality: u8,
+    width: u32,
+    /// 拖动开始的位置，图片坐标
+    drag: Option<Pos2>,
+    /// 裁剪区域，图片坐标
+    selection: Option<Rect>,
+    message: Option<String>,
+}
+
+impl Toolbox {
+    pub fn new(data: &[u8]) -> Result<Self, String> {
+        let image = image::load_from_memory(data).map_err(|e| e.to_string())?;
+        Ok(Toolbox {
+            width: WIDTHS
+                .iter()
+                .copied()
+                .rfind(|w| *w < image.width())
+                .unwrap_or(WIDTHS[0]),
+            image,
+            texture: None,
+            format: Format::detect(data),
+            quality: 85,
+            drag: None,
+            selection: None,
+            message: None,
+        })
+    }
+
+    /// 返回 (是否继续显示, 新图片的数据)
+    pub fn show(&mut self, ctx: &egui::Context) -> (bool, Option<Vec<u8>>) {
+        let mut open = true;
+        let mut op = None;
+        egui::Window::new("image")
+            .collapsible(false)
+            .resizable(false)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.label(format!("{} × {}", self.image.width(), self.image.height()));
+                self.canvas(ui);
+                ui.horizontal(|ui| {
+                    let crop = self.selection.map(|r| {
+                        Op::Crop(
+                            r.min.x as u32,
+                            r.min.y as u32,
+                            r.width() as u32,
+                            r.height() as u32,
+                        )
+                    });
+                    if ui
+                        .add_enabled(crop.is_some(), egui::Button::new("crop"))
+                        .on_hover_text("drag on the image to select")
+                        .clicked()
+                    {
+                        op = crop;
+                    }
+                    if ui.button("⟲ 90°").clicked() {
+                        op = Some(Op::Rotate(270));
+                    }
+                    if ui.button("⟳ 90°").clicked() {
+                        op = Some(Op::Rotate(90));
+                    }
+                    if ui.button("180°").clicked() {
+                        op = Some(Op::Rotate(180));
+                    }
+                });
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_id_salt("image width")
+                        .selected_text(format!("{} px", self.width))
+                        .show_ui(ui, |ui| {
+                            for w in WIDTHS {
+                                ui.selectable_value(&mut self.width, w, format!("{} px", w));
+                            }
+                        });
+                    if ui.button("resize").clicked() {
+                        op = Some(Op::Resize(self.width));
+                    }
+                });
+                ui.horizontal(|ui| {
+                    for f in Format::ALL {
+                        ui.radio_value(&mut self.format, f, f.name());
+                    }
+                    if self.format == Format::Jpeg {
+                        ui.add(egui::Slider::new(&mut self.quality, 1..=100).text("quality"));
+                    }
+                });
+                ui.horizontal(|ui| {
+                    if ui
+                        .button("convert / strip metadata")
+                        .on_hover_text("re-encode in the selected format, metadata is dropped")
+                        .clicked()
+                    {
+                        op = Some(Op::Encode);
+                    }
+                });
+                if let Some(m) = &self.message {
+                    ui.colored_label(ui.visuals().error_fg_color, m);
+                }
+            });
+        let Some(op) = op else {
+            return (open, None);
+        };
+        let image = apply(&self.image, op);
+        match encode(&image, self.format, self.quality) {
+            Ok(data) => {
+                self.image = image;
+                self.texture = None;
+                self.selection = None;
+                self.message = None;
+                (open, Some(data))
+            }
+            Err(e) => {
+                self.message = Some(e);
+                (open, None)
+            }
+        }
+    }
+
+    /// 显示图片，拖动选择裁剪区域，点击取消选择
+    fn canvas(&mut self, ui: &mut egui::Ui) {
+        let image = &self.image;
+        let texture = self.texture.get_or_insert_with(|| {
+            let preview = if image.width() > PREVIEW_MAX || image.height() > PREVIEW_MAX {
+                image.thumbnail(PREVIEW_MAX, PREVIEW_MAX)
+            } else {
+                image.clone()
+            };
+            let rgba = preview.to_rgba8();
+            let size = [rgba.width() as usize, rgba.height() as usize];
+            ui.ctx().load_texture(
+                "image toolbox",
+                egui::ColorImage::from_rgba_unmultiplied(size, rgba.as_raw()),
+                egui::TextureOptions::LINEAR,
+            )
+        });
+        let full = Vec2::new(image.width() as f32, image.height() as f32);
+        let scale = (CANVAS.x / full.x).min(CANVAS.y / full.y).min(1.0);
+        let (rect, resp) = ui.allocate_exact_size(full * scale, Sense::click_and_drag());
+        let painter = ui.painter_at(rect);
+        painter.image(
+            texture.id(),
+            rect,
+            Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0)),
+            Color32::WHITE,
+        );
+        // 屏幕坐标转为图片坐标
+        let to_image = |p: Pos2| {
+            let v = (p - rect.min) / scale;
+            Pos2::new(v.x.clamp(0.0, full.x), v.y.clamp(0.0, full.y))
+        };
+        if resp.drag_started()
+            && let Some(p) = resp.interact_pointer_pos()
+        {
+            self.drag = Some(to_image(p));
+        }
+        if resp.dragged()
+            && let (Some(start), Some(p)) = (self.drag, resp.interact_pointer_pos())
+        {
+            let r = Rect::from_two_pos(start, to_image(p));
+            self.selection = (r.width() >= 1.0 && r.height() >= 1.0).then_some(r);
+        }
+        if resp.drag_stopped() {
+            self.drag = None;
+        }
+        if resp.clicked() {
+            self.selection = None;
+        }
+        if let Some(sel) = self.selection {
+            let screen = Rect::from_min_max(
+                rect.min + sel.min.to_vec2() * scale,
+                rect.min + sel.max.to_vec2() * scale,
+            );
+            // 选区外变暗
+            let shade = Color32::from_black_alpha(120);
+            for r in [
+                Rect::from_min_max(rect.min, Pos2::new(rect.max.x, screen.min.y)),
+                Rect::from_min_max(Pos2::new(rect.min.x, screen.max.y), rect.max),
+                Rect::from_min_max(
+                    Pos2::new(rect.min.x, screen.min.y),
+                    Pos2::new(screen.min.x, screen.max.y),
+                ),
+                Rect::from_min_max(
+                    Pos2::new(screen.max.x, screen.min.y),
+                    Pos2::new(rect.max.x, screen.max.y),
+                ),
+            ] {
+                painter.rect_filled(r, 0.0, shade);
+            }
+            painter.rect_stroke(
+                screen,
+                0.0,
+                Stroke::new(1.0, Color32::WHITE),
+                egui::StrokeKind::Inside,
+            );
+            resp.on_hover_text(format!("{} × {}", sel.width() as u32, sel.height() as u32));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ops() {
+        let img = DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+            200,
+            100,
+            image::Rgba([10, 20, 30, 255]),
+        ));
+        let crop = apply(&img, Op::Crop(10, 10, 50, 40));
+        assert_eq!((crop.width(), crop.height()), (50, 40));
+        let resized = apply(&img, Op::Resize(80));
+        assert_eq!((resized.width(), resized.height()), (80, 40));
+        let rotated = apply(&img, Op::Rotate(90));
+        assert_eq!((rotated.width(), rotated.height()), (100, 200));
+
+        for format in Format::ALL {
+            let data = encode(&img, format, 80).unwrap();
+            assert_eq!(Format::detect(&data), format);
+            let back = image::load_from_memory(&data).unwrap();
+            assert_eq!((back.width(), back.height()), (200, 100));
+        }
+    }
+
+    #[test]
+    fn strip_metadata() {
+        let img = DynamicImage::ImageRgb8(image::RgbImage::new(4, 4));
+        let png = encode(&img, Format::Png, 0).unwrap();
+        // 在 IHDR 之后插入一个 tEXt 块
+        let mut chunk = Vec::new();
+        let body = b"tEXtComment\0secret";
+        chunk.extend_from_slice(&(body.len() as u32 - 4).to_be_bytes());
+        chunk.extend_from_slice(body);
+        let mut crc = flate2::Crc::new();
+        crc.update(body);
+        chunk.extend_from_slice(&crc.sum().to_be_bytes());
+        let mut tagged = png[..33].to_vec();
+        tagged.extend(chunk);
+        tagged.extend_from_slice(&png[33..]);
+        assert!(tagged.windows(6).any(|w| w == b"secret"));
+
+        let tool = Toolbox::new(&tagged).unwrap();
+        assert_eq!(tool.format, Format::Png);
+        let out = encode(&apply(&tool.image, Op::Encode), tool.format, 0).unwrap();
+        assert!(!out.windows(6).any(|w| w == b"secret"));
+    }
+}
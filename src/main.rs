@@ -6,7 +6,7 @@ use clipboard_rs::{
     RustImageData, common::RustImage,
 };
 use device_query::device_state;
-use eframe::egui::{self, IconData, ImageSource, Pos2, ScrollArea, load::Bytes};
+use eframe::egui::{self, IconData, Pos2, ScrollArea};
 #[cfg(feature = "print")]
 use log::{error, info};
 use std::{
@@ -43,6 +43,38 @@ macro_rules! s_info {
     )
 }
 
+mod blob;
+mod classify;
+mod color;
+mod compare;
+mod config;
+mod exists;
+mod export;
+mod files;
+mod folder;
+mod imaging;
+mod migrate;
+mod ocr;
+mod open;
+mod paste;
+mod persist;
+mod preview;
+mod qr;
+mod queue;
+mod snippet;
+mod stats;
+mod store;
+mod sync;
+#[cfg(test)]
+mod testdir;
+mod texture;
+mod time;
+mod transform;
+
+use classify::Kind;
+use store::{Entry, Push, Store};
+use transform::Transform;
+
 enum Clip {
     Text(String),
     Img(Vec<u8>),
@@ -59,8 +91,13 @@ impl PartialEq<&Clip> for Clip {
                     false
                 }
             }
-            Clip::Img(_) => {
-                return false;
+            Clip::Img(d) => {
+                // 只在哈希命中时才会比较，代价可以接受
+                if let Clip::Img(o) = other {
+                    o == d
+                } else {
+                    false
+                }
             }
             Clip::Quit => {
                 if let Clip::Quit = other {
@@ -82,12 +119,13 @@ impl PartialEq<Clip> for Clip {
 struct Manager {
     ctx: ClipboardContext,
     tx: Sender<Clip>,
+    queue: Arc<Mutex<queue::Queue>>,
 }
 
 impl Manager {
-    pub fn new(tx: Sender<Clip>) -> Self {
+    pub fn new(tx: Sender<Clip>, queue: Arc<Mutex<queue::Queue>>) -> Self {
         let ctx = ClipboardContext::new().unwrap();
-        Manager { ctx, tx }
+        Manager { ctx, tx, queue }
     }
 
     fn start(self) -> clipboard_rs::WatcherShutdown {
@@ -110,6 +148,13 @@ impl ClipboardHandler for Manager {
             && !t.is_empty()
         {
             s_info!("on_clipboard_change, txt = {}", t);
+            // 队列模式下剪切板保持为队首
+            if let Ok(mut q) = self.queue.lock()
+                && let Some(head) = q.capture(&t)
+                && let Err(e) = self.ctx.set_text(head)
+            {
+                s_error!("set queue head fail {}", e);
+            }
             match self.tx.send(Clip::Text(t)) {
                 Ok(_) => {}
                 Err(e) => {
@@ -183,95 +228,8 @@ mod bundle {
 #[cfg(debug_assertions)]
 mod custom_log {
 
-    use std::{io::Write, time::Duration};
-    /// 时间戳转换，从1970年开始
-    pub(crate) fn time_display(value: u64) -> String {
-        do_time_display(value, 1970, Duration::from_secs(8 * 60 * 60))
-    }
-
-    /// 时间戳转换，支持从不同年份开始计算
-    pub(crate) fn do_time_display(value: u64, start_year: u64, timezone: Duration) -> String {
-        // 先粗略定位到哪一年
-        // 以 365 来计算，年通常只会相比正确值更晚，剩下的秒数也就更多，并且有可能出现需要往前一年的情况
-        let value = value + timezone.as_secs();
-
-        let per_year_sec = 365 * 24 * 60 * 60; // 平年的秒数
-
-        let mut year = value / per_year_sec;
-        // 剩下的秒数，如果这些秒数 不够填补闰年，比如粗略计算是 2024年，还有 86300秒，不足一天，那么中间有很多闰年，所以 年应该-1，只有-1，因为-2甚至更多 需要 last_sec > 365 * 86400，然而这是不可能的
-        let last_sec = value - (year) * per_year_sec;
-        year += start_year;
-
-        let mut leap_year_sec = 0;
-        // 计算中间有多少闰年，当前年是否是闰年不影响回退，只会影响后续具体月份计算
-        for y in start_year..year {
-            if is_leap(y) {
-                // 出现了闰年
-                leap_year_sec += 86400;
-            }
-        }
-        if last_sec < leap_year_sec {
-            // 不够填补闰年，年份应该-1
-            year -= 1;
-            // 上一年是闰年，所以需要补一天
-            if is_leap(year) {
-                leap_year_sec -= 86400;
-            }
-        }
-        // 剩下的秒数
-        let mut time = value - leap_year_sec - (year - start_year) * per_year_sec;
-
-        // 平年的月份天数累加
-        let mut day_of_year: [u64; 12] = [31, 28, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
-
-        // 找到了 计算日期
-        let sec = time % 60;
-        time /= 60;
-        let min = time % 60;
-        time /= 60;
-        let hour = time % 24;
-        time /= 24;
-
-        // 计算是哪天，因为每个月不一样多，所以需要修改
-        if is_leap(year) {
-            day_of_year[1] += 1;
-        }
-        let mut month = 0;
-        for (index, ele) in day_of_year.iter().enumerate() {
-            if &time < ele {
-                month = index + 1;
-                time += 1; // 日期必须加一，否则 每年的 第 1 秒就成了第0天了
-                break;
-            }
-            time -= ele;
-        }
-
-        format!(
-            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
-            year, month, time, hour, min, sec
-        )
-    }
-    //
-    // 判断是否是闰年
-    //
-    fn is_leap(year: u64) -> bool {
-        year % 4 == 0 && ((year % 100) != 0 || year % 400 == 0)
-    }
-    ///
-    /// 输出当前时间格式化
-    ///
-    /// 例如：
-    /// 2023-09-28T09:32:24Z
-    ///
-    pub(crate) fn time_format() -> String {
-        // 获取当前时间戳
-        let time = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .map(|v| v.as_secs())
-            .unwrap_or(0);
-
-        time_display(time)
-    }
+    use crate::time::time_format;
+    use std::io::Write;
     struct Writer {
         console: std::io::Stdout,
         fs: Option<std::fs::File>,
@@ -342,12 +300,33 @@ fn icon_data() -> Vec<u8> {
 }
 
 fn main() -> eframe::Result {
+    // 命令行导入导出
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if matches!(
+        args.first().map(String::as_str),
+        Some("export" | "import" | "migrate")
+    ) {
+        match export::cli(&args) {
+            Ok(msg) => {
+                println!("{}", msg);
+                return Ok(());
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    }
     use tray_icon::{
         TrayIconBuilder,
         menu::{Menu, Submenu},
     };
     #[cfg(debug_assertions)]
     let _ = custom_log::init();
+    // 运行期间持有，命令行导入时检查
+    let _lock = persist::lock().inspect_err(|e| {
+        s_error!("lock data dir fail {}", e);
+    });
 
     let icon = load_icon();
     #[cfg(not(target_os = "linux"))]
@@ -366,7 +345,8 @@ fn main() -> eframe::Result {
     // 消息
     let (tx, rx) = std::sync::mpsc::channel();
 
-    let manager = Manager::new(tx.clone());
+    let queue = Arc::new(Mutex::new(queue::Queue::default()));
+    let manager = Manager::new(tx.clone(), Arc::clone(&queue));
     let watcher_shutdown = manager.start();
     eframe::run_native(
         "Clip",
@@ -386,16 +366,30 @@ fn main() -> eframe::Result {
                 watcher_shutdown,
                 &cc.egui_ctx,
                 tx,
+                queue,
             )))
         }),
     )
 }
 struct Data {
-    clip: Vec<Clip>,
+    clip: Store,
     window_visble: bool,
     /// 是否是hotkey触发显示的窗口
     is_hotkey_visible: bool,
     is_top: bool,
+    /// 正在预览的记录
+    detail: Option<u64>,
+    /// 记录有变化，需要保存
+    dirty: bool,
+    /// 只有复制次数变化，退出时或者下次保存时一起写入，避免每次重复复制都重写历史记录
+    touched: bool,
+    config: config::Config,
+    /// hotkey 弹出窗口前的焦点窗口，用于自动粘贴
+    paste_target: Option<paste::Target>,
+    /// 局域网同步
+    sync: Option<sync::Node>,
+    /// 共享目录同步
+    folder: Option<Arc<folder::Folder>>,
     /// 用于操作窗口
     ctx: egui::Context,
 }
@@ -430,12 +424,184 @@ impl Data {
         }
     }
 
-    fn push(&mut self, clip:Clip){
-        self.clip.push(clip);
-        if self.clip.len() > 100 {
-            self.clip.remove(0);
+    /// 取出需要保存的内容，文件在锁外写入
+    fn snapshot(&mut self) -> Snapshot {
+        self.dirty = false;
+        self.touched = false;
+        Snapshot {
+            history: self.config.history.then(|| persist::snapshot(&self.clip)),
+            stats: self.clip.stats().clone(),
         }
     }
+
+    /// 新增记录，返回是否真的加入了，新记录会同步给其他设备
+    fn push(&mut self, clip: Clip) -> bool {
+        match self.clip.push(clip) {
+            Push::Added(id) => {
+                if let Some(r) = self
+                    .clip
+                    .get(id)
+                    .and_then(|e| persist::Record::lazy(e, &self.clip))
+                {
+                    if let Some(folder) = &self.folder
+                        && let Err(e) = folder.append(vec![r.clone()])
+                    {
+                        s_error!("append sync folder fail {}", e);
+                    }
+                    if let Some(node) = &self.sync {
+                        node.publish(vec![r]);
+                    }
+                }
+                true
+            }
+            Push::Duplicate(_) => {
+                self.touched = true;
+                false
+            }
+            Push::Rejected | Push::Stale => false,
+        }
+    }
+
+    /// 合并其他设备的记录
+    fn merge(&mut self, records: Vec<persist::Record>) {
+        let report = persist::merge(&mut self.clip, records);
+        s_info!("merge {}", report);
+        if report.added > 0 {
+            self.dirty = true;
+            self.ctx.request_repaint();
+        }
+    }
+}
+
+/// 需要保存的内容
+struct Snapshot {
+    /// 没有开启保存历史记录时为 None
+    history: Option<Vec<persist::Record>>,
+    stats: store::Stats,
+}
+
+/// 保存历史记录和统计，只在复制内容时持有锁，避免历史记录很多时卡住界面
+fn save(data: &Mutex<Data>) {
+    // 定时保存和退出时的保存不能同时写同一个文件
+    static SAVING: Mutex<()> = Mutex::new(());
+    let _saving = SAVING.lock();
+    let Some(snapshot) = data.lock().ok().map(|mut d| d.snapshot()) else {
+        return;
+    };
+    let saved = match (&snapshot.history, persist::history_path()) {
+        (Some(records), Some(path)) => persist::save(&path, records),
+        _ => Ok(()),
+    };
+    match saved {
+        Err(e) => {
+            s_error!("save history fail {}", e);
+        }
+        // 历史记录文件不再引用的图片可以删除了
+        Ok(()) => {
+            if let Ok(mut d) = data.lock() {
+                let n = d.clip.gc();
+                if n > 0 {
+                    s_info!("remove {} unused blobs", n);
+                }
+            }
+        }
+    }
+    if let Some(path) = persist::stats_path()
+        && let Err(e) = persist::save_stats(&path, &snapshot.stats)
+    {
+        s_error!("save stats fail {}", e);
+    }
+}
+
+/// 字节数转成便于阅读的格式
+fn human_size(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// 列表中的摘要，最多两行，超出部分用省略号
+fn preview_galley(ui: &egui::Ui, preview: &str) -> Arc<egui::Galley> {
+    let mut job = egui::text::LayoutJob::single_section(
+        preview.to_string(),
+        egui::TextFormat::simple(
+            egui::TextStyle::Body.resolve(ui.style()),
+            ui.visuals().text_color(),
+        ),
+    );
+    job.wrap = egui::text::TextWrapping {
+        max_width: ui.available_width(),
+        max_rows: 2,
+        break_anywhere: true,
+        overflow_character: Some('…'),
+    };
+    ui.fonts(|f| f.layout_job(job))
+}
+
+/// 摘要前的类型标记，url 显示可点击的域名，颜色显示色块，不存在的路径标红
+fn kind_badge(ui: &mut egui::Ui, ele: &Entry, exists: bool) -> Option<RowAction> {
+    let Clip::Text(t) = &ele.clip else {
+        return None;
+    };
+    let visuals = ui.visuals();
+    let (fg, bg) = match ele.kind {
+        Kind::Text | Kind::Image => return None,
+        Kind::Path if !exists => (visuals.error_fg_color, visuals.extreme_bg_color),
+        _ => (visuals.weak_text_color(), visuals.extreme_bg_color),
+    };
+    let badge = ui.label(
+        egui::RichText::new(ele.kind.name())
+            .small()
+            .color(fg)
+            .background_color(bg),
+    );
+    match ele.kind {
+        Kind::Url => {
+            if let Some(domain) = classify::domain(t.trim()) {
+                let url = classify::url_target(t);
+                if ui.link(domain).on_hover_text(&url).clicked() {
+                    return Some(RowAction::Open(open::Action::Browser));
+                }
+            }
+        }
+        Kind::Color([r, g, b, a]) => {
+            let (rect, resp) = ui.allocate_exact_size(egui::vec2(14.0, 14.0), egui::Sense::hover());
+            ui.painter().rect(
+                rect,
+                2.0,
+                egui::Color32::from_rgba_unmultiplied(r, g, b, a),
+                egui::Stroke::new(1.0, ui.visuals().weak_text_color()),
+                egui::StrokeKind::Inside,
+            );
+            resp.on_hover_text(format!("rgba({}, {}, {}, {})", r, g, b, a));
+        }
+        Kind::Path if !exists => {
+            badge.on_hover_text("path does not exist");
+        }
+        _ => {}
+    }
+    None
+}
+
+/// 右键菜单中的转换列表
+fn transform_menu(ui: &mut egui::Ui, title: &str, new_entry: bool, action: &mut Option<RowAction>) {
+    ui.menu_button(title, |ui| {
+        for t in Transform::ALL {
+            if ui.button(t.name()).clicked() {
+                *action = Some(RowAction::Transform(t, new_entry));
+                ui.close();
+            }
+        }
+    });
 }
 
 struct ClipboardApp {
@@ -443,6 +609,84 @@ struct ClipboardApp {
     ctx: ClipboardContext,
     _shutdown: clipboard_rs::WatcherShutdown,
     sender: Sender<Clip>,
+    /// 预览面板
+    pane: preview::Pane,
+    /// 正在编辑的记录和编辑中的内容
+    editing: Option<(u64, String)>,
+    /// 转换面板中选中的项，None 表示没有打开
+    palette: Option<usize>,
+    /// 上一帧列表中可见的行
+    visible_rows: std::ops::Range<usize>,
+    /// 显示在标题栏的提示，通常是错误信息
+    status: Option<String>,
+    tab: Tab,
+    /// 搜索内容，同时用于历史记录和片段
+    search: String,
+    /// 搜索结果缓存 (搜索内容, 记录版本, 从新到旧的id)
+    filtered: Option<(String, u64, Vec<u64>)>,
+    snippets: snippet::Snippets,
+    /// 多选的记录，按选择的顺序
+    selection: Vec<u64>,
+    /// 粘贴队列，和剪切板监听共享
+    queue: Arc<Mutex<queue::Queue>>,
+    /// 导入导出窗口
+    transfer: export::Dialog,
+    dashboard: stats::Dashboard,
+    /// 二维码弹窗
+    qr: Option<qr::Popup>,
+    ocr_tx: Sender<ocr::Done>,
+    ocr_rx: Receiver<ocr::Done>,
+    /// 正在识别的图片
+    ocr_pending: std::collections::HashSet<u64>,
+    /// 图片编辑窗口
+    toolbox: Option<imaging::Toolbox>,
+    save_dialog: Option<files::SaveDialog>,
+    /// 图片对比窗口
+    compare: Option<compare::Compare>,
+    /// 取色器中的颜色和输出的写法
+    picker: Option<(egui::Color32, color::Notation)>,
+    /// 图片记录的纹理
+    textures: std::cell::RefCell<texture::Cache>,
+    /// 路径记录是否存在
+    paths: exists::Checker,
+}
+
+#[derive(PartialEq)]
+enum Tab {
+    History,
+    Snippets,
+    Stats,
+}
+
+/// 列表中一行上的操作
+enum RowAction {
+    Copy,
+    Delete,
+    Select,
+    /// 转换，是否作为新记录
+    Transform(Transform, bool),
+    /// 保存为片段
+    SaveSnippet,
+    /// 颜色转换为其他写法后复制
+    CopyColor(color::Notation),
+    /// 打开取色器
+    PickColor,
+    /// 打开 url 或路径
+    Open(open::Action),
+    /// 显示为二维码
+    ShowQr,
+    /// 识别图片中的文字
+    Ocr,
+    /// 跳转到识别出的文本记录
+    ShowOcr,
+    /// 打开图片编辑
+    EditImage,
+    /// 图片另存为
+    SaveImage,
+    /// 作为文件写入剪切板
+    CopyFile,
+    /// 复制为纯文本
+    CopyPlain,
 }
 
 impl ClipboardApp {
@@ -451,26 +695,158 @@ impl ClipboardApp {
         shutdown: clipboard_rs::WatcherShutdown,
         cc: &egui::Context,
         sender: Sender<Clip>,
+        queue: Arc<Mutex<queue::Queue>>,
     ) -> Self {
+        let (ocr_tx, ocr_rx) = std::sync::mpsc::channel();
+        let config = config::Config::load();
+        let status = config.error.clone();
         let c = Arc::new(Mutex::new(Data {
             window_visble: true,
-            clip: Vec::new(),
+            clip: Store::new(config.limits),
             ctx: cc.clone(),
             is_top: false,
             is_hotkey_visible: false,
+            detail: None,
+            dirty: false,
+            touched: false,
+            config,
+            paste_target: None,
+            sync: None,
+            folder: None,
         }));
+        if let Ok(mut data) = c.lock()
+            && let Some(dir) = persist::blob_dir()
+        {
+            data.clip.set_blob_dir(dir);
+        }
+        if let Ok(mut data) = c.lock() {
+            let restored = match persist::history_path() {
+                Some(path) if data.config.history && path.exists() => {
+                    persist::restore(&path, &mut data.clip)
+                }
+                _ => Ok(()),
+            };
+            match restored {
+                // 清理上次没有保存就退出时留下的图片
+                Ok(()) => {
+                    data.clip.gc();
+                }
+                Err(e) => {
+                    s_error!("load history fail {}", e);
+                }
+            }
+        }
+        if let Ok(mut data) = c.lock()
+            && let Some(path) = persist::stats_path()
+        {
+            data.clip.set_stats(persist::load_stats(&path));
+        }
+        if let Ok(mut data) = c.lock()
+            && data.config.sync.lan
+        {
+            let cfg = data.config.sync.clone();
+            let d = Arc::clone(&c);
+            let on_receive: sync::OnReceive = Box::new(move |records| {
+                if let Ok(mut s) = d.lock() {
+                    s.merge(records);
+                }
+            });
+            match sync::Node::start(
+                cfg.device,
+                &cfg.secret,
+                (std::net::Ipv4Addr::UNSPECIFIED, cfg.port).into(),
+                data.clip.blob_reader(),
+                on_receive,
+            )
+            .and_then(|node| node.discover(cfg.port).map(|_| node))
+            {
+                Ok(node) => {
+                    // 最近的记录发送给之后上线的设备
+                    node.publish(
+                        data.clip
+                            .iter()
+                            .filter_map(|e| persist::Record::lazy(e, &data.clip))
+                            .collect(),
+                    );
+                    data.sync = Some(node);
+                }
+                Err(e) => {
+                    s_error!("start sync fail {}", e);
+                }
+            }
+        }
+        if let Ok(mut data) = c.lock()
+            && !data.config.sync.folder.is_empty()
+        {
+            match folder::Folder::new(
+                data.config.sync.folder.clone().into(),
+                data.config.sync.device.clone(),
+                &data.config.sync.secret,
+                data.clip.blob_reader(),
+                persist::data_dir().map(|d| d.join("sync_offsets.json")),
+            ) {
+                Ok(folder) => {
+                    let folder = Arc::new(folder);
+                    // 第一次使用时写入已有的记录
+                    if !folder.has_segment() {
+                        let records: Vec<_> = data
+                            .clip
+                            .iter()
+                            .filter_map(|e| persist::Record::lazy(e, &data.clip))
+                            .collect();
+                        if let Err(e) = folder.append(records) {
+                            s_error!("init sync folder fail {}", e);
+                        }
+                    }
+                    let d = Arc::clone(&c);
+                    Arc::clone(&folder).watch(Box::new(move |records| {
+                        if let Ok(mut s) = d.lock() {
+                            s.merge(records);
+                        }
+                    }));
+                    data.folder = Some(folder);
+                }
+                Err(e) => {
+                    s_error!("start sync folder fail {}", e);
+                }
+            }
+        }
         // v.start(rx);
         let res = Self {
             data: Arc::clone(&c),
             ctx: ClipboardContext::new().unwrap(),
             _shutdown: shutdown,
             sender,
+            pane: preview::Pane::default(),
+            editing: None,
+            palette: None,
+            visible_rows: 0..0,
+            status,
+            tab: Tab::History,
+            search: String::new(),
+            filtered: None,
+            snippets: snippet::Snippets::load(),
+            selection: Vec::new(),
+            queue,
+            transfer: export::Dialog::default(),
+            dashboard: stats::Dashboard::default(),
+            qr: None,
+            ocr_tx,
+            ocr_rx,
+            ocr_pending: Default::default(),
+            toolbox: None,
+            save_dialog: None,
+            compare: None,
+            picker: None,
+            textures: Default::default(),
+            paths: exists::Checker::new(cc.clone()),
         };
 
         res.add_font(cc);
         res.clip_msg_listen(rx, Arc::clone(&c));
         res.tray_listen(Arc::clone(&c));
         res.hotkey_listen(Arc::clone(&c));
+        res.save_listen(Arc::clone(&c));
         res
     }
 
@@ -487,11 +863,12 @@ impl ClipboardApp {
                         s_info!("收到消息");
                         match data.lock() {
                             Ok(mut s) => {
-                                if !s.clip.iter().any(|f| r == f) {
-                                    s.push(r);
+                                // 重复的内容只修改复制次数，不马上保存
+                                if s.push(r) {
                                     s_info!("修改");
-                                    s.ctx.request_repaint();
+                                    s.dirty = true;
                                 }
+                                s.ctx.request_repaint();
                             }
                             Err(_) => {
                                 s_error!("lock 失败");
@@ -508,19 +885,99 @@ impl ClipboardApp {
         });
     }
 
+    /// 定时保存有变化的记录
+    fn save_listen(&self, data: Arc<Mutex<Data>>) {
+        thread::spawn(move || {
+            loop {
+                thread::sleep(std::time::Duration::from_secs(2));
+                if data.lock().is_ok_and(|s| s.dirty) {
+                    save(&data);
+                }
+            }
+        });
+    }
+
     fn hotkey_listen(&self, data: Arc<Mutex<Data>>) {
         use device_query::{DeviceEvents, DeviceEventsHandler, DeviceQuery, DeviceState, Keycode};
         let event_handler = DeviceEventsHandler::new(std::time::Duration::from_millis(10))
             .expect("无法初始化事件处理器");
+        // 纯文本快捷键和队列使用，写入后需要一直持有
+        let clipboard = Arc::new(Mutex::new(ClipboardContext::new().unwrap()));
+        let queue = Arc::clone(&self.queue);
+        // 队列模式下松开粘贴键后切换到下一条，目标程序在按下时已经读取了剪切板
+        let release = {
+            let queue = Arc::clone(&queue);
+            let clipboard = Arc::clone(&clipboard);
+            let egui_ctx = data.lock().ok().map(|s| s.ctx.clone());
+            event_handler.on_key_up(move |key: &Keycode| {
+                if key != &Keycode::V {
+                    return;
+                }
+                let Some(next) = queue.lock().ok().and_then(|mut q| q.release()) else {
+                    return;
+                };
+                if let Ok(c) = clipboard.lock()
+                    && let Err(e) = c.set_text(next)
+                {
+                    s_error!("set queue next fail {}", e);
+                }
+                if let Some(ctx) = &egui_ctx {
+                    ctx.request_repaint();
+                }
+            })
+        };
         let key_up = event_handler.on_key_down(move |key: &Keycode| {
             // s_info!("按键释放: {:?}", key);
 
             if let Some(device_state) = DeviceState::checked_new() {
                 let keys = device_state.get_keys();
-                if (keys.contains(&Keycode::LControl) || keys.contains(&Keycode::RControl))
+                let ctrl = keys.contains(&Keycode::LControl) || keys.contains(&Keycode::RControl);
+                if ctrl
+                    && (keys.contains(&Keycode::LAlt) || keys.contains(&Keycode::RAlt))
+                    && key == &Keycode::V
+                {
+                    // 把当前剪切板转换为纯文本
+                    let cfg = match data.lock() {
+                        Ok(s) => s.config.plain_text.clone(),
+                        Err(_) => return,
+                    };
+                    if let Ok(c) = clipboard.lock()
+                        && let Ok(text) = c.get_text()
+                    {
+                        let plain = transform::plain_text(&text, &cfg);
+                        s_info!("plain text {}", plain);
+                        if let Err(e) = c.set_text(plain) {
+                            s_error!("set plain text fail {}", e);
+                        }
+                    }
+                    return;
+                }
+                // 队列模式下粘贴后切换到下一条
+                let paste_modifier = if cfg!(target_os = "macos") {
+                    [
+                        Keycode::Command,
+                        Keycode::RCommand,
+                        Keycode::LMeta,
+                        Keycode::RMeta,
+                    ]
+                    .iter()
+                    .any(|k| keys.contains(k))
+                } else {
+                    ctrl
+                };
+                if paste_modifier && key == &Keycode::V && queue.lock().is_ok_and(|mut q| q.press())
+                {
+                    return;
+                }
+                if ctrl
                     && (keys.contains(&Keycode::LShift) || keys.contains(&Keycode::RShift))
                     && key == &Keycode::A
                 {
+                    // 获取焦点窗口需要调用外部命令，不能持有锁
+                    let target = data
+                        .lock()
+                        .is_ok_and(|s| !s.window_visble && s.config.paste_on_select)
+                        .then(paste::active_window);
                     if let Ok(mut s) = data.lock() {
                         // 修改窗口位置
                         let mouse = device_state.get_mouse();
@@ -533,6 +990,10 @@ impl ClipboardApp {
                                 x, y,
                             )));
 
+                        if !s.window_visble {
+                            s.paste_target = target;
+                        }
+
                         s.switch_visible(true);
                     }
                 }
@@ -541,6 +1002,7 @@ impl ClipboardApp {
         // key_up 被回收事件就会被remove，所以这里直接泄漏，保证不会被drop
         // 否则就要保证生命周期，但是放到结构体里类型很难写
         Box::leak(Box::new(key_up));
+        Box::leak(Box::new(release));
     }
 
     fn tray_listen(&self, data: Arc<Mutex<Data>>) {
@@ -632,6 +1094,965 @@ impl ClipboardApp {
         }
     }
 
+    /// 历史记录页，返回是否写入了剪切板
+    fn history_ui(
+        &mut self,
+        ctx: &egui::Context,
+        ui: &mut egui::Ui,
+        data: &mut Data,
+        search_focused: bool,
+    ) -> bool {
+        self.textures.get_mut().prune(&data.clip);
+        let mut removed_id = None;
+        let mut copyed = false;
+        let mut detail = None;
+        let mut saved = None;
+        let mut restored = None;
+        let mut cancel_edit = false;
+        let mut copy_id = None;
+        let mut plain_id = None;
+        let mut transform = None;
+        let mut join = false;
+
+        // 搜索时只显示匹配的记录
+        let filter = self.filter(&data.clip);
+        let len = data.clip.len();
+        let total = filter.as_ref().map_or(len, |f| f.len());
+        // 列表中新的在上面
+        let id_at = |row: usize| match &filter {
+            Some(f) => f.get(row).copied(),
+            None => len
+                .checked_sub(row + 1)
+                .and_then(|i| data.clip.at(i))
+                .map(|e| e.id),
+        };
+        let row_of = |id: u64| match &filter {
+            Some(f) => f.iter().position(|i| *i == id),
+            None => data.clip.index_of(id).map(|i| len - 1 - i),
+        };
+
+        // 键盘操作列表
+        let mut scroll_to = None;
+        if self.palette.is_none() && (search_focused || !ctx.wants_keyboard_input()) {
+            let (up, down, enter, shift, t, toggle, join_key, esc) = ctx.input(|i| {
+                (
+                    i.key_pressed(egui::Key::ArrowUp),
+                    i.key_pressed(egui::Key::ArrowDown),
+                    i.key_pressed(egui::Key::Enter),
+                    i.modifiers.shift,
+                    i.modifiers.is_none() && i.key_pressed(egui::Key::T),
+                    i.modifiers.command && i.key_pressed(egui::Key::Space),
+                    i.modifiers.command && i.key_pressed(egui::Key::J),
+                    i.key_pressed(egui::Key::Escape),
+                )
+            });
+            if total > 0 && (up || down) {
+                let row = match data.detail.and_then(row_of) {
+                    None => 0,
+                    Some(r) if up => r.saturating_sub(1),
+                    Some(r) => (r + 1).min(total - 1),
+                };
+                // shift 加方向键扩展多选
+                if shift {
+                    for id in [data.detail, id_at(row)].into_iter().flatten() {
+                        if !self.selection.contains(&id) {
+                            self.selection.push(id);
+                        }
+                    }
+                }
+                data.detail = id_at(row);
+                self.editing = None;
+                scroll_to = Some(row);
+            }
+            if toggle && let Some(id) = data.detail {
+                match self.selection.iter().position(|i| *i == id) {
+                    Some(p) => {
+                        self.selection.remove(p);
+                    }
+                    None => self.selection.push(id),
+                }
+            }
+            if esc {
+                self.selection.clear();
+            }
+            join |= join_key;
+            if enter && shift {
+                plain_id = data.detail;
+            } else if enter {
+                copy_id = data.detail;
+            }
+            if t && !ctx.wants_keyboard_input()
+                && let Some(id) = data.detail
+                && let Some(Clip::Text(_)) = data.clip.get(id).map(|e| &e.clip)
+            {
+                self.palette = Some(0);
+            }
+        }
+
+        // 多选
+        if !self.selection.is_empty() {
+            ui.horizontal(|ui| {
+                ui.label(format!("{} selected", self.selection.len()));
+                let sep = &mut data.config.join_separator;
+                let before = sep.clone();
+                egui::ComboBox::from_id_salt("join_separator")
+                    .selected_text(sep.name())
+                    .show_ui(ui, |ui| {
+                        for s in [
+                            config::Separator::Newline,
+                            config::Separator::Comma,
+                            config::Separator::Space,
+                        ] {
+                            let name = s.name();
+                            ui.selectable_value(sep, s, name);
+                        }
+                        if ui
+                            .selectable_label(matches!(sep, config::Separator::Custom(_)), "custom")
+                            .clicked()
+                            && !matches!(sep, config::Separator::Custom(_))
+                        {
+                            *sep = config::Separator::Custom(String::new());
+                        }
+                    });
+                let mut changed = *sep != before;
+                if let config::Separator::Custom(s) = sep {
+                    changed |= ui
+                        .add(
+                            egui::TextEdit::singleline(s)
+                                .hint_text("\\n \\t")
+                                .desired_width(60.0),
+                        )
+                        .lost_focus();
+                }
+                if changed && let Err(e) = data.config.save() {
+                    self.status = Some(e);
+                }
+                if ui.button("join").on_hover_text("ctrl+j").clicked() {
+                    join = true;
+                }
+                // 选中两张图片时可以对比
+                if let [a, b] = *self.selection.as_slice()
+                    && let Some(Clip::Img(_)) = data.clip.get(a).map(|e| &e.clip)
+                    && let Some(Clip::Img(_)) = data.clip.get(b).map(|e| &e.clip)
+                    && ui.button("compare").clicked()
+                {
+                    let res = match (data.clip.load(a), data.clip.load(b)) {
+                        (Ok(Clip::Img(a)), Ok(Clip::Img(b))) => compare::Compare::new(&a, &b),
+                        (Err(e), _) | (_, Err(e)) => Err(e),
+                        _ => Err("not an image".to_string()),
+                    };
+                    match res {
+                        Ok(c) => self.compare = Some(c),
+                        Err(e) => self.status = Some(format!("compare fail {}", e)),
+                    }
+                }
+                if ui.link("clear").on_hover_text("esc").clicked() {
+                    self.selection.clear();
+                }
+            });
+        }
+
+        // 预览选中的记录
+        if let Some(id) = data.detail {
+            let mut open = true;
+            match data.clip.get(id) {
+                Some(ele) => {
+                    // 预览图片时才读取原图，同一条记录只读取一次
+                    let full = ele
+                        .image
+                        .is_some()
+                        .then(|| self.textures.get_mut().full(ctx, id, || data.clip.load(id)));
+                    egui::TopBottomPanel::bottom("preview")
+                        .resizable(true)
+                        .default_height(220.0)
+                        .show_inside(ui, |ui| {
+                            let editing = match &mut self.editing {
+                                Some((i, buf)) if *i == ele.id => Some(buf),
+                                _ => None,
+                            };
+                            if let Some(buf) = editing {
+                                ui.horizontal(|ui| {
+                                    if ui.button("save").clicked() {
+                                        saved = Some((ele.id, buf.clone(), false));
+                                    }
+                                    if ui.button("save & copy").clicked() {
+                                        saved = Some((ele.id, buf.clone(), true));
+                                    }
+                                    if ui.link("cancel").clicked() {
+                                        cancel_edit = true;
+                                    }
+                                });
+                                ScrollArea::vertical().auto_shrink(false).show(ui, |ui| {
+                                    ui.add(
+                                        egui::TextEdit::multiline(buf)
+                                            .code_editor()
+                                            .desired_width(f32::INFINITY)
+                                            .desired_rows(10),
+                                    );
+                                });
+                                return;
+                            }
+                            ui.horizontal(|ui| {
+                                if ui.button("Copy").clicked() {
+                                    copy_id = Some(ele.id);
+                                }
+                                if let Clip::Text(t) = &ele.clip
+                                    && ui.button("edit").clicked()
+                                {
+                                    self.editing = Some((ele.id, t.clone()));
+                                }
+                                if !ele.revisions.is_empty() {
+                                    ui.menu_button(
+                                        format!("revisions ({})", ele.revisions.len()),
+                                        |ui| {
+                                            for (i, r) in ele.revisions.iter().enumerate().rev() {
+                                                let line: String = r
+                                                    .lines()
+                                                    .next()
+                                                    .unwrap_or("")
+                                                    .chars()
+                                                    .take(40)
+                                                    .collect();
+                                                if ui.button(format!("restore: {}", line)).clicked()
+                                                {
+                                                    restored = Some((ele.id, i));
+                                                    ui.close();
+                                                }
+                                            }
+                                        },
+                                    );
+                                }
+                                if ui.link("close").clicked() {
+                                    open = false;
+                                }
+                            });
+                            match &ele.clip {
+                                Clip::Text(t) => {
+                                    self.pane.show_text(ui, ele.id, data.clip.version(), t)
+                                }
+                                Clip::Img(_) => match full {
+                                    Some(Ok(texture)) => {
+                                        ScrollArea::both().auto_shrink(false).show(ui, |ui| {
+                                            ui.image(texture);
+                                        });
+                                    }
+                                    Some(Err(e)) => {
+                                        ui.colored_label(ui.visuals().error_fg_color, e);
+                                    }
+                                    None => {}
+                                },
+                                _ => {}
+                            }
+                        });
+                }
+                None => open = false,
+            }
+            if !open {
+                data.detail = None;
+                cancel_edit = true;
+            }
+        } else {
+            self.textures.get_mut().forget_full();
+        }
+        // 滚动，只渲染可见的行
+        let row_height = ui.text_style_height(&egui::TextStyle::Body) * 2.0;
+        let row_full = row_height + ui.spacing().item_spacing.y;
+        let mut area = ScrollArea::vertical()
+            .auto_shrink(false)
+            .scroll_bar_visibility(egui::scroll_area::ScrollBarVisibility::VisibleWhenNeeded);
+        if let Some(row) = scroll_to
+            && !self.visible_rows.contains(&row)
+        {
+            let offset = if row < self.visible_rows.start {
+                row as f32 * row_full
+            } else {
+                (row + 1) as f32 * row_full - ui.available_height()
+            };
+            area = area.vertical_scroll_offset(offset.max(0.0));
+        }
+        let mut visible = 0..0;
+        let mut action = None;
+        area.show_rows(ui, row_height, total, |ui, range| {
+            visible = range.clone();
+            ui.with_layout(
+                egui::Layout::top_down(egui::Align::LEFT).with_cross_justify(true),
+                |ui| {
+                    // 新的在上面
+                    for row in range {
+                        let Some(ele) = id_at(row).and_then(|id| data.clip.get(id)) else {
+                            continue;
+                        };
+                        let selected = data.detail == Some(ele.id);
+                        let order = self.selection.iter().position(|i| *i == ele.id);
+                        let res = self.show_row(ui, ele, row_height, selected, order);
+                        if let Some(a) = res.inner {
+                            action = Some((ele.id, a));
+                        }
+                        if scroll_to == Some(row) {
+                            res.response.scroll_to_me(None);
+                        }
+                    }
+                },
+            );
+        });
+        self.visible_rows = visible;
+        match action {
+            Some((id, RowAction::Copy)) => copy_id = Some(id),
+            Some((id, RowAction::Delete)) => removed_id = Some(id),
+            Some((id, RowAction::Select)) => {
+                let modifiers = ctx.input(|i| i.modifiers);
+                if modifiers.command {
+                    match self.selection.iter().position(|i| *i == id) {
+                        Some(p) => {
+                            self.selection.remove(p);
+                        }
+                        None => self.selection.push(id),
+                    }
+                } else if modifiers.shift
+                    && let Some(from) = data.detail.and_then(row_of)
+                    && let Some(to) = row_of(id)
+                {
+                    // 从当前选中的行到点击的行
+                    let rows: Vec<usize> = if from <= to {
+                        (from..=to).collect()
+                    } else {
+                        (to..=from).rev().collect()
+                    };
+                    for id in rows.into_iter().filter_map(id_at) {
+                        if !self.selection.contains(&id) {
+                            self.selection.push(id);
+                        }
+                    }
+                } else {
+                    self.selection.clear();
+                }
+                detail = Some(id);
+            }
+            Some((id, RowAction::Transform(t, new_entry))) => transform = Some((id, t, new_entry)),
+            Some((id, RowAction::CopyPlain)) => plain_id = Some(id),
+            Some((id, RowAction::SaveSnippet)) => {
+                if let Some(Clip::Text(t)) = data.clip.get(id).map(|e| &e.clip) {
+                    self.snippets.create(t.clone());
+                }
+            }
+            Some((id, RowAction::CopyColor(n))) => {
+                if let Some(Kind::Color(c)) = data.clip.get(id).map(|e| e.kind)
+                    && let Some(text) = color::format(c, n)
+                {
+                    let _ = self.ctx.set_text(text);
+                    copyed = true;
+                }
+            }
+            Some((id, RowAction::PickColor)) => {
+                if let Some(Clip::Text(t)) = data.clip.get(id).map(|e| &e.clip)
+                    && let Some(([r, g, b, a], n)) = color::parse(t)
+                {
+                    self.picker = Some((egui::Color32::from_rgba_unmultiplied(r, g, b, a), n));
+                }
+            }
+            Some((id, RowAction::ShowQr)) => {
+                if let Some(Clip::Text(t)) = data.clip.get(id).map(|e| &e.clip) {
+                    self.qr = Some(qr::Popup::new(t.clone()));
+                }
+            }
+            Some((id, RowAction::Ocr)) => match data.clip.load(id) {
+                Ok(Clip::Img(d)) => {
+                    self.ocr_pending.insert(id);
+                    ocr::spawn(
+                        id,
+                        d,
+                        data.config.ocr.clone(),
+                        self.ocr_tx.clone(),
+                        ctx.clone(),
+                    );
+                }
+                Ok(_) => {}
+                Err(e) => self.status = Some(format!("load image fail {}", e)),
+            },
+            Some((id, RowAction::SaveImage)) => match data.clip.load(id) {
+                Ok(Clip::Img(d)) => {
+                    let time = data.clip.get(id).map_or(0, |e| e.time);
+                    self.save_dialog = Some(files::SaveDialog::new(
+                        d,
+                        time,
+                        &data.config.screenshots_dir(),
+                    ));
+                }
+                Ok(_) => {}
+                Err(e) => self.status = Some(format!("load image fail {}", e)),
+            },
+            Some((id, RowAction::CopyFile)) => {
+                let time = data.clip.get(id).map_or(0, |e| e.time);
+                let res = data
+                    .clip
+                    .load(id)
+                    .and_then(|clip| files::temp_file(&clip, time))
+                    .and_then(|p| {
+                        self.ctx
+                            .set_files(vec![p.display().to_string()])
+                            .map_err(|e| e.to_string())
+                    });
+                match res {
+                    Ok(_) => copyed = true,
+                    Err(e) => self.status = Some(format!("copy as file fail {}", e)),
+                }
+            }
+            Some((id, RowAction::EditImage)) => match data.clip.load(id) {
+                Ok(Clip::Img(d)) => match imaging::Toolbox::new(&d) {
+                    Ok(t) => self.toolbox = Some(t),
+                    Err(e) => self.status = Some(format!("open image fail {}", e)),
+                },
+                Ok(_) => {}
+                Err(e) => self.status = Some(format!("load image fail {}", e)),
+            },
+            Some((id, RowAction::ShowOcr)) => {
+                if let Some(text) = data.clip.get(id).and_then(|e| e.ocr.clone()) {
+                    let clip = Clip::Text(text.clone());
+                    // 文本记录被删除或淘汰时重新加入
+                    if data.clip.find(&clip).is_none() && data.push(Clip::Text(text)) {
+                        data.dirty = true;
+                    }
+                    detail = data.clip.find(&clip);
+                }
+            }
+            Some((id, RowAction::Open(a))) => {
+                if let Some(Clip::Text(t)) = data.clip.get(id).map(|e| &e.clip) {
+                    let res = match a {
+                        open::Action::Browser => open::url(&classify::url_target(t)),
+                        open::Action::Reveal => open::reveal(&classify::local_path(t)),
+                        open::Action::Editor => {
+                            open::edit(&classify::local_path(t), &data.config.editor)
+                        }
+                    };
+                    if let Err(e) = res {
+                        self.status = Some(e);
+                    }
+                }
+            }
+            None => {}
+        }
+
+        if let Some(popup) = &mut self.qr {
+            let (open, png) = popup.show(ctx);
+            if !open {
+                self.qr = None;
+            }
+            if let Some(png) = png {
+                let clip = Clip::Img(png);
+                if let Err(e) = self.copy_clip(&clip) {
+                    self.status = Some(format!("copy fail {}", e));
+                }
+                if data.push(clip) {
+                    data.dirty = true;
+                }
+                copyed = true;
+            }
+        }
+
+        // 图片编辑，每次的结果作为新记录
+        if let Some(toolbox) = &mut self.toolbox {
+            let (open, image) = toolbox.show(ctx);
+            if !open {
+                self.toolbox = None;
+            }
+            // 可能还有后续操作，不隐藏窗口
+            if let Some(image) = image {
+                let clip = Clip::Img(image);
+                if let Err(e) = self.copy_clip(&clip) {
+                    self.status = Some(format!("copy fail {}", e));
+                }
+                if data.push(clip) {
+                    data.dirty = true;
+                }
+            }
+        }
+
+        if let Some(c) = &mut self.compare
+            && !c.show(ctx)
+        {
+            self.compare = None;
+        }
+
+        if let Some(dialog) = &mut self.save_dialog {
+            let (open, dir) = dialog.show(ctx);
+            if !open {
+                self.save_dialog = None;
+            }
+            // 记住保存的目录
+            if let Some(dir) = dir
+                && std::path::Path::new(&dir) != data.config.screenshots_dir()
+            {
+                data.config.screenshots = dir;
+                if let Err(e) = data.config.save() {
+                    self.status = Some(e);
+                }
+            }
+        }
+
+        // 取色器，结果作为新记录
+        if let Some((mut c, mut notation)) = self.picker {
+            let mut open = true;
+            let mut picked = None;
+            egui::Window::new("color")
+                .open(&mut open)
+                .collapsible(false)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    egui::color_picker::color_picker_color32(
+                        ui,
+                        &mut c,
+                        egui::color_picker::Alpha::OnlyBlend,
+                    );
+                    ui.horizontal(|ui| {
+                        for n in color::Notation::ALL {
+                            ui.radio_value(&mut notation, n, n.name());
+                        }
+                    });
+                    let text = color::format(c.to_srgba_unmultiplied(), notation);
+                    ui.horizontal(|ui| {
+                        match &text {
+                            Some(t) => ui.monospace(t),
+                            None => ui.weak("no css name"),
+                        };
+                        if ui
+                            .add_enabled(text.is_some(), egui::Button::new("new entry"))
+                            .clicked()
+                        {
+                            picked = text.clone();
+                        }
+                    });
+                });
+            self.picker = (open && picked.is_none()).then_some((c, notation));
+            if let Some(text) = picked {
+                if data.push(Clip::Text(text.clone())) {
+                    data.dirty = true;
+                }
+                let _ = self.ctx.set_text(text);
+                copyed = true;
+            }
+        }
+
+        // 转换面板
+        if let Some(mut sel) = self.palette {
+            let n = Transform::ALL.len();
+            let (up, down, enter, shift, esc) = ctx.input(|i| {
+                (
+                    i.key_pressed(egui::Key::ArrowUp),
+                    i.key_pressed(egui::Key::ArrowDown),
+                    i.key_pressed(egui::Key::Enter),
+                    i.modifiers.shift,
+                    i.key_pressed(egui::Key::Escape),
+                )
+            });
+            if up {
+                sel = (sel + n - 1) % n;
+            }
+            if down {
+                sel = (sel + 1) % n;
+            }
+            let mut chosen = enter.then_some((Transform::ALL[sel], shift));
+            egui::Window::new("transform")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .show(ctx, |ui| {
+                    ui.weak("enter: copy · shift+enter: new entry · esc: close");
+                    for (i, t) in Transform::ALL.iter().enumerate() {
+                        let r = ui.selectable_label(i == sel, t.name());
+                        if r.clicked() {
+                            chosen = Some((*t, false));
+                        }
+                        if i == sel && (up || down) {
+                            r.scroll_to_me(None);
+                        }
+                    }
+                });
+            self.palette = if chosen.is_some() || esc {
+                None
+            } else {
+                Some(sel)
+            };
+            if let Some((t, new_entry)) = chosen
+                && let Some(id) = data.detail
+            {
+                transform = Some((id, t, new_entry));
+            }
+        }
+
+        if cancel_edit {
+            self.editing = None;
+        }
+        if let Some((id, text, copy)) = saved {
+            if copy {
+                let _ = self.ctx.set_text(text.clone());
+                copyed = true;
+            }
+            match data.clip.edit(id, text) {
+                Ok(changed) => {
+                    data.dirty |= changed;
+                    self.editing = None;
+                }
+                // 保留编辑中的内容
+                Err(e) => self.status = Some(e),
+            }
+        }
+        if let Some((id, revision)) = restored
+            && data.clip.restore(id, revision)
+        {
+            data.dirty = true;
+        }
+        if detail.is_some() {
+            data.detail = detail;
+            self.editing = None;
+        }
+        if let Some(id) = copy_id {
+            match data.clip.load(id).and_then(|clip| self.copy_clip(&clip)) {
+                Ok(()) => copyed = true,
+                Err(e) => self.status = Some(format!("copy fail {}", e)),
+            }
+        }
+        if let Some(id) = plain_id {
+            match data.clip.get(id).map(|e| &e.clip) {
+                Some(Clip::Text(text)) => {
+                    let text = transform::plain_text(text, &data.config.plain_text);
+                    let _ = self.ctx.set_text(text);
+                    copyed = true;
+                }
+                _ => self.status = Some("only text can be copied as plain text".to_string()),
+            }
+        }
+        if let Some((id, t, new_entry)) = transform {
+            let res = match data.clip.get(id).map(|e| &e.clip) {
+                Some(Clip::Text(text)) => t.apply(text),
+                _ => Err("only text can be transformed".to_string()),
+            };
+            match res {
+                Ok(text) => {
+                    self.status = None;
+                    if new_entry {
+                        if data.push(Clip::Text(text)) {
+                            data.dirty = true;
+                        }
+                    } else {
+                        let _ = self.ctx.set_text(text);
+                        copyed = true;
+                    }
+                }
+                Err(e) => self.status = Some(format!("{}: {}", t.name(), e)),
+            }
+        }
+
+        if join && !self.selection.is_empty() {
+            copyed |= self.join(data);
+        }
+
+        if let Some(id) = removed_id {
+            data.clip.remove(id);
+            self.selection.retain(|i| *i != id);
+            data.dirty = true;
+        }
+        copyed
+    }
+
+    /// 按选择顺序合并多选的文本记录，作为新记录写入剪切板
+    fn join(&mut self, data: &mut Data) -> bool {
+        let mut parts = Vec::new();
+        for id in &self.selection {
+            match data.clip.get(*id).map(|e| &e.clip) {
+                Some(Clip::Text(t)) => parts.push(t.as_str()),
+                Some(_) => {
+                    self.status = Some("only text can be joined".to_string());
+                    return false;
+                }
+                None => {}
+            }
+        }
+        if parts.is_empty() {
+            return false;
+        }
+        let text = parts.join(&data.config.join_separator.value());
+        s_info!("join {} entries", parts.len());
+        if data.push(Clip::Text(text.clone())) {
+            data.dirty = true;
+        }
+        let _ = self.ctx.set_text(text);
+        self.selection.clear();
+        true
+    }
+
+    /// 按搜索内容过滤，返回从新到旧的id，没有搜索时返回 None
+    fn filter(&mut self, store: &Store) -> Option<Vec<u64>> {
+        if self.search.is_empty() {
+            return None;
+        }
+        if let Some((search, version, ids)) = &self.filtered
+            && *search == self.search
+            && *version == store.version()
+        {
+            return Some(ids.clone());
+        }
+        let query = self.search.to_lowercase();
+        let ids: Vec<u64> = store
+            .iter()
+            .rev()
+            .filter(|e| match &e.clip {
+                Clip::Text(t) => t.to_lowercase().contains(&query),
+                // 图片按识别出的文字搜索
+                _ => e
+                    .ocr
+                    .as_ref()
+                    .is_some_and(|t| t.to_lowercase().contains(&query)),
+            })
+            .map(|e| e.id)
+            .collect();
+        self.filtered = Some((self.search.clone(), store.version(), ids.clone()));
+        Some(ids)
+    }
+
+    /// 写入剪切板，图片无法解码时返回错误
+    fn copy_clip(&self, clip: &Clip) -> Result<(), String> {
+        match clip {
+            Clip::Text(t) => {
+                s_info!("copy {}", t);
+                let _ = self.ctx.set_text(t.clone());
+            }
+            Clip::Img(d) => {
+                s_info!("copy img",);
+                let img = RustImageData::from_bytes(d.as_slice()).map_err(|e| e.to_string())?;
+                let _ = self.ctx.set_image(img);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// 列表中的一行，高度固定
+    fn show_row(
+        &self,
+        ui: &mut egui::Ui,
+        ele: &Entry,
+        row_height: f32,
+        selected: bool,
+        order: Option<usize>,
+    ) -> egui::InnerResponse<Option<RowAction>> {
+        ui.allocate_ui_with_layout(
+            egui::vec2(ui.available_width(), row_height),
+            egui::Layout::left_to_right(egui::Align::Center),
+            |ui| {
+                ui.set_height(row_height);
+                if selected {
+                    ui.painter().rect_filled(
+                        ui.max_rect(),
+                        2.0,
+                        ui.visuals().selection.bg_fill.gamma_multiply(0.3),
+                    );
+                }
+                if let Some(n) = order {
+                    ui.painter().rect_filled(
+                        ui.max_rect(),
+                        2.0,
+                        ui.visuals().selection.bg_fill.gamma_multiply(0.3),
+                    );
+                    ui.label(egui::RichText::new((n + 1).to_string()).strong());
+                }
+                let mut action = None;
+                if ui.button("Copy").clicked() {
+                    action = Some(RowAction::Copy);
+                }
+                if ui.link("del").clicked() {
+                    action = Some(RowAction::Delete);
+                }
+                let exists = ele.kind == Kind::Path && self.path_exists(ele);
+                if let Some(a) = kind_badge(ui, ele, exists) {
+                    action = Some(a);
+                }
+                let resp = match &ele.clip {
+                    Clip::Text(_) => ui.add(
+                        egui::Label::new(preview_galley(ui, &ele.preview))
+                            .selectable(false)
+                            .sense(egui::Sense::click()),
+                    ),
+                    Clip::Img(_) => {
+                        let texture = self
+                            .textures
+                            .borrow_mut()
+                            .thumb(ui.ctx(), ele.id, &ele.clip);
+                        match texture {
+                            Ok(texture) => ui.add(
+                                egui::Image::new(texture)
+                                    .max_height(row_height)
+                                    .sense(egui::Sense::click()),
+                            ),
+                            Err(e) => ui
+                                .add(
+                                    egui::Label::new(
+                                        egui::RichText::new("[image]")
+                                            .color(ui.visuals().weak_text_color()),
+                                    )
+                                    .selectable(false)
+                                    .sense(egui::Sense::click()),
+                                )
+                                .on_hover_text(e),
+                        }
+                    }
+                    _ => return action,
+                };
+                if resp.clicked() {
+                    action = Some(RowAction::Select);
+                }
+                let pending = self.ocr_pending.contains(&ele.id);
+                if pending {
+                    ui.spinner().on_hover_text("recognizing text");
+                } else if let Some(text) = &ele.ocr {
+                    ui.label(
+                        egui::RichText::new("ocr")
+                            .small()
+                            .color(ui.visuals().weak_text_color())
+                            .background_color(ui.visuals().extreme_bg_color),
+                    )
+                    .on_hover_text(store::preview_text(text));
+                }
+                if let Clip::Img(_) = &ele.clip {
+                    resp.context_menu(|ui| {
+                        let label = if ele.ocr.is_some() {
+                            "extract text again"
+                        } else {
+                            "extract text (ocr)"
+                        };
+                        if ui.add_enabled(!pending, egui::Button::new(label)).clicked() {
+                            action = Some(RowAction::Ocr);
+                        }
+                        if ele.ocr.is_some() && ui.button("go to text").clicked() {
+                            action = Some(RowAction::ShowOcr);
+                        }
+                        if ui.button("edit image…").clicked() {
+                            action = Some(RowAction::EditImage);
+                        }
+                        if ui.button("save as…").clicked() {
+                            action = Some(RowAction::SaveImage);
+                        }
+                        if ui
+                            .button("copy as file")
+                            .on_hover_text("paste into a file manager or chat app")
+                            .clicked()
+                        {
+                            action = Some(RowAction::CopyFile);
+                        }
+                    });
+                }
+                if let Clip::Text(_) = &ele.clip {
+                    resp.context_menu(|ui| {
+                        transform_menu(ui, "transform → copy", false, &mut action);
+                        transform_menu(ui, "transform → new entry", true, &mut action);
+                        if ui.button("copy as plain text").clicked() {
+                            action = Some(RowAction::CopyPlain);
+                        }
+                        if ui.button("save as snippet").clicked() {
+                            action = Some(RowAction::SaveSnippet);
+                        }
+                        if ui.button("show as qr").clicked() {
+                            action = Some(RowAction::ShowQr);
+                        }
+                        if ui
+                            .button("copy as file")
+                            .on_hover_text("paste into a file manager or chat app")
+                            .clicked()
+                        {
+                            action = Some(RowAction::CopyFile);
+                        }
+                        match ele.kind {
+                            Kind::Url if ui.button("open in browser").clicked() => {
+                                action = Some(RowAction::Open(open::Action::Browser));
+                            }
+                            Kind::Path => {
+                                if ui.button("reveal in file manager").clicked() {
+                                    action = Some(RowAction::Open(open::Action::Reveal));
+                                }
+                                if ui.button("open in editor").clicked() {
+                                    action = Some(RowAction::Open(open::Action::Editor));
+                                }
+                            }
+                            _ => {}
+                        }
+                        if let Kind::Color(c) = ele.kind {
+                            ui.menu_button("color", |ui| {
+                                for n in color::Notation::ALL {
+                                    let text = color::format(c, n);
+                                    let label =
+                                        format!("copy {}", text.as_deref().unwrap_or(n.name()));
+                                    if ui
+                                        .add_enabled(text.is_some(), egui::Button::new(label))
+                                        .clicked()
+                                    {
+                                        action = Some(RowAction::CopyColor(n));
+                                    }
+                                }
+                                if ui.button("pick…").clicked() {
+                                    action = Some(RowAction::PickColor);
+                                }
+                            });
+                        }
+                    });
+                }
+                action
+            },
+        )
+    }
+
+    /// 拖入窗口的文件作为新记录
+    fn receive_drop(&mut self, ctx: &egui::Context, data: &mut Data) {
+        let (hovered, dropped) =
+            ctx.input(|i| (!i.raw.hovered_files.is_empty(), i.raw.dropped_files.clone()));
+        if hovered {
+            let rect = ctx.screen_rect();
+            let painter = ctx.layer_painter(egui::LayerId::new(
+                egui::Order::Foreground,
+                egui::Id::new("drop"),
+            ));
+            painter.rect_filled(rect, 0.0, egui::Color32::from_black_alpha(160));
+            painter.text(
+                rect.center(),
+                egui::Align2::CENTER_CENTER,
+                "drop to add",
+                egui::FontId::proportional(20.0),
+                egui::Color32::WHITE,
+            );
+        }
+        for f in &dropped {
+            match files::dropped(f) {
+                Ok(clip) => {
+                    if data.push(clip) {
+                        data.dirty = true;
+                    }
+                }
+                Err(e) => self.status = Some(e),
+            }
+        }
+    }
+
+    /// 处理识别完成的图片，文字作为新记录并关联到图片
+    fn receive_ocr(&mut self, data: &mut Data) {
+        while let Ok((id, res)) = self.ocr_rx.try_recv() {
+            self.ocr_pending.remove(&id);
+            match res {
+                Ok(text) => {
+                    data.push(Clip::Text(text.clone()));
+                    data.clip.set_ocr(id, text);
+                    data.dirty = true;
+                }
+                Err(e) => self.status = Some(e),
+            }
+        }
+    }
+
+    /// 路径是否存在，在后台检查，避免每帧访问文件系统
+    fn path_exists(&self, ele: &Entry) -> bool {
+        match &ele.clip {
+            Clip::Text(t) => self.paths.exists(ele.id, || classify::local_path(t)),
+            _ => false,
+        }
+    }
+
     fn switch_top(&mut self, _ctx: &egui::Context) {
         match self.data.lock() {
             Ok(mut v) => {
@@ -648,11 +2069,19 @@ impl eframe::App for ClipboardApp {
             // 响应退出
             if ctx.input(|i| i.viewport().close_requested()) {
                 let _ = self.sender.send(Clip::Quit);
+                if self.data.lock().is_ok_and(|d| d.dirty || d.touched) {
+                    save(&self.data);
+                }
             }
             let mut sw = false;
-            match self.data.lock() {
+            let mut clear_status = false;
+            let mut save_error = None;
+            let data = Arc::clone(&self.data);
+            match data.lock() {
                 Ok(mut data) => {
                     if !data.window_visble {
+                        // 隐藏时释放纹理，显示时重新加载
+                        self.textures.get_mut().clear();
                         return;
                     }
                     ui.horizontal(|ui| {
@@ -660,68 +2089,197 @@ impl eframe::App for ClipboardApp {
                         if ui.button("top").clicked() {
                             sw = true;
                         }
+                        if let Ok(mut q) = self.queue.lock() {
+                            let on = q.is_on();
+                            if ui
+                                .selectable_label(on, "queue")
+                                .on_hover_text("paste copied items one by one in order")
+                                .clicked()
+                            {
+                                q.set_on(!on);
+                            }
+                            if q.is_on() {
+                                let (pasted, total) = q.progress();
+                                ui.label(format!("{}/{}", pasted, total));
+                            }
+                        }
+                        ui.menu_button("settings", |ui| {
+                            let reason = paste::unsupported();
+                            if ui
+                                .add_enabled(
+                                    reason.is_none(),
+                                    egui::Checkbox::new(&mut data.config.paste_on_select, "paste on select"),
+                                )
+                                .on_hover_text("paste into the previous window after copy from the hotkey popup")
+                                .on_disabled_hover_text(reason.unwrap_or_default())
+                                .changed()
+                                && let Err(e) = data.config.save()
+                            {
+                                save_error = Some(e);
+                            }
+                            if data.config.paste_on_select
+                                && !data.config.paste_overrides.is_empty()
+                                && !paste::has_window_class()
+                            {
+                                ui.weak("paste_overrides are ignored on wayland")
+                                    .on_hover_text(format!(
+                                        "the focused app is unknown, always using {}",
+                                        data.config.paste_keys
+                                    ));
+                            }
+                            ui.horizontal(|ui| {
+                                ui.label("editor")
+                                    .on_hover_text("command to open paths, empty for the system default");
+                                if ui
+                                    .add(
+                                        egui::TextEdit::singleline(&mut data.config.editor)
+                                            .desired_width(120.0),
+                                    )
+                                    .lost_focus()
+                                    && let Err(e) = data.config.save()
+                                {
+                                    save_error = Some(e);
+                                }
+                            });
+                            ui.separator();
+                            let mut changed = ui
+                                .checkbox(&mut data.config.sync.lan, "lan sync")
+                                .on_hover_text("takes effect after restart")
+                                .changed();
+                            ui.horizontal(|ui| {
+                                ui.label("folder")
+                                    .on_hover_text("shared sync folder, needs secret, takes effect after restart");
+                                changed |= ui
+                                    .add(
+                                        egui::TextEdit::singleline(
+                                            &mut data.config.sync.folder,
+                                        )
+                                        .desired_width(120.0),
+                                    )
+                                    .lost_focus();
+                            });
+                            ui.horizontal(|ui| {
+                                ui.label("secret");
+                                changed |= ui
+                                    .add(
+                                        egui::TextEdit::singleline(
+                                            &mut data.config.sync.secret,
+                                        )
+                                        .password(true)
+                                        .desired_width(120.0),
+                                    )
+                                    .lost_focus();
+                            });
+                            if changed && let Err(e) = data.config.save() {
+                                save_error = Some(e);
+                            }
+                            if let Some(node) = &data.sync {
+                                ui.weak(format!("listening on {}", node.addr()));
+                            }
+                            ui.separator();
+                            if ui.button("export / import").clicked() {
+                                self.transfer.open = true;
+                                ui.close();
+                            }
+                        });
+                        ui.label(format!(
+                            "{} · {}",
+                            data.clip.len(),
+                            human_size(data.clip.bytes())
+                        ));
+                        if let Some(status) = &self.status
+                            && ui
+                                .add(
+                                    egui::Label::new(
+                                        egui::RichText::new(status)
+                                            .color(ui.visuals().error_fg_color),
+                                    )
+                                    .sense(egui::Sense::click()),
+                                )
+                                .on_hover_text("click to dismiss")
+                                .clicked()
+                        {
+                            clear_status = true;
+                        }
                     });
+                    if clear_status {
+                        self.status = None;
+                    }
+                    if let Some(e) = save_error {
+                        self.status = Some(e);
+                    }
 
-                    // 滚动
-                    ScrollArea::vertical()
-                        .auto_shrink(false)
-                        .scroll_bar_visibility(
-                            egui::scroll_area::ScrollBarVisibility::VisibleWhenNeeded,
-                        )
-                        .show(ui, |ui| {
-                            ui.with_layout(
-                                egui::Layout::top_down(egui::Align::LEFT).with_cross_justify(true),
-                                |ui| {
-                                    let mut removed_index = None;
-                                    let mut copyed = false;
-                                    for (index, ele) in data.clip.iter().enumerate().rev() {
-                                        match ele {
-                                            Clip::Text(t) => {
-                                                ui.horizontal(|ui| {
-                                                    if ui.button("Copy").clicked() {
-                                                        s_info!("copy {}", t);
-                                                        let _ = self.ctx.set_text(t.clone());
-                                                        copyed = true;
-                                                    }
-                                                    if ui.link("del").clicked() {
-                                                        removed_index = Some(index);
-                                                    }
-                                                    ui.label(format!("{}", t));
-                                                });
-                                            }
-                                            Clip::Img(d) => {
-                                                ui.horizontal(|ui| {
-                                                    if ui.button("Copy").clicked() {
-                                                        s_info!("copy img",);
-                                                        let _ = self.ctx.set_image(
-                                                            RustImageData::from_bytes(d.as_slice())
-                                                                .unwrap(),
-                                                        );
-                                                    }
-                                                    if ui.link("del").clicked() {
-                                                        removed_index = Some(index);
-                                                    }
-                                                    ui.image(ImageSource::Bytes {
-                                                        uri: std::borrow::Cow::Borrowed(
-                                                            "bytes://1.jpg",
-                                                        ),
-                                                        bytes: Bytes::from(d.clone()),
-                                                    });
-                                                });
-                                            }
-                                            _ => {}
-                                        }
-                                    }
-                                    if let Some(index) = removed_index {
-                                        data.clip.remove(index);
-                                    }
-                                    if copyed && data.is_hotkey_visible {
-                                        // 隐藏窗口
-                                        data.switch_visible(false);
-                                    }
-                                },
-                            );
-                        });
+                    // 搜索
+                    let mut search_focused = false;
+                    ui.horizontal(|ui| {
+                        ui.selectable_value(&mut self.tab, Tab::History, "history");
+                        ui.selectable_value(&mut self.tab, Tab::Snippets, "snippets");
+                        ui.selectable_value(&mut self.tab, Tab::Stats, "stats");
+                        search_focused = ui
+                            .add(
+                                egui::TextEdit::singleline(&mut self.search)
+                                    .hint_text("search")
+                                    .desired_width(f32::INFINITY),
+                            )
+                            .has_focus();
+                    });
+
+                    self.receive_ocr(&mut data);
+                    self.receive_drop(ctx, &mut data);
+
+                    // 导出多选或搜索的结果
+                    if self.transfer.open {
+                        let subset = if self.selection.is_empty() {
+                            self.filter(&data.clip)
+                        } else {
+                            Some(self.selection.clone())
+                        };
+                        if self.transfer.show(ctx, &mut data.clip, subset.as_deref()) {
+                            data.dirty = true;
+                        }
+                    }
+
+                    let clipboard = || self.ctx.get_text().unwrap_or_default();
+                    let mut used = self.snippets.show_windows(ctx, &clipboard);
+                    let mut copyed = match self.tab {
+                        Tab::History => {
+                            // 搜索时同时显示匹配的片段
+                            used = used.or(self.snippets.show_matches(ui, &self.search, &clipboard));
+                            self.history_ui(ctx, ui, &mut data, search_focused)
+                        }
+                        Tab::Stats => {
+                            self.dashboard.show(ui, &data.clip);
+                            false
+                        }
+                        Tab::Snippets => {
+                            let clipboard = || self.ctx.get_text().unwrap_or_default();
+                            used = used.or(self.snippets.show_list(ui, &self.search, &clipboard));
+                            false
+                        }
+                    };
+                    match used {
+                        Some(Ok(text)) => {
+                            let _ = self.ctx.set_text(text);
+                            copyed = true;
+                        }
+                        Some(Err(e)) => self.status = Some(e),
+                        None => {}
+                    }
+                    if copyed && data.is_hotkey_visible {
+                        // 隐藏窗口
+                        let target = data.paste_target.take();
+                        data.switch_visible(false);
+                        if data.config.paste_on_select
+                            && let Some(target) = target
+                        {
+                            if let Some(reason) = paste::unsupported() {
+                                self.status = Some(reason.to_string());
+                            } else {
+                                let keys = data.config.paste_keys_for(target.class.as_deref());
+                                paste::paste(target, keys.to_string());
+                            }
+                        }
+                    }
                 }
                 Err(_) => {
                     s_info!("update fial");
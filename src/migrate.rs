@@ -0,0 +1,564 @@
+//! 从其他剪切板管理器导入
+//!
+//! 支持 CopyQ、Clipman、GPaste 和 Klipper 的历史文件，
+//! 这些程序的列表都是新的在前，转换后按从旧到新的顺序合并。
+//! CopyQ 使用 `application/x-copyq-user-copy-time` 中的复制时间（需要在 CopyQ 中启用保存复制时间的命令），
+//! GPaste 的图片记录带有时间，Clipman 的 textsrc 和 Klipper 的 history2.lst 没有保存时间。
+//! 没有时间的记录保持来源中的顺序，最新的一条使用历史文件的修改时间，之后每条依次早一秒
+
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    Clip,
+    persist::{self, Record, Report},
+    store::Store,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Source {
+    CopyQ,
+    Clipman,
+    GPaste,
+    Klipper,
+}
+
+impl Source {
+    pub const ALL: [Source; 4] = [
+        Source::CopyQ,
+        Source::Clipman,
+        Source::GPaste,
+        Source::Klipper,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Source::CopyQ => "copyq",
+            Source::Clipman => "clipman",
+            Source::GPaste => "gpaste",
+            Source::Klipper => "klipper",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Source> {
+        Source::ALL.into_iter().find(|v| v.name() == s)
+    }
+
+    /// 默认的历史文件位置
+    pub fn default_path(self) -> Option<PathBuf> {
+        match self {
+            Source::CopyQ => {
+                dirs::config_dir().map(|d| d.join("copyq").join("copyq_tab_&clipboard.dat"))
+            }
+            Source::Clipman => {
+                dirs::cache_dir().map(|d| d.join("xfce4").join("clipman").join("textsrc"))
+            }
+            Source::GPaste => dirs::data_dir().map(|d| d.join("gpaste").join("history.xml")),
+            Source::Klipper => dirs::data_dir().map(|d| d.join("klipper").join("history2.lst")),
+        }
+    }
+
+    /// 解析历史文件，dir 用于查找单独保存的图片，newest 是没有时间时最新一条记录使用的时间，
+    /// 返回从旧到新的记录
+    pub fn read(self, data: &[u8], dir: &Path, newest: u64) -> Result<Vec<Record>, String> {
+        let mut res = match self {
+            Source::CopyQ => copyq(data)?,
+            Source::Clipman => clipman(data, dir)?,
+            Source::GPaste => gpaste(data, dir)?,
+            Source::Klipper => klipper(data)?,
+        };
+        fill_times(&mut res, newest);
+        res.reverse();
+        Ok(res)
+    }
+}
+
+/// 没有时间的记录按新的在前的顺序使用递减的时间，保持来源中的顺序
+fn fill_times(records: &mut [Record], newest: u64) {
+    let mut next = newest;
+    for r in records {
+        if r.time == 0 {
+            r.time = next;
+        }
+        next = r.time.saturating_sub(1).max(1);
+    }
+}
+
+/// 导入并合并，没有时间的记录从文件的修改时间开始依次递减
+pub(crate) fn import(source: Source, path: &Path, store: &mut Store) -> Result<Report, String> {
+    let data = std::fs::read(path).map_err(|e| format!("{} {}", path.display(), e))?;
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let mtime = std::fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or_else(crate::time::now, |d| d.as_secs());
+    let records = source.read(&data, dir, mtime)?;
+    Ok(persist::import(store, records))
+}
+
+/// QDataStream 的读取，大端
+struct Stream<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Stream<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Stream { data, pos: 0 }
+    }
+
+    fn is_end(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .filter(|e| *e <= self.data.len())
+            .ok_or_else(|| format!("unexpected end at {}", self.pos))?;
+        let res = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(res)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let b = self.take(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        self.u32().map(|v| v as i32)
+    }
+
+    fn bool(&mut self) -> Result<bool, String> {
+        Ok(self.take(1)?[0] != 0)
+    }
+
+    /// QByteArray，0xffffffff 表示 null
+    fn bytes(&mut self) -> Result<&'a [u8], String> {
+        match self.u32()? {
+            u32::MAX => Ok(&[]),
+            n => self.take(n as usize),
+        }
+    }
+
+    /// QString，utf-16 大端
+    fn string(&mut self) -> Result<String, String> {
+        let b = self.bytes()?;
+        let units: Vec<u16> = b
+            .chunks_exact(2)
+            .map(|c| u16::from_be_bytes([c[0], c[1]]))
+            .collect();
+        String::from_utf16(&units).map_err(|e| e.to_string())
+    }
+
+    /// const char*，长度包括结尾的 0
+    fn c_str(&mut self) -> Result<String, String> {
+        let b = self.bytes()?;
+        let b = b.strip_suffix(&[0]).unwrap_or(b);
+        Ok(String::from_utf8_lossy(b).to_string())
+    }
+
+    /// 内嵌的 png，没有长度，按 chunk 读到 IEND
+    fn png(&mut self) -> Result<Vec<u8>, String> {
+        let start = self.pos;
+        if self.take(8)? != b"\x89PNG\r\n\x1a\n" {
+            return Err("invalid png".to_string());
+        }
+        loop {
+            let len = self.u32()? as usize;
+            let kind = self.take(4)?;
+            self.take(len + 4)?;
+            if kind == b"IEND" {
+                return Ok(self.data[start..self.pos].to_vec());
+            }
+        }
+    }
+}
+
+/// qCompress 的数据，前 4 个字节是原始长度
+fn q_uncompress(data: &[u8]) -> Result<Vec<u8>, String> {
+    let Some(body) = data.get(4..) else {
+        return Ok(Vec::new());
+    };
+    let mut res = Vec::new();
+    flate2::read::ZlibDecoder::new(body)
+        .read_to_end(&mut res)
+        .map_err(|e| e.to_string())?;
+    Ok(res)
+}
+
+/// 多个 mime 中选择文本或者图片
+fn from_mimes(mimes: Vec<(String, Vec<u8>)>) -> Option<Clip> {
+    let find = |p: &dyn Fn(&str) -> bool| mimes.iter().find(|(m, _)| p(m)).map(|(_, d)| d);
+    if let Some(t) = find(&|m| m == "text/plain" || m.starts_with("text/plain;")) {
+        return Some(Clip::Text(String::from_utf8_lossy(t).to_string()));
+    }
+    if let Some(t) = find(&|m| m == "text/uri-list") {
+        return Some(Clip::Text(
+            String::from_utf8_lossy(t).trim_end().to_string(),
+        ));
+    }
+    // 只接受可以解码的图片，例如 svg、xcf 或者不完整的数据都会跳过
+    mimes
+        .iter()
+        .filter(|(m, _)| m.starts_with("image/"))
+        .find_map(|(_, d)| decodable(d.clone()))
+}
+
+/// 可以解码的图片
+fn decodable(data: Vec<u8>) -> Option<Clip> {
+    image::load_from_memory(&data)
+        .is_ok()
+        .then_some(Clip::Img(data))
+}
+
+/// CopyQ 保存复制时间的命令写入的 mime，值是本地时间 `yyyy-MM-dd hh:mm:ss`
+const COPYQ_TIME: &str = "application/x-copyq-user-copy-time";
+
+/// CopyQ 压缩后的 mime 前缀
+const COPYQ_MIMES: [(&str, &str); 6] = [
+    ("0", "application/x-copyq-"),
+    ("1", "text/plain"),
+    ("2", "text/html"),
+    ("3", "text/uri-list"),
+    ("4", "image/"),
+    ("5", "application/"),
+];
+
+fn copyq_mime(mime: String) -> String {
+    if mime.contains('/') {
+        return mime;
+    }
+    for (alias, prefix) in COPYQ_MIMES {
+        if let Some(rest) = mime.strip_prefix(alias) {
+            return format!("{}{}", prefix, rest);
+        }
+    }
+    mime
+}
+
+/// CopyQ 的标签页文件 copyq_tab_*.dat
+fn copyq(data: &[u8]) -> Result<Vec<Record>, String> {
+    let mut s = Stream::new(data);
+    // 可选的文件头 "CopyQ v*"
+    let mut head = Stream::new(data);
+    if let Ok(h) = head.string()
+        && h.starts_with("CopyQ")
+    {
+        s = head;
+    }
+    let count = s.i32()?;
+    let mut res = Vec::new();
+    for _ in 0..count.max(0) {
+        let mut mimes = Vec::new();
+        match s.i32()? {
+            -2 => {
+                for _ in 0..s.i32()?.max(0) {
+                    let mime = copyq_mime(s.string()?);
+                    let compressed = s.bool()?;
+                    let bytes = s.bytes()?;
+                    let bytes = if compressed {
+                        q_uncompress(bytes)?
+                    } else {
+                        bytes.to_vec()
+                    };
+                    mimes.push((mime, bytes));
+                }
+            }
+            -1 => return Err("unsupported copyq item format".to_string()),
+            // 旧格式，数据都经过 qCompress
+            n => {
+                for _ in 0..n {
+                    let mime = s.string()?;
+                    mimes.push((mime, q_uncompress(s.bytes()?)?));
+                }
+            }
+        }
+        let time = mimes
+            .iter()
+            .find(|(m, _)| m == COPYQ_TIME)
+            .and_then(|(_, d)| crate::time::parse_local(&String::from_utf8_lossy(d)))
+            .unwrap_or(0);
+        if let Some(clip) = from_mimes(mimes) {
+            res.push(Record::new(clip, time));
+        }
+    }
+    Ok(res)
+}
+
+/// GKeyFile 字符串列表的转义
+fn key_file_list(value: &str) -> Vec<String> {
+    let mut res = Vec::new();
+    let mut cur = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => cur.push('\n'),
+                Some('t') => cur.push('\t'),
+                Some('r') => cur.push('\r'),
+                Some('s') => cur.push(' '),
+                Some(c) => cur.push(c),
+                None => {}
+            },
+            ';' => res.push(std::mem::take(&mut cur)),
+            c => cur.push(c),
+        }
+    }
+    if !cur.is_empty() {
+        res.push(cur);
+    }
+    res
+}
+
+/// xfce4-clipman 的 textsrc，图片保存在同目录的 image*.png
+fn clipman(data: &[u8], dir: &Path) -> Result<Vec<Record>, String> {
+    let text = String::from_utf8_lossy(data);
+    let mut res = Vec::new();
+    let mut group = "";
+    for line in text.lines() {
+        let line = line.trim_start();
+        if let Some(g) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            group = g;
+        } else if group == "texts"
+            && let Some((key, value)) = line.split_once('=')
+            && key.trim() == "texts"
+        {
+            res.extend(
+                key_file_list(value)
+                    .into_iter()
+                    .filter(|t| !t.is_empty())
+                    .map(|t| Record::new(Clip::Text(t), 0)),
+            );
+        }
+    }
+    // 图片排在文本之后，也就是更旧
+    for i in 0.. {
+        match std::fs::read(dir.join(format!("image{}.png", i))) {
+            Ok(d) => res.extend(decodable(d).map(|c| Record::new(c, 0))),
+            Err(_) => break,
+        }
+    }
+    Ok(res)
+}
+
+/// GPaste 的 history.xml，图片的值是文件路径
+fn gpaste(data: &[u8], dir: &Path) -> Result<Vec<Record>, String> {
+    let text = std::str::from_utf8(data).map_err(|e| e.to_string())?;
+    let doc = roxmltree::Document::parse(text).map_err(|e| e.to_string())?;
+    let mut res = Vec::new();
+    for item in doc
+        .root_element()
+        .children()
+        .filter(|n| n.has_tag_name("item"))
+    {
+        // 2.0 的内容在 value 中，1.0 直接是 item 的内容
+        let value: String = item
+            .children()
+            .find(|n| n.has_tag_name("value"))
+            .unwrap_or(item)
+            .descendants()
+            .filter(|n| n.is_text())
+            .filter_map(|n| n.text())
+            .collect();
+        let time = item
+            .attribute("date")
+            .and_then(|d| d.parse().ok())
+            .unwrap_or(0);
+        let clip = match item.attribute("kind") {
+            Some("Text") | Some("Uris") => Clip::Text(value),
+            Some("Image") => {
+                let path = dir.join(value.trim());
+                match std::fs::read(&path).map(decodable) {
+                    Ok(Some(c)) => c,
+                    Ok(None) => {
+                        s_error!("skip gpaste image {} not an image", path.display());
+                        continue;
+                    }
+                    Err(e) => {
+                        s_error!("skip gpaste image {} {}", path.display(), e);
+                        continue;
+                    }
+                }
+            }
+            // 密码不导入
+            _ => continue,
+        };
+        res.push(Record::new(clip, time));
+    }
+    Ok(res)
+}
+
+/// Klipper 的 history2.lst，crc32 加上 QByteArray，
+/// 内容是版本号和若干条 (类型, 数据)
+fn klipper(data: &[u8]) -> Result<Vec<Record>, String> {
+    let mut outer = Stream::new(data);
+    let crc = outer.u32()?;
+    let body = outer.bytes()?;
+    let mut hasher = flate2::Crc::new();
+    hasher.update(body);
+    if hasher.sum() != crc {
+        return Err("klipper history checksum mismatch".to_string());
+    }
+    let mut s = Stream::new(body);
+    let version = s.c_str()?;
+    s_info!("klipper history version {}", version);
+    let mut res = Vec::new();
+    while !s.is_end() {
+        let clip = match s.string()?.as_str() {
+            "string" => Clip::Text(s.string()?),
+            "url" => {
+                let mut urls = Vec::new();
+                for _ in 0..s.u32()? {
+                    urls.push(String::from_utf8_lossy(s.bytes()?).to_string());
+                }
+                // 元数据
+                for _ in 0..s.u32()? {
+                    s.string()?;
+                    s.string()?;
+                }
+                // (int)m_cut
+                s.i32()?;
+                Clip::Text(urls.join("\n"))
+            }
+            "image" => {
+                if s.i32()? == 0 {
+                    continue;
+                }
+                match decodable(s.png()?) {
+                    Some(c) => c,
+                    None => continue,
+                }
+            }
+            t => return Err(format!("unknown klipper item {}", t)),
+        };
+        res.push(Record::new(clip, 0));
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试中历史文件的修改时间
+    const NEWEST: u64 = 1_800_000_000;
+
+    fn fixture(source: Source, file: &str) -> Vec<Record> {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("fixtures/migrate")
+            .join(source.name());
+        let data = std::fs::read(dir.join(file)).unwrap();
+        source.read(&data, &dir, NEWEST).unwrap()
+    }
+
+    /// 文本原样返回，图片返回 "<img>"
+    fn texts(records: Vec<Record>) -> Vec<(String, u64)> {
+        records
+            .into_iter()
+            .map(|r| {
+                let time = r.time;
+                match r.into_clip().unwrap().0 {
+                    Clip::Text(t) => (t, time),
+                    Clip::Img(d) => {
+                        assert!(d.starts_with(b"\x89PNG"));
+                        ("<img>".to_string(), time)
+                    }
+                    Clip::Quit => unreachable!(),
+                }
+            })
+            .collect()
+    }
+
+    fn check(source: Source, file: &str, expect: &[(&str, u64)]) {
+        let got = texts(fixture(source, file));
+        let expect: Vec<(String, u64)> = expect.iter().map(|(t, n)| (t.to_string(), *n)).collect();
+        assert_eq!(got, expect);
+    }
+
+    #[test]
+    fn copyq() {
+        // 复制时间是本地时间，没有时间的图片排在更新的记录之前
+        let newest = crate::time::parse_local("2024-02-29 23:30:00").unwrap();
+        let multi = crate::time::parse_local("2024-02-29 23:00:00").unwrap();
+        check(
+            Source::CopyQ,
+            "copyq_tab_&clipboard.dat",
+            &[
+                ("<img>", multi - 1),
+                ("multi\nline", multi),
+                ("copyq newest 你好", newest),
+            ],
+        );
+    }
+
+    #[test]
+    fn clipman() {
+        check(
+            Source::Clipman,
+            "textsrc",
+            &[
+                ("<img>", NEWEST - 3),
+                ("semi;colon and \\ backslash", NEWEST - 2),
+                ("two\nlines\twith tab", NEWEST - 1),
+                ("clipman newest", NEWEST),
+            ],
+        );
+    }
+
+    #[test]
+    fn gpaste() {
+        check(
+            Source::GPaste,
+            "history.xml",
+            &[
+                ("file:///tmp/a.txt\nfile:///tmp/b.txt", 1_699_999_999),
+                ("<img>", 1_700_000_000),
+                ("gpaste newest & <tags>", NEWEST),
+            ],
+        );
+    }
+
+    #[test]
+    fn klipper() {
+        check(
+            Source::Klipper,
+            "history2.lst",
+            &[
+                ("klipper oldest", NEWEST - 3),
+                ("<img>", NEWEST - 2),
+                ("file:///tmp/a.txt\nhttps://example.com", NEWEST - 1),
+                ("klipper newest", NEWEST),
+            ],
+        );
+    }
+
+    #[test]
+    fn klipper_checksum() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/migrate/klipper");
+        let mut data = std::fs::read(dir.join("history2.lst")).unwrap();
+        let last = data.len() - 1;
+        data[last] ^= 1;
+        assert!(Source::Klipper.read(&data, &dir, NEWEST).is_err());
+    }
+
+    #[test]
+    fn invalid_images() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/migrate/clipman");
+        let png = std::fs::read(dir.join("image0.png")).unwrap();
+        let mime = |m: &str, d: &[u8]| (m.to_string(), d.to_vec());
+        let svg = mime(
+            "image/svg+xml",
+            b"<svg xmlns='http://www.w3.org/2000/svg'/>",
+        );
+        assert!(from_mimes(vec![svg.clone()]).is_none());
+        assert!(from_mimes(vec![mime("image/png", &png[..png.len() / 2])]).is_none());
+        // 跳过无法解码的，使用后面的图片
+        assert!(from_mimes(vec![svg, mime("image/png", &png)]) == Some(Clip::Img(png)));
+    }
+}
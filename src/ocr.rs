@@ -0,0 +1,169 @@
+//! 图片文字识别
+//!
+//! 调用本地安装的 tesseract 命令行，图片先写入当前用户私有目录下的临时文件，结果从标准输出读取。
+//! 识别较慢，在新线程中运行，超时后结束进程，结果通过 channel 返回
+
+use std::{
+    io::Read,
+    path::Path,
+    process::{Command, Stdio},
+    sync::mpsc::Sender,
+    thread,
+    time::{Duration, Instant},
+};
+
+use eframe::egui;
+
+use crate::config::Ocr;
+
+/// 识别的超时时间
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// (图片记录的id, 识别结果)
+pub(crate) type Done = (u64, Result<String, String>);
+
+/// 识别图片中的文字，没有识别出文字时返回错误
+pub(crate) fn recognize(image: &[u8], config: &Ocr) -> Result<String, String> {
+    recognize_with(image, config, TIMEOUT)
+}
+
+fn recognize_with(image: &[u8], config: &Ocr, timeout: Duration) -> Result<String, String> {
+    let path = crate::persist::private_dir("ocr")?.join(crate::snippet::uuid_v4());
+    crate::persist::write_new(&path, image)?;
+    let out = run(&path, config, timeout);
+    let _ = std::fs::remove_file(&path);
+    clean(&out?)
+}
+
+/// 运行命令，返回标准输出
+fn run(path: &Path, config: &Ocr, timeout: Duration) -> Result<Vec<u8>, String> {
+    let mut child = Command::new(&config.command)
+        .arg(path)
+        .arg("stdout")
+        .args(["-l", &config.lang])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => {
+                format!("{} not found, install tesseract first", config.command)
+            }
+            _ => format!("{} {}", config.command, e),
+        })?;
+    // 在其他线程读取输出，避免输出填满管道后进程阻塞
+    let read = |pipe: Option<Box<dyn Read + Send>>| {
+        thread::spawn(move || {
+            let mut buf = Vec::new();
+            if let Some(mut p) = pipe {
+                let _ = p.read_to_end(&mut buf);
+            }
+            buf
+        })
+    };
+    let stdout = read(child.stdout.take().map(|p| Box::new(p) as _));
+    let stderr = read(child.stderr.take().map(|p| Box::new(p) as _));
+    let start = Instant::now();
+    let status = loop {
+        match child.try_wait().map_err(|e| e.to_string())? {
+            Some(status) => break status,
+            None if start.elapsed() > timeout => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("ocr timeout after {}s", timeout.as_secs()));
+            }
+            None => thread::sleep(Duration::from_millis(50)),
+        }
+    };
+    let stdout = stdout.join().unwrap_or_default();
+    if !status.success() {
+        let err = stderr.join().unwrap_or_default();
+        let err = String::from_utf8_lossy(&err);
+        let line = err.lines().rfind(|l| !l.trim().is_empty()).unwrap_or("");
+        return Err(format!("ocr fail {}", line.trim()));
+    }
+    Ok(stdout)
+}
+
+/// 去掉每行末尾的空白、多余的空行和分页符
+fn clean(stdout: &[u8]) -> Result<String, String> {
+    let text = String::from_utf8_lossy(stdout);
+    let lines: Vec<&str> = text.lines().map(str::trim_end).collect();
+    let text = lines.join("\n").trim().replace('\u{c}', "");
+    if text.is_empty() {
+        return Err("no text recognized".to_string());
+    }
+    Ok(text)
+}
+
+/// 在新线程中识别，完成后通知界面刷新
+pub(crate) fn spawn(id: u64, image: Vec<u8>, config: Ocr, tx: Sender<Done>, ctx: egui::Context) {
+    thread::spawn(move || {
+        s_info!("ocr {} start", id);
+        let res = recognize(&image, &config);
+        if let Err(e) = &res {
+            s_error!("ocr {} {}", id, e);
+        }
+        let _ = tx.send((id, res));
+        ctx.request_repaint();
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cleanup() {
+        assert_eq!(
+            clean(b"  first  \n\nsecond\t\n\n\x0c").unwrap(),
+            "first\n\nsecond"
+        );
+        assert!(clean(b" \n\x0c\n").is_err());
+    }
+
+    /// 用脚本代替 tesseract
+    #[cfg(unix)]
+    #[test]
+    fn command() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = crate::testdir::TempDir::new("ocr");
+        let script = |name: &str, body: &str| {
+            let path = dir.join(name);
+            std::fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+            Ocr {
+                command: path.display().to_string(),
+                lang: "eng".to_string(),
+            }
+        };
+        // 输出图片路径，识别结束后临时文件已经删除
+        let echo = script("echo", "printf '%s  \\n\\n' \"$1\"; cat \"$1\" >/dev/null");
+        let path = recognize(b"image", &echo).unwrap();
+        assert!(path.contains("ocr"));
+        assert!(!Path::new(&path).exists());
+
+        let fail = script("fail", "echo 'bad image' >&2; exit 1");
+        assert_eq!(
+            recognize(b"image", &fail).unwrap_err(),
+            "ocr fail bad image"
+        );
+
+        let hang = script("hang", "sleep 5");
+        let start = Instant::now();
+        let err = recognize_with(b"image", &hang, Duration::from_millis(200)).unwrap_err();
+        assert!(err.contains("timeout"));
+        assert!(start.elapsed() < Duration::from_secs(3));
+
+        let missing = Ocr {
+            command: dir.join("missing").display().to_string(),
+            lang: "eng".to_string(),
+        };
+        assert!(
+            recognize(b"image", &missing)
+                .unwrap_err()
+                .contains("not found")
+        );
+    }
+}
@@ -0,0 +1,223 @@
+//! 打开 url 和路径
+//!
+//! 使用系统默认程序，Linux 为 xdg-open，macOS 为 open，Windows 为 explorer。
+//! 打开前检查 url，只允许常见的协议，拒绝指向可执行文件的 file://
+
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    process::Command,
+    thread,
+};
+
+/// 允许打开的协议
+const SCHEMES: &[&str] = &["http", "https", "ftp", "ftps", "mailto", "file"];
+/// 视为可执行文件的扩展名，不区分大小写
+const EXECUTABLE: &[&str] = &[
+    "exe", "com", "bat", "cmd", "msi", "scr", "pif", "cpl", "lnk", "ps1", "vbs", "vbe", "js",
+    "jse", "wsf", "wsh", "hta", "reg", "jar", "sh", "bash", "command", "app", "desktop", "run",
+    "appimage",
+];
+
+/// 打开的方式
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Action {
+    /// 默认浏览器打开 url
+    Browser,
+    /// 在文件管理器中显示
+    Reveal,
+    /// 使用配置的编辑器打开
+    Editor,
+}
+
+fn opener() -> &'static str {
+    if cfg!(target_os = "macos") {
+        "open"
+    } else if cfg!(target_os = "windows") {
+        "explorer"
+    } else {
+        "xdg-open"
+    }
+}
+
+/// 启动后在新线程中等待退出，避免留下僵尸进程
+fn spawn<S: AsRef<OsStr>>(cmd: &str, args: &[S]) -> Result<(), String> {
+    let mut child = Command::new(cmd)
+        .args(args)
+        .spawn()
+        .map_err(|e| format!("{} {}", cmd, e))?;
+    thread::spawn(move || {
+        let _ = child.wait();
+    });
+    Ok(())
+}
+
+/// 检查 url 是否可以打开
+pub(crate) fn check_url(url: &str) -> Result<(), String> {
+    if url.starts_with('-') || url.chars().any(|c| c.is_control() || c.is_whitespace()) {
+        return Err("refuse to open malformed url".to_string());
+    }
+    let Some((scheme, rest)) = url.split_once(':') else {
+        return Err("url has no scheme".to_string());
+    };
+    let scheme = scheme.to_ascii_lowercase();
+    if !SCHEMES.contains(&scheme.as_str()) {
+        return Err(format!("refuse to open {}: url", scheme));
+    }
+    if scheme == "file" {
+        let path = file_path(rest).ok_or("malformed file url")?;
+        if is_executable(&path) {
+            return Err(format!("refuse to open executable {}", path.display()));
+        }
+    }
+    Ok(())
+}
+
+/// file: 后面的部分转为本地路径
+fn file_path(rest: &str) -> Option<PathBuf> {
+    let rest = rest.strip_prefix("//")?;
+    // 只支持本机
+    let rest = rest.strip_prefix("localhost").unwrap_or(rest);
+    if !rest.starts_with('/') {
+        return None;
+    }
+    let path = percent_decode(rest)?;
+    // Windows 下是 /C:/...
+    let bytes = path.as_bytes();
+    if bytes.len() >= 3 && bytes[2] == b':' && bytes[1].is_ascii_alphabetic() {
+        return Some(PathBuf::from(&path[1..]));
+    }
+    Some(PathBuf::from(path))
+}
+
+fn percent_decode(s: &str) -> Option<String> {
+    let mut res = Vec::with_capacity(s.len());
+    let mut bytes = s.bytes();
+    while let Some(b) = bytes.next() {
+        if b == b'%' {
+            let hex = [bytes.next()?, bytes.next()?];
+            res.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            res.push(b);
+        }
+    }
+    String::from_utf8(res).ok()
+}
+
+/// 扩展名在列表中，或者 unix 下有执行权限的文件
+fn is_executable(path: &Path) -> bool {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+    if ext.is_some_and(|e| EXECUTABLE.contains(&e.as_str())) {
+        return true;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if let Ok(m) = std::fs::metadata(path)
+            && m.is_file()
+            && m.permissions().mode() & 0o111 != 0
+        {
+            return true;
+        }
+    }
+    false
+}
+
+/// 使用默认浏览器打开
+pub(crate) fn url(url: &str) -> Result<(), String> {
+    check_url(url)?;
+    s_info!("open {}", url);
+    spawn(opener(), &[url])
+}
+
+/// 在文件管理器中显示，Linux 下打开所在的目录
+pub(crate) fn reveal(path: &Path) -> Result<(), String> {
+    if !path.exists() {
+        return Err(format!("{} does not exist", path.display()));
+    }
+    if cfg!(target_os = "macos") {
+        return spawn("open", &[OsStr::new("-R"), path.as_os_str()]);
+    }
+    if cfg!(target_os = "windows") {
+        let mut arg = std::ffi::OsString::from("/select,");
+        arg.push(path);
+        return spawn("explorer", &[arg]);
+    }
+    let dir = if path.is_dir() {
+        path
+    } else {
+        path.parent().unwrap_or(path)
+    };
+    spawn("xdg-open", &[dir])
+}
+
+/// 使用编辑器打开，编辑器为空时使用默认程序，此时拒绝可执行文件
+pub(crate) fn edit(path: &Path, editor: &str) -> Result<(), String> {
+    if !path.exists() {
+        return Err(format!("{} does not exist", path.display()));
+    }
+    let mut parts = editor.split_whitespace();
+    match parts.next() {
+        Some(cmd) => {
+            let mut args: Vec<&OsStr> = parts.map(OsStr::new).collect();
+            args.push(path.as_os_str());
+            spawn(cmd, &args)
+        }
+        None => {
+            if is_executable(path) {
+                return Err(format!("refuse to open executable {}", path.display()));
+            }
+            spawn(opener(), &[path])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guard() {
+        for url in [
+            "https://example.com/a?b=1",
+            "http://localhost:8080",
+            "mailto:someone@example.com",
+            "FTP://files.example.com",
+            "file:///tmp/notes%20a.txt",
+        ] {
+            assert!(check_url(url).is_ok(), "{}", url);
+        }
+        for url in [
+            "javascript:alert(1)",
+            "JavaScript:alert(1)",
+            "vbscript:msgbox",
+            "data:text/html,<script>",
+            "ssh://host",
+            "example.com",
+            "-https://example.com",
+            "https://example.com/a b",
+            "file:///tmp/setup.exe",
+            "file:///C:/Windows/system32/cmd.EXE",
+            "file:///home/u/run.sh",
+            "file://other-host/share/a.txt",
+        ] {
+            assert!(check_url(url).is_err(), "{}", url);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn executable_bit() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = crate::testdir::TempDir::new("open");
+        let path = dir.join("script");
+        std::fs::write(&path, "#!/bin/sh\n").unwrap();
+        let url = format!("file://{}", path.display());
+        assert!(check_url(&url).is_ok());
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(check_url(&url).is_err());
+    }
+}
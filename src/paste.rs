@@ -0,0 +1,151 @@
+//! 自动粘贴
+//!
+//! 显示窗口前记录当前焦点窗口，隐藏后恢复焦点并模拟粘贴按键。
+//! X11 使用 xdotool（XTest），Wayland 使用 wtype（virtual keyboard 协议），
+//! macOS 使用 osascript，Windows 不支持。
+//! Wayland 下无法获取其他程序的窗口，隐藏后由合成器恢复焦点，
+//! 也无法得到窗口类名，按窗口覆盖粘贴按键不会生效，设置中会说明
+
+use std::{process::Command, thread, time::Duration};
+
+/// 之前的焦点窗口
+#[derive(Clone, Default)]
+pub(crate) struct Target {
+    /// X11 的窗口id 或 macOS 的应用名
+    window: Option<String>,
+    /// 窗口类名，用于选择粘贴按键
+    pub class: Option<String>,
+}
+
+fn output(cmd: &str, args: &[&str]) -> Option<String> {
+    let out = Command::new(cmd).args(args).output().ok()?;
+    if !out.status.success() {
+        return None;
+    }
+    let s = String::from_utf8_lossy(&out.stdout).trim().to_string();
+    (!s.is_empty()).then_some(s)
+}
+
+fn is_wayland() -> bool {
+    std::env::var_os("WAYLAND_DISPLAY").is_some()
+}
+
+/// 当前平台不支持自动粘贴时返回原因
+pub(crate) fn unsupported() -> Option<&'static str> {
+    cfg!(target_os = "windows").then_some("auto paste is not supported on windows")
+}
+
+/// 能否得到焦点窗口的类名，不能时 `paste_overrides` 不会生效
+pub(crate) fn has_window_class() -> bool {
+    !(cfg!(target_os = "linux") && is_wayland())
+}
+
+/// 获取当前焦点窗口
+pub(crate) fn active_window() -> Target {
+    if cfg!(target_os = "macos") {
+        let app = output(
+            "osascript",
+            &[
+                "-e",
+                "tell application \"System Events\" to get name of first process whose frontmost is true",
+            ],
+        );
+        return Target {
+            class: app.clone(),
+            window: app,
+        };
+    }
+    if cfg!(target_os = "linux") && !is_wayland() {
+        let window = output("xdotool", &["getactivewindow"]);
+        let class = window
+            .as_deref()
+            .and_then(|w| output("xdotool", &["getwindowclassname", w]));
+        return Target { window, class };
+    }
+    // Wayland 下无法获取其他窗口，隐藏后由合成器把焦点还给之前的窗口
+    Target::default()
+}
+
+/// 隐藏窗口后调用，恢复焦点并发送按键，例如 ctrl+shift+v
+pub(crate) fn paste(target: Target, keys: String) {
+    thread::spawn(move || {
+        // 等待窗口隐藏
+        thread::sleep(Duration::from_millis(150));
+        if let Err(e) = do_paste(&target, &keys) {
+            s_error!("paste fail {}", e);
+        }
+    });
+}
+
+fn run(cmd: &str, args: &[&str]) -> Result<(), String> {
+    let status = Command::new(cmd)
+        .args(args)
+        .status()
+        .map_err(|e| format!("{} {}", cmd, e))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("{} exit {}", cmd, status))
+    }
+}
+
+fn do_paste(target: &Target, keys: &str) -> Result<(), String> {
+    if let Some(reason) = unsupported() {
+        return Err(reason.to_string());
+    }
+    let parts: Vec<&str> = keys.split('+').map(|k| k.trim()).collect();
+    let (key, modifiers) = parts.split_last().ok_or("empty paste keys")?;
+
+    if cfg!(target_os = "macos") {
+        // 应用名和按键作为参数传入，不拼接到脚本中
+        if let Some(app) = &target.window {
+            run(
+                "osascript",
+                &[
+                    "-e",
+                    "on run argv",
+                    "-e",
+                    "tell application (item 1 of argv) to activate",
+                    "-e",
+                    "end run",
+                    app,
+                ],
+            )?;
+        }
+        let using = modifiers
+            .iter()
+            .map(|m| match *m {
+                "cmd" | "super" => Ok("command down"),
+                "ctrl" | "control" => Ok("control down"),
+                "alt" | "option" => Ok("option down"),
+                "shift" => Ok("shift down"),
+                m => Err(format!("unknown modifier {}", m)),
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        let script = format!(
+            "tell application \"System Events\" to keystroke (item 1 of argv) using {{{}}}",
+            using.join(", ")
+        );
+        return run(
+            "osascript",
+            &["-e", "on run argv", "-e", &script, "-e", "end run", key],
+        );
+    }
+
+    if is_wayland() {
+        let mut args = Vec::new();
+        for m in modifiers {
+            args.extend(["-M", m]);
+        }
+        args.extend(["-k", key]);
+        for m in modifiers.iter().rev() {
+            args.extend(["-m", m]);
+        }
+        return run("wtype", &args);
+    }
+
+    if let Some(w) = &target.window {
+        run("xdotool", &["windowactivate", "--sync", w])?;
+    }
+    run("xdotool", &["key", "--clearmodifiers", keys])
+}
@@ -0,0 +1,498 @@
+//! 历史记录持久化
+//!
+//! 每条记录一行 json。历史记录文件中的图片只保存 blob 的引用，
+//! 导出和同步时图片使用 base64 编码
+
+use std::{
+    collections::HashSet,
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Clip,
+    blob::{Image, Reader},
+    store::{Entry, Oversize, Push, Stats, Store},
+};
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub(crate) enum Content {
+    Text {
+        text: String,
+    },
+    Img {
+        data: String,
+    },
+    /// 保存在数据目录中的图片
+    Blob {
+        blob: String,
+        size: usize,
+        width: u32,
+        height: u32,
+    },
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct Record {
+    #[serde(flatten)]
+    pub content: Content,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub revisions: Vec<String>,
+    /// 复制的时间戳，旧文件中没有
+    #[serde(default)]
+    pub time: u64,
+    /// Lamport 时钟，时间相同时用于排序，保存在历史记录文件中，重启后继续递增
+    #[serde(default, skip_serializing_if = "is_zero_u64")]
+    pub clock: u64,
+    /// 再次复制的次数
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub copies: u32,
+    /// 图片中识别出的文字
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ocr: Option<String>,
+}
+
+fn is_zero(v: &u32) -> bool {
+    *v == 0
+}
+
+fn is_zero_u64(v: &u64) -> bool {
+    *v == 0
+}
+
+impl Record {
+    pub fn new(clip: Clip, time: u64) -> Record {
+        let content = match clip {
+            Clip::Img(d) => Content::Img {
+                data: STANDARD.encode(d),
+            },
+            Clip::Text(text) => Content::Text { text },
+            Clip::Quit => Content::Text {
+                text: String::new(),
+            },
+        };
+        Record {
+            content,
+            revisions: Vec::new(),
+            time,
+            clock: 0,
+            copies: 0,
+            ocr: None,
+        }
+    }
+
+    /// 完整的记录，图片从 blob 中读取，用于导出和同步
+    pub fn from_entry(e: &Entry, store: &Store) -> Option<Record> {
+        let content = match (&e.clip, &e.image) {
+            (_, Some(_)) => match store.load(e.id) {
+                Ok(Clip::Img(d)) => Content::Img {
+                    data: STANDARD.encode(d),
+                },
+                Ok(_) => return None,
+                Err(err) => {
+                    s_error!("load image {} fail {}", e.id, err);
+                    return None;
+                }
+            },
+            (Clip::Text(t), None) => Content::Text { text: t.clone() },
+            _ => return None,
+        };
+        Some(with_meta(e, content))
+    }
+
+    /// 同步使用的记录，图片保存在磁盘上时只带引用，发送时再用 resolve 读取，
+    /// 避免等待发送的记录占用内存
+    pub fn lazy(e: &Entry, store: &Store) -> Option<Record> {
+        match store.blob_reader() {
+            Some(_) => Record::reference(e),
+            None => Record::from_entry(e, store),
+        }
+    }
+
+    /// 把图片引用替换成图片数据
+    pub fn resolve(self, reader: Option<&Reader>) -> Result<Record, String> {
+        let Content::Blob { blob, .. } = &self.content else {
+            return Ok(self);
+        };
+        let data = reader.ok_or("no blob dir")?.get(blob)?;
+        Ok(Record {
+            content: Content::Img {
+                data: STANDARD.encode(data),
+            },
+            ..self
+        })
+    }
+
+    /// 历史记录文件中的记录，图片只保存引用
+    fn reference(e: &Entry) -> Option<Record> {
+        let content = match (&e.clip, &e.image) {
+            (_, Some(img)) => Content::Blob {
+                blob: img.hash.clone(),
+                size: img.size,
+                width: img.width,
+                height: img.height,
+            },
+            (Clip::Text(t), None) => Content::Text { text: t.clone() },
+            _ => return None,
+        };
+        Some(with_meta(e, content))
+    }
+
+    pub fn into_clip(self) -> Result<(Clip, Vec<String>), String> {
+        let clip = match self.content {
+            Content::Text { text } => Clip::Text(text),
+            Content::Img { data } => Clip::Img(STANDARD.decode(data).map_err(|e| e.to_string())?),
+            Content::Blob { blob, .. } => return Err(format!("image {} is not embedded", blob)),
+        };
+        Ok((clip, self.revisions))
+    }
+}
+
+/// 带上记录的元数据
+fn with_meta(e: &Entry, content: Content) -> Record {
+    Record {
+        content,
+        revisions: e.revisions.clone(),
+        time: e.time,
+        clock: e.clock,
+        copies: e.copies,
+        ocr: e.ocr.clone(),
+    }
+}
+
+/// 数据目录
+pub(crate) fn data_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|d| d.join("clip"))
+}
+
+/// 历史记录文件
+pub(crate) fn history_path() -> Option<PathBuf> {
+    data_dir().map(|d| d.join("history.jsonl"))
+}
+
+/// 图片 blob 目录
+pub(crate) fn blob_dir() -> Option<PathBuf> {
+    data_dir().map(|d| d.join("blobs"))
+}
+
+/// 当前用户私有的临时目录，不使用共享的系统临时目录，避免其他用户读取或者预先放置链接
+pub(crate) fn private_dir(name: &str) -> Result<PathBuf, String> {
+    let dir = dirs::cache_dir()
+        .map(|d| d.join("clip"))
+        .or_else(data_dir)
+        .ok_or("no cache dir")?
+        .join(name);
+    let mut builder = std::fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder.create(&dir).map_err(|e| e.to_string())?;
+    Ok(dir)
+}
+
+/// 创建新文件并写入，文件已经存在（包括链接）时失败
+pub(crate) fn write_new(path: &Path, data: &[u8]) -> Result<(), String> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options
+        .open(path)
+        .and_then(|mut f| f.write_all(data))
+        .map_err(|e| format!("{} {}", path.display(), e))
+}
+
+/// 原子写入只有当前用户可以读写的文件，用于包含密钥的配置，已有文件的权限也会被替换
+pub(crate) fn write_private(path: &Path, data: &[u8]) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let tmp = path.with_extension("tmp");
+    // 上次中断留下的临时文件权限可能不对，重新创建
+    let _ = std::fs::remove_file(&tmp);
+    write_new(&tmp, data)?;
+    std::fs::rename(&tmp, path).map_err(|e| format!("{} {}", path.display(), e))
+}
+
+/// 运行中的程序持有的锁，命令行修改历史记录文件前检查，进程退出时自动释放
+pub(crate) struct Lock {
+    _file: std::fs::File,
+}
+
+/// 获取数据目录中的锁，其他进程已经持有时失败
+pub(crate) fn lock() -> Result<Lock, String> {
+    let dir = data_dir().ok_or("no data dir")?;
+    std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(dir.join("clip.lock"))
+        .map_err(|e| e.to_string())?;
+    match file.try_lock() {
+        Ok(()) => Ok(Lock { _file: file }),
+        Err(std::fs::TryLockError::WouldBlock) => Err("clip is running, quit it first".to_string()),
+        Err(std::fs::TryLockError::Error(e)) => Err(e.to_string()),
+    }
+}
+
+/// 统计文件
+pub(crate) fn stats_path() -> Option<PathBuf> {
+    data_dir().map(|d| d.join("stats.json"))
+}
+
+pub(crate) fn load_stats(path: &Path) -> Stats {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+pub(crate) fn save_stats(path: &Path, stats: &Stats) -> Result<(), String> {
+    let s = serde_json::to_string(stats).map_err(|e| e.to_string())?;
+    write_atomic(path, s.as_bytes())
+}
+
+/// 写入整个文件，目录不存在时创建，先写临时文件再替换，避免写到一半时丢失原来的内容
+pub(crate) fn write_atomic(path: &Path, data: &[u8]) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, data)
+        .and_then(|_| std::fs::rename(&tmp, path))
+        .map_err(|e| format!("{} {}", path.display(), e))
+}
+
+/// 读取 json lines，返回记录和无法解析的行数
+pub(crate) fn read(reader: impl BufRead) -> Result<(Vec<Record>, usize), String> {
+    let mut res = Vec::new();
+    let mut invalid = 0;
+    for line in reader.lines() {
+        let line = line.map_err(|e| e.to_string())?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line) {
+            Ok(r) => res.push(r),
+            Err(e) => {
+                s_error!("skip history line {}", e);
+                invalid += 1;
+            }
+        }
+    }
+    Ok((res, invalid))
+}
+
+/// 写入 json lines，返回写入的数量
+pub(crate) fn write(
+    w: &mut impl Write,
+    records: impl Iterator<Item = Record>,
+) -> Result<usize, String> {
+    let mut n = 0;
+    for r in records {
+        serde_json::to_writer(&mut *w, &r).map_err(|e| e.to_string())?;
+        w.write_all(b"\n").map_err(|e| e.to_string())?;
+        n += 1;
+    }
+    Ok(n)
+}
+
+/// 读取记录，无法解析的行直接跳过
+pub(crate) fn load(path: &Path) -> Result<Vec<Record>, String> {
+    let f = std::fs::File::open(path).map_err(|e| e.to_string())?;
+    read(BufReader::new(f)).map(|(res, _)| res)
+}
+
+/// 需要保存的记录，图片只保存引用
+pub(crate) fn snapshot(store: &Store) -> Vec<Record> {
+    store.iter().filter_map(Record::reference).collect()
+}
+
+/// 保存所有记录，先写临时文件再替换，避免写到一半时丢失数据
+pub(crate) fn save(path: &Path, records: &[Record]) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let tmp = path.with_extension("tmp");
+    {
+        let mut f =
+            std::io::BufWriter::new(std::fs::File::create(&tmp).map_err(|e| e.to_string())?);
+        write(&mut f, records.iter().cloned())?;
+        f.flush().map_err(|e| e.to_string())?;
+    }
+    std::fs::rename(&tmp, path).map_err(|e| e.to_string())
+}
+
+/// 合并的结果
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct Report {
+    pub added: usize,
+    /// 内容已存在
+    pub duplicate: usize,
+    /// 内容已存在但历史版本不同，保留已有的
+    pub conflict: usize,
+    /// 超过大小上限
+    pub rejected: usize,
+    /// 已经满了并且比保留的记录都旧，没有加入
+    pub stale: usize,
+    /// 导入时剩余空间放不下，没有导入
+    pub skipped: usize,
+    /// 无法解析
+    pub invalid: usize,
+    /// 超过数量或大小上限被淘汰的旧记录
+    pub evicted: usize,
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "added {}, duplicate {} ({} conflict), rejected {}, stale {}, skipped {}, invalid {}, evicted {}",
+            self.added,
+            self.duplicate,
+            self.conflict,
+            self.rejected,
+            self.stale,
+            self.skipped,
+            self.invalid,
+            self.evicted
+        )
+    }
+}
+
+/// 合并到 store 中，按记录的时间和时钟插入到对应的位置，相同内容按去重规则跳过。
+/// 只会淘汰比合并的记录更旧的记录
+pub(crate) fn merge(store: &mut Store, records: Vec<Record>) -> Report {
+    let mut report = Report::default();
+    let before = store.len();
+    let now = crate::time::now();
+    for r in records {
+        // 旧文件中没有时间，使用加入的时间
+        let time = if r.time > 0 { r.time } else { now };
+        let (clock, copies, ocr) = (r.clock, r.copies, r.ocr.clone());
+        let pushed = match &r.content {
+            Content::Blob {
+                blob,
+                size,
+                width,
+                height,
+            } => store
+                .insert_blob(
+                    Image {
+                        hash: blob.clone(),
+                        size: *size,
+                        width: *width,
+                        height: *height,
+                    },
+                    time,
+                    clock,
+                )
+                .map(|p| (p, r.revisions)),
+            _ => r
+                .into_clip()
+                .map(|(clip, revisions)| (store.insert(clip, time, clock), revisions)),
+        };
+        let (push, revisions) = match pushed {
+            Ok(v) => v,
+            Err(e) => {
+                s_error!("skip history record {}", e);
+                report.invalid += 1;
+                continue;
+            }
+        };
+        match push {
+            Push::Added(id) => {
+                report.added += 1;
+                if !revisions.is_empty() {
+                    store.set_revisions(id, revisions);
+                }
+                if copies > 0 {
+                    store.set_copies(id, copies);
+                }
+                if let Some(ocr) = ocr {
+                    store.set_ocr(id, ocr);
+                }
+            }
+            Push::Duplicate(id) => {
+                report.duplicate += 1;
+                if !revisions.is_empty() && store.get(id).is_some_and(|e| e.revisions != revisions)
+                {
+                    report.conflict += 1;
+                }
+                // 其他设备上识别过的文字
+                if let Some(ocr) = ocr
+                    && store.get(id).is_some_and(|e| e.ocr.is_none())
+                {
+                    store.set_ocr(id, ocr);
+                }
+            }
+            Push::Rejected => report.rejected += 1,
+            Push::Stale => report.stale += 1,
+        }
+    }
+    report.evicted = (before + report.added).saturating_sub(store.len());
+    report
+}
+
+/// 导入其他文件中的记录。只导入剩余空间放得下的最新的记录，不会淘汰已有的记录，
+/// 放不下的更旧的记录跳过并计入 skipped。没有时间的记录按文件中的顺序，后面的较新
+pub(crate) fn import(store: &mut Store, records: Vec<Record>) -> Report {
+    let mut records: Vec<(usize, Record)> = records.into_iter().enumerate().collect();
+    // 从新到旧
+    records.sort_by_key(|(i, r)| std::cmp::Reverse((r.time, *i)));
+    let limits = *store.limits();
+    let (mut entries, mut bytes) = (store.len(), store.bytes());
+    let mut seen = HashSet::new();
+    let mut keep = Vec::new();
+    let mut skipped = 0;
+    for (_, r) in records {
+        let size = match &r.content {
+            Content::Text { text } => {
+                if !seen.insert(text.clone()) || store.find(&Clip::Text(text.clone())).is_some() {
+                    keep.push(r);
+                    continue;
+                }
+                text.len()
+            }
+            Content::Img { data } => match STANDARD.decode(data).map(Clip::Img) {
+                Ok(clip) if store.find(&clip).is_none() => data.len() / 4 * 3,
+                _ => {
+                    keep.push(r);
+                    continue;
+                }
+            },
+            Content::Blob { size, .. } => *size,
+        };
+        // 合并时拒绝
+        if size > limits.max_entry_bytes && limits.oversize == Oversize::Reject {
+            keep.push(r);
+            continue;
+        }
+        let size =
+            size.min(limits.max_entry_bytes) + r.revisions.iter().map(|r| r.len()).sum::<usize>();
+        // 放不下时更旧的记录也不再导入
+        if skipped > 0 || entries >= limits.max_entries || bytes + size > limits.max_bytes {
+            skipped += 1;
+            continue;
+        }
+        entries += 1;
+        bytes += size;
+        keep.push(r);
+    }
+    keep.reverse();
+    let mut report = merge(store, keep);
+    report.skipped = skipped;
+    report
+}
+
+/// 加载到 store 中
+pub(crate) fn restore(path: &Path, store: &mut Store) -> Result<(), String> {
+    let report = merge(store, load(path)?);
+    s_info!("restore history {}", report);
+    Ok(())
+}
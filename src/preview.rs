@@ -0,0 +1,254 @@
+//! 预览面板
+//!
+//! 显示完整内容，带行号，按检测到的语言做语法高亮
+
+use std::sync::Arc;
+
+use eframe::egui::{self, Color32, TextFormat, text::LayoutJob};
+use egui_extras::syntax_highlighting::{self, CodeTheme};
+
+/// 超过这个大小只显示开头部分
+const SHOW_LIMIT: usize = 1024 * 1024;
+/// 超过这个大小不做高亮，避免卡顿
+const HIGHLIGHT_LIMIT: usize = 256 * 1024;
+
+#[derive(Default)]
+pub(crate) struct Pane {
+    /// 是否自动换行
+    pub wrap: bool,
+    /// (记录id, store 版本, 深色主题, 语言, 排版结果)
+    cache: Option<(u64, u64, bool, &'static str, Arc<LayoutJob>)>,
+}
+
+impl Pane {
+    /// 显示文本内容，version 为 store 的版本，内容被编辑后版本会变化
+    pub fn show_text(&mut self, ui: &mut egui::Ui, id: u64, version: u64, text: &str) {
+        let dark = ui.visuals().dark_mode;
+        let (language, job) = match &self.cache {
+            Some((i, v, d, language, job)) if *i == id && *v == version && *d == dark => {
+                (*language, job.clone())
+            }
+            _ => {
+                let language = detect_language(head(text, SHOW_LIMIT));
+                let job = Arc::new(layout(ui, text, language));
+                self.cache = Some((id, version, dark, language, job.clone()));
+                (language, job)
+            }
+        };
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.wrap, "wrap");
+            if !language.is_empty() {
+                ui.weak(language);
+            }
+            if text.len() > SHOW_LIMIT {
+                ui.weak(format!("showing first {}", crate::human_size(SHOW_LIMIT)));
+            }
+        });
+        egui::ScrollArea::both().auto_shrink(false).show(ui, |ui| {
+            ui.add(
+                egui::Label::new(job)
+                    .selectable(true)
+                    .wrap_mode(if self.wrap {
+                        egui::TextWrapMode::Wrap
+                    } else {
+                        egui::TextWrapMode::Extend
+                    }),
+            );
+        });
+    }
+}
+
+/// 高亮并加上行号
+fn layout(ui: &egui::Ui, text: &str, language: &str) -> LayoutJob {
+    let text = head(text, SHOW_LIMIT);
+    let font_id = egui::TextStyle::Monospace.resolve(ui.style());
+    let job = if text.len() > HIGHLIGHT_LIMIT || language.is_empty() {
+        LayoutJob::single_section(
+            text.to_string(),
+            TextFormat::simple(font_id.clone(), ui.visuals().text_color()),
+        )
+    } else if language == "log" {
+        log_job(ui, text, &font_id)
+    } else {
+        let theme = CodeTheme::from_memory(ui.ctx(), ui.style());
+        syntax_highlighting::highlight(ui.ctx(), ui.style(), &theme, text, language)
+    };
+    with_line_numbers(job, &font_id, ui.visuals().weak_text_color())
+}
+
+/// 在字符边界处截取开头
+fn head(text: &str, max: usize) -> &str {
+    if text.len() <= max {
+        return text;
+    }
+    let mut end = max;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+/// 每行开头插入行号
+fn with_line_numbers(job: LayoutJob, font_id: &egui::FontId, color: Color32) -> LayoutJob {
+    let lines = job.text.lines().count().max(1);
+    let width = lines.to_string().len();
+    let number = TextFormat::simple(font_id.clone(), color);
+
+    let mut res = LayoutJob::default();
+    let mut line = 0;
+    let mut line_start = true;
+    for section in &job.sections {
+        for piece in job.text[section.byte_range.clone()].split_inclusive('\n') {
+            if line_start {
+                line += 1;
+                res.append(&format!("{:>width$}  ", line), 0.0, number.clone());
+            }
+            res.append(piece, 0.0, section.format.clone());
+            line_start = piece.ends_with('\n');
+        }
+    }
+    res
+}
+
+/// 日志按级别着色
+fn log_job(ui: &egui::Ui, text: &str, font_id: &egui::FontId) -> LayoutJob {
+    let mut job = LayoutJob::default();
+    for line in text.split_inclusive('\n') {
+        let color = if line.contains("ERROR") {
+            ui.visuals().error_fg_color
+        } else if line.contains("WARN") {
+            ui.visuals().warn_fg_color
+        } else if line.contains("DEBUG") || line.contains("TRACE") {
+            ui.visuals().weak_text_color()
+        } else {
+            ui.visuals().text_color()
+        };
+        job.append(line, 0.0, TextFormat::simple(font_id.clone(), color));
+    }
+    job
+}
+
+/// 根据内容猜测语言，返回 syntect 能识别的扩展名，无法识别返回空字符串
+pub(crate) fn detect_language(text: &str) -> &'static str {
+    let trimmed = text.trim();
+    let lines: Vec<&str> = trimmed
+        .lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty())
+        .take(50)
+        .collect();
+    let count = |f: &dyn Fn(&str) -> bool| lines.iter().filter(|l| f(l)).count();
+    let lower = head(trimmed, 64).to_ascii_lowercase();
+
+    if (trimmed.starts_with('{') && trimmed.ends_with('}'))
+        || (trimmed.starts_with('[') && trimmed.ends_with(']') && trimmed.contains(','))
+    {
+        return "json";
+    }
+    if lower.starts_with("<!doctype html") || lower.starts_with("<html") {
+        return "html";
+    }
+    if trimmed.starts_with('<') && trimmed.ends_with('>') {
+        return "xml";
+    }
+    if let Some(first) = lines.first()
+        && first.starts_with("#!")
+    {
+        return if first.contains("python") { "py" } else { "sh" };
+    }
+    let levels = ["ERROR", "WARN", "INFO", "DEBUG", "TRACE"];
+    if count(&|l| levels.iter().any(|v| l.contains(v))) * 2 > lines.len() {
+        return "log";
+    }
+    if [
+        "select ",
+        "insert into",
+        "update ",
+        "delete from",
+        "create table",
+    ]
+    .iter()
+    .any(|k| lower.starts_with(k))
+    {
+        return "sql";
+    }
+    if count(&|l| {
+        l.starts_with("fn ")
+            || l.starts_with("pub ")
+            || l.starts_with("impl ")
+            || l.starts_with("let mut ")
+            || (l.starts_with("use ") && l.contains("::"))
+    }) > 0
+    {
+        return "rs";
+    }
+    if count(&|l| {
+        l.starts_with("def ")
+            || l.starts_with("elif ")
+            || (l.starts_with("from ") && l.contains(" import "))
+            || (l.starts_with("class ") && l.ends_with(':'))
+    }) > 0
+    {
+        return "py";
+    }
+    if count(&|l| {
+        l.starts_with("function ")
+            || l.starts_with("const ")
+            || l.contains("=>")
+            || l.contains("console.")
+    }) > 0
+    {
+        return "js";
+    }
+    if count(&|l| l.starts_with('[') && l.ends_with(']')) > 0 && count(&|l| l.contains(" = ")) > 0 {
+        return "toml";
+    }
+    if lines.len() > 1
+        && count(&|l| l.starts_with("- ") || (l.contains(": ") && !l.contains(';'))) == lines.len()
+    {
+        return "yaml";
+    }
+    if count(&|l| l.starts_with("$ ") || l.starts_with("sudo ") || l.starts_with("cd ")) > 0 {
+        return "sh";
+    }
+    ""
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn languages() {
+        for (text, expect) in [
+            ("{\"a\": 1}", "json"),
+            ("[1, 2]", "json"),
+            ("<!DOCTYPE html>\n<html></html>", "html"),
+            ("<note><to>a</to></note>", "xml"),
+            ("#!/usr/bin/env python3\nprint(1)", "py"),
+            ("#!/bin/bash\necho hi", "sh"),
+            (
+                "2024-01-01 INFO start\n2024-01-01 ERROR boom\nnext line",
+                "log",
+            ),
+            ("SELECT * FROM t WHERE id = 1", "sql"),
+            ("use std::io::Read;\nfn main() {}", "rs"),
+            ("def f(x):\n    return x", "py"),
+            ("const a = 1;\nconsole.log(a)", "js"),
+            ("[package]\nname = \"clip\"", "toml"),
+            ("name: clip\nversion: 1", "yaml"),
+            ("$ cargo build", "sh"),
+            ("hello world", ""),
+            ("", ""),
+        ] {
+            assert_eq!(detect_language(text), expect, "{}", text);
+        }
+    }
+
+    #[test]
+    fn head_on_char_boundary() {
+        assert_eq!(head("ab中文", 3), "ab");
+        assert_eq!(head("ab中文", 5), "ab中");
+        assert_eq!(head("abc", 10), "abc");
+    }
+}
@@ -0,0 +1,203 @@
+//! 二维码
+//!
+//! 把文本记录编码成二维码显示在弹窗中，可以复制为图片记录
+
+use eframe::egui;
+use qrcode::{Color, EcLevel, QrCode};
+
+/// 四周留白的模块数
+const QUIET: usize = 4;
+/// 复制为图片时每个模块的像素数
+const PNG_SCALE: usize = 8;
+
+/// 纠错等级
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Level {
+    L,
+    M,
+    Q,
+    H,
+}
+
+impl Level {
+    pub const ALL: [Level; 4] = [Level::L, Level::M, Level::Q, Level::H];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Level::L => "L 7%",
+            Level::M => "M 15%",
+            Level::Q => "Q 25%",
+            Level::H => "H 30%",
+        }
+    }
+
+    /// 版本 40 按字节编码时的容量
+    pub fn capacity(&self) -> usize {
+        match self {
+            Level::L => 2953,
+            Level::M => 2331,
+            Level::Q => 1663,
+            Level::H => 1273,
+        }
+    }
+
+    fn ec(&self) -> EcLevel {
+        match self {
+            Level::L => EcLevel::L,
+            Level::M => EcLevel::M,
+            Level::Q => EcLevel::Q,
+            Level::H => EcLevel::H,
+        }
+    }
+}
+
+/// 编码结果，按行保存每个模块是否为深色
+pub(crate) struct Qr {
+    width: usize,
+    dark: Vec<bool>,
+}
+
+pub(crate) fn encode(text: &str, level: Level) -> Result<Qr, String> {
+    if text.len() > level.capacity() {
+        return Err(format!(
+            "{} bytes is too large for a qr code, the limit is {} at level {}",
+            text.len(),
+            level.capacity(),
+            level.name()
+        ));
+    }
+    let code = QrCode::with_error_correction_level(text, level.ec()).map_err(|e| e.to_string())?;
+    Ok(Qr {
+        width: code.width(),
+        dark: code
+            .to_colors()
+            .into_iter()
+            .map(|c| c == Color::Dark)
+            .collect(),
+    })
+}
+
+impl Qr {
+    /// 带留白的灰度像素
+    fn pixels(&self, scale: usize) -> (usize, Vec<u8>) {
+        let size = (self.width + QUIET * 2) * scale;
+        let mut res = vec![255u8; size * size];
+        for y in 0..self.width {
+            for x in 0..self.width {
+                if !self.dark[y * self.width + x] {
+                    continue;
+                }
+                for dy in 0..scale {
+                    let row = ((y + QUIET) * scale + dy) * size;
+                    let start = row + (x + QUIET) * scale;
+                    res[start..start + scale].fill(0);
+                }
+            }
+        }
+        (size, res)
+    }
+
+    fn image(&self) -> egui::ColorImage {
+        let (size, pixels) = self.pixels(1);
+        egui::ColorImage::from_gray([size, size], &pixels)
+    }
+
+    /// 编码成 png，用于复制为图片记录
+    pub fn png(&self) -> Result<Vec<u8>, String> {
+        let (size, pixels) = self.pixels(PNG_SCALE);
+        let img = image::GrayImage::from_raw(size as u32, size as u32, pixels)
+            .ok_or("invalid qr image")?;
+        let mut res = Vec::new();
+        img.write_to(&mut std::io::Cursor::new(&mut res), image::ImageFormat::Png)
+            .map_err(|e| e.to_string())?;
+        Ok(res)
+    }
+}
+
+/// 二维码弹窗
+pub(crate) struct Popup {
+    text: String,
+    level: Level,
+    code: Result<Qr, String>,
+    texture: Option<egui::TextureHandle>,
+}
+
+impl Popup {
+    pub fn new(text: String) -> Self {
+        let level = Level::M;
+        Popup {
+            code: encode(&text, level),
+            text,
+            level,
+            texture: None,
+        }
+    }
+
+    /// 返回 (是否继续显示, 需要复制的 png)
+    pub fn show(&mut self, ctx: &egui::Context) -> (bool, Option<Vec<u8>>) {
+        let mut open = true;
+        let mut png = None;
+        egui::Window::new("qr code")
+            .collapsible(false)
+            .resizable(false)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                let level = self.level;
+                ui.horizontal(|ui| {
+                    ui.label("error correction");
+                    for l in Level::ALL {
+                        ui.radio_value(&mut self.level, l, l.name());
+                    }
+                });
+                if level != self.level {
+                    self.code = encode(&self.text, self.level);
+                    self.texture = None;
+                }
+                match &self.code {
+                    Ok(qr) => {
+                        let texture = self.texture.get_or_insert_with(|| {
+                            // 放大时保持模块边缘清晰
+                            ctx.load_texture("qr", qr.image(), egui::TextureOptions::NEAREST)
+                        });
+                        let size = texture.size_vec2();
+                        let scale = (240.0 / size.x).max(1.0).floor();
+                        ui.image((texture.id(), size * scale));
+                        if ui.button("copy as image").clicked() {
+                            png = Some(qr.png());
+                        }
+                    }
+                    Err(e) => {
+                        ui.colored_label(ui.visuals().error_fg_color, e);
+                    }
+                }
+            });
+        match png {
+            Some(Ok(png)) => (open, Some(png)),
+            Some(Err(e)) => {
+                self.code = Err(e);
+                (open, None)
+            }
+            None => (open, None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn size_limit() {
+        let qr = encode("https://example.com", Level::M).unwrap();
+        // 版本 2，25 个模块
+        assert_eq!(qr.width, 25);
+        let (size, pixels) = qr.pixels(2);
+        assert_eq!(size, (25 + QUIET * 2) * 2);
+        assert_eq!(pixels.len(), size * size);
+        assert!(qr.png().unwrap().starts_with(b"\x89PNG"));
+
+        let big = "a".repeat(Level::H.capacity() + 1);
+        assert!(matches!(encode(&big, Level::H), Err(e) if e.contains("too large")));
+        assert!(encode(&big, Level::L).is_ok());
+    }
+}
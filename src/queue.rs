@@ -0,0 +1,161 @@
+//! 粘贴队列
+//!
+//! 开启后复制的内容依次进入队列，剪切板中始终是队首；
+//! 每次松开粘贴键后弹出队首，把下一条写入剪切板
+
+use std::collections::VecDeque;
+
+#[derive(Default)]
+pub(crate) struct Queue {
+    on: bool,
+    items: VecDeque<String>,
+    /// 已经粘贴的数量
+    pasted: usize,
+    /// 自己写入剪切板但还没有捕获到的内容，按写入顺序
+    own: VecDeque<String>,
+    /// 按下了粘贴键，松开后切换到下一条
+    pasting: bool,
+}
+
+impl Queue {
+    pub fn is_on(&self) -> bool {
+        self.on
+    }
+
+    /// 开启或关闭，都会清空队列
+    pub fn set_on(&mut self, on: bool) {
+        *self = Queue {
+            on,
+            ..Default::default()
+        };
+    }
+
+    /// 记录写入剪切板的内容，返回需要写入的内容
+    fn write(&mut self, text: String) -> Option<String> {
+        self.own.push_back(text.clone());
+        Some(text)
+    }
+
+    /// 捕获到新内容，返回需要写回剪切板的队首
+    pub fn capture(&mut self, text: &str) -> Option<String> {
+        if !self.on {
+            return None;
+        }
+        if let Some(i) = self.own.iter().position(|o| o == text) {
+            // 连续的写入可能只产生一次变化，之前的写入不会再捕获到
+            self.own.drain(..=i);
+            return None;
+        }
+        // 用户复制了新内容，之前的写入已经被覆盖
+        self.own.clear();
+        self.items.push_back(text.to_string());
+        if self.items.len() > 1 {
+            let head = self.items.front().cloned()?;
+            return self.write(head);
+        }
+        None
+    }
+
+    /// 按下粘贴键，队列中有内容时返回 true
+    pub fn press(&mut self) -> bool {
+        self.pasting = self.on && !self.items.is_empty();
+        self.pasting
+    }
+
+    /// 松开粘贴键后调用，返回需要写入剪切板的下一条
+    pub fn release(&mut self) -> Option<String> {
+        if !std::mem::take(&mut self.pasting) || self.items.pop_front().is_none() {
+            return None;
+        }
+        self.pasted += 1;
+        let next = self.items.front().cloned()?;
+        self.write(next)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// (已粘贴, 总数)
+    pub fn progress(&self) -> (usize, usize) {
+        (self.pasted, self.pasted + self.items.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn on() -> Queue {
+        let mut q = Queue::default();
+        q.set_on(true);
+        q
+    }
+
+    #[test]
+    fn fifo() {
+        let mut q = on();
+        assert_eq!(q.capture("a"), None);
+        // 剪切板写回队首，写入产生的变化不会进入队列
+        assert_eq!(q.capture("b").as_deref(), Some("a"));
+        assert_eq!(q.capture("a"), None);
+        assert_eq!(q.capture("c").as_deref(), Some("a"));
+        assert_eq!(q.capture("a"), None);
+        assert_eq!(q.progress(), (0, 3));
+
+        // 只有按下后松开才切换
+        assert_eq!(q.release(), None);
+        assert!(q.press());
+        assert_eq!(q.release().as_deref(), Some("b"));
+        assert_eq!(q.capture("b"), None);
+        assert!(q.press());
+        assert_eq!(q.release().as_deref(), Some("c"));
+        assert!(q.press());
+        assert_eq!(q.release(), None);
+        assert!(q.is_empty() && !q.press());
+        assert_eq!(q.progress(), (3, 3));
+    }
+
+    #[test]
+    fn copy_same_as_head() {
+        let mut q = on();
+        q.capture("a");
+        assert_eq!(q.capture("b").as_deref(), Some("a"));
+        assert_eq!(q.capture("a"), None);
+        // 自己的写入已经捕获过，用户再复制相同的内容仍然进入队列
+        assert_eq!(q.capture("a").as_deref(), Some("a"));
+        assert_eq!(q.progress(), (0, 3));
+    }
+
+    #[test]
+    fn merged_writes() {
+        let mut q = on();
+        q.capture("a");
+        q.capture("b");
+        q.press();
+        q.release();
+        // 两次写入只捕获到最后一次
+        assert_eq!(q.capture("b"), None);
+        assert!(q.own.is_empty());
+        assert_eq!(q.progress(), (1, 2));
+
+        // 写入没有产生变化时，用户复制的新内容照常进入队列
+        let mut q = on();
+        q.capture("a");
+        q.capture("b");
+        assert_eq!(q.capture("c").as_deref(), Some("a"));
+        assert_eq!(q.own, ["a"]);
+        assert_eq!(q.progress(), (0, 3));
+    }
+
+    #[test]
+    fn off() {
+        let mut q = Queue::default();
+        assert_eq!(q.capture("a"), None);
+        assert!(q.is_empty() && !q.press());
+        let mut q = on();
+        q.capture("a");
+        q.set_on(false);
+        assert!(q.is_empty() && !q.is_on());
+    }
+}
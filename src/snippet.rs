@@ -0,0 +1,483 @@
+//! 片段库
+//!
+//! 和历史记录分开保存，支持名称、标签、目录，内容中可以使用占位符：
+//! `{date}` `{time}` `{datetime}` `{clipboard}` `{uuid}` `{input:提示}`。
+//! 其他大括号原样保留，json 等内容不需要转义，`\{` 表示字面量的 `{`。
+//! 历史记录中搜索时，名称、标签、目录或内容匹配的片段显示在列表上方
+
+use std::{collections::HashMap, path::PathBuf};
+
+use chacha20poly1305::aead::{OsRng, rand_core::RngCore};
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub(crate) struct Snippet {
+    pub name: String,
+    #[serde(default)]
+    pub folder: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub body: String,
+}
+
+impl Snippet {
+    fn matches(&self, query: &str) -> bool {
+        let query = query.to_lowercase();
+        self.name.to_lowercase().contains(&query)
+            || self.folder.to_lowercase().contains(&query)
+            || self.tags.iter().any(|t| t.to_lowercase().contains(&query))
+            || self.body.to_lowercase().contains(&query)
+    }
+}
+
+/// 模板中的一段
+enum Part<'a> {
+    Text(&'a str),
+    Placeholder(&'a str),
+}
+
+/// 拆分模板
+fn parse(body: &str) -> Vec<Part<'_>> {
+    let mut res = Vec::new();
+    let mut rest = body;
+    while !rest.is_empty() {
+        if let Some(r) = rest.strip_prefix("\\{") {
+            res.push(Part::Text("{"));
+            rest = r;
+        } else if let Some(name) = placeholder(rest) {
+            res.push(Part::Placeholder(name));
+            rest = &rest[name.len() + 2..];
+        } else {
+            let first = rest.chars().next().map(|c| c.len_utf8()).unwrap_or(1);
+            let end = rest[first..]
+                .find(['{', '\\'])
+                .map(|e| e + first)
+                .unwrap_or(rest.len());
+            res.push(Part::Text(&rest[..end]));
+            rest = &rest[end..];
+        }
+    }
+    res
+}
+
+/// 开头的占位符名称，只认识固定的几种，其他的大括号不是占位符
+fn placeholder(s: &str) -> Option<&str> {
+    let s = s.strip_prefix('{')?;
+    let end = s.find(['{', '}', '\n'])?;
+    let name = &s[..end];
+    if !s[end..].starts_with('}') {
+        return None;
+    }
+    match name {
+        "date" | "time" | "datetime" | "clipboard" | "uuid" => Some(name),
+        _ if name.starts_with("input:") => Some(name),
+        _ => None,
+    }
+}
+
+/// 需要用户输入的提示，去重后按出现顺序返回
+pub(crate) fn prompts(body: &str) -> Vec<String> {
+    let mut res: Vec<String> = Vec::new();
+    for p in parse(body) {
+        if let Part::Placeholder(p) = p
+            && let Some(label) = p.strip_prefix("input:")
+            && !res.iter().any(|r| r == label)
+        {
+            res.push(label.to_string());
+        }
+    }
+    res
+}
+
+/// 填充占位符，没有输入的占位符原样保留
+pub(crate) fn fill(
+    body: &str,
+    inputs: &HashMap<String, String>,
+    clipboard: &dyn Fn() -> String,
+) -> String {
+    let now = crate::time::time_format();
+    let mut res = String::with_capacity(body.len());
+    for p in parse(body) {
+        match p {
+            Part::Text(t) => res.push_str(t),
+            Part::Placeholder("date") => res.push_str(&now[..10]),
+            Part::Placeholder("time") => res.push_str(&now[11..]),
+            Part::Placeholder("datetime") => res.push_str(&now),
+            Part::Placeholder("clipboard") => res.push_str(&clipboard()),
+            Part::Placeholder("uuid") => res.push_str(&uuid_v4()),
+            Part::Placeholder(p) => match p.strip_prefix("input:").and_then(|l| inputs.get(l)) {
+                Some(v) => res.push_str(v),
+                None => {
+                    res.push('{');
+                    res.push_str(p);
+                    res.push('}');
+                }
+            },
+        }
+    }
+    res
+}
+
+/// 随机 uuid
+pub(crate) fn uuid_v4() -> String {
+    let mut bytes = [0u8; 16];
+    OsRng.fill_bytes(&mut bytes);
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// 历史记录中最多显示的匹配片段数量
+const MAX_MATCHES: usize = 5;
+
+/// 匹配的片段下标，查询为空时没有结果
+fn search(list: &[Snippet], query: &str) -> Vec<usize> {
+    if query.trim().is_empty() {
+        return Vec::new();
+    }
+    (0..list.len())
+        .filter(|i| list[*i].matches(query))
+        .collect()
+}
+
+fn library_path() -> Option<PathBuf> {
+    crate::persist::data_dir().map(|d| d.join("snippets.json"))
+}
+
+/// 片段库以及相关窗口的状态
+#[derive(Default)]
+pub(crate) struct Snippets {
+    pub list: Vec<Snippet>,
+    /// 编辑中的片段，下标为 None 表示新建，字符串是逗号分隔的标签
+    editing: Option<(Option<usize>, Snippet, String)>,
+    /// 等待用户输入的片段下标和输入内容
+    filling: Option<(usize, Vec<(String, String)>)>,
+}
+
+impl Snippets {
+    pub fn load() -> Self {
+        let list = library_path()
+            .and_then(|p| std::fs::read_to_string(p).ok())
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default();
+        Snippets {
+            list,
+            ..Default::default()
+        }
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let path = library_path().ok_or("no data dir")?;
+        let s = serde_json::to_string_pretty(&self.list).map_err(|e| e.to_string())?;
+        crate::persist::write_atomic(&path, s.as_bytes())
+    }
+
+    /// 打开编辑窗口新建片段
+    pub fn create(&mut self, body: String) {
+        self.editing = Some((
+            None,
+            Snippet {
+                body,
+                ..Default::default()
+            },
+            String::new(),
+        ));
+    }
+
+    /// 使用片段，有需要输入的占位符时先打开输入窗口
+    fn use_snippet(
+        &mut self,
+        index: usize,
+        clipboard: &dyn Fn() -> String,
+    ) -> Option<Result<String, String>> {
+        let body = &self.list.get(index)?.body;
+        let prompts = prompts(body);
+        if prompts.is_empty() {
+            Some(Ok(fill(body, &HashMap::new(), clipboard)))
+        } else {
+            self.filling = Some((
+                index,
+                prompts.into_iter().map(|p| (p, String::new())).collect(),
+            ));
+            None
+        }
+    }
+
+    /// 片段列表，返回需要写入剪切板的内容或者错误
+    pub fn show_list(
+        &mut self,
+        ui: &mut egui::Ui,
+        query: &str,
+        clipboard: &dyn Fn() -> String,
+    ) -> Option<Result<String, String>> {
+        let mut used = None;
+        let mut edit = None;
+        let mut removed = None;
+        if ui.button("new snippet").clicked() {
+            self.create(String::new());
+        }
+        let mut folders: Vec<&str> = self.list.iter().map(|s| s.folder.as_str()).collect();
+        folders.sort();
+        folders.dedup();
+        egui::ScrollArea::vertical()
+            .auto_shrink(false)
+            .show(ui, |ui| {
+                for folder in folders {
+                    let mut rows = |ui: &mut egui::Ui| {
+                        for (i, s) in self.list.iter().enumerate() {
+                            if s.folder != folder || (!query.is_empty() && !s.matches(query)) {
+                                continue;
+                            }
+                            ui.horizontal(|ui| {
+                                if ui.button("Use").clicked() {
+                                    used = Some(i);
+                                }
+                                if ui.link("edit").clicked() {
+                                    edit = Some(i);
+                                }
+                                if ui.link("del").clicked() {
+                                    removed = Some(i);
+                                }
+                                ui.label(&s.name)
+                                    .on_hover_text(crate::store::preview_text(&s.body));
+                                for t in &s.tags {
+                                    ui.weak(format!("#{}", t));
+                                }
+                            });
+                        }
+                    };
+                    if folder.is_empty() {
+                        rows(ui);
+                    } else {
+                        egui::CollapsingHeader::new(folder)
+                            .default_open(true)
+                            .show(ui, rows);
+                    }
+                }
+            });
+        if let Some(i) = edit {
+            let s = self.list[i].clone();
+            let tags = s.tags.join(", ");
+            self.editing = Some((Some(i), s, tags));
+        }
+        if let Some(i) = removed {
+            self.list.remove(i);
+            if let Err(e) = self.save() {
+                return Some(Err(e));
+            }
+        }
+        used.and_then(|i| self.use_snippet(i, clipboard))
+    }
+
+    /// 历史记录搜索时显示匹配的片段，返回需要写入剪切板的内容或者错误
+    pub fn show_matches(
+        &mut self,
+        ui: &mut egui::Ui,
+        query: &str,
+        clipboard: &dyn Fn() -> String,
+    ) -> Option<Result<String, String>> {
+        let found = search(&self.list, query);
+        if found.is_empty() {
+            return None;
+        }
+        let mut used = None;
+        for &i in found.iter().take(MAX_MATCHES) {
+            let s = &self.list[i];
+            ui.horizontal(|ui| {
+                if ui.button("Use").clicked() {
+                    used = Some(i);
+                }
+                ui.label(&s.name)
+                    .on_hover_text(crate::store::preview_text(&s.body));
+                if !s.folder.is_empty() {
+                    ui.weak(&s.folder);
+                }
+                for t in &s.tags {
+                    ui.weak(format!("#{}", t));
+                }
+            });
+        }
+        if found.len() > MAX_MATCHES {
+            ui.weak(format!("{} more in snippets", found.len() - MAX_MATCHES));
+        }
+        ui.separator();
+        used.and_then(|i| self.use_snippet(i, clipboard))
+    }
+
+    /// 编辑和输入窗口，返回需要写入剪切板的内容或者错误
+    pub fn show_windows(
+        &mut self,
+        ctx: &egui::Context,
+        clipboard: &dyn Fn() -> String,
+    ) -> Option<Result<String, String>> {
+        let mut res = None;
+        if let Some((index, snippet, tags)) = &mut self.editing {
+            let mut open = true;
+            let mut saved = false;
+            egui::Window::new("snippet")
+                .collapsible(false)
+                .open(&mut open)
+                .show(ctx, |ui| {
+                    egui::Grid::new("snippet_grid")
+                        .num_columns(2)
+                        .show(ui, |ui| {
+                            ui.label("name");
+                            ui.text_edit_singleline(&mut snippet.name);
+                            ui.end_row();
+                            ui.label("folder");
+                            ui.text_edit_singleline(&mut snippet.folder);
+                            ui.end_row();
+                            ui.label("tags");
+                            ui.text_edit_singleline(tags);
+                            ui.end_row();
+                        });
+                    ui.weak("{date} {time} {datetime} {clipboard} {uuid} {input:label}, \\{ for a literal {");
+                    egui::ScrollArea::vertical()
+                        .max_height(200.0)
+                        .show(ui, |ui| {
+                            ui.add(
+                                egui::TextEdit::multiline(&mut snippet.body)
+                                    .code_editor()
+                                    .desired_width(f32::INFINITY),
+                            );
+                        });
+                    if ui.button("save").clicked() {
+                        saved = true;
+                    }
+                });
+            if saved {
+                snippet.tags = tags
+                    .split(',')
+                    .map(|t| t.trim().to_string())
+                    .filter(|t| !t.is_empty())
+                    .collect();
+                if snippet.name.trim().is_empty() {
+                    snippet.name = crate::store::preview_text(&snippet.body)
+                        .lines()
+                        .next()
+                        .unwrap_or("untitled")
+                        .to_string();
+                }
+                let snippet = std::mem::take(snippet);
+                match index {
+                    Some(i) if *i < self.list.len() => self.list[*i] = snippet,
+                    _ => self.list.push(snippet),
+                }
+                if let Err(e) = self.save() {
+                    res = Some(Err(e));
+                }
+            }
+            if saved || !open {
+                self.editing = None;
+            }
+        }
+
+        if let Some((index, inputs)) = &mut self.filling {
+            let mut open = true;
+            let mut ok = false;
+            egui::Window::new("fill snippet")
+                .collapsible(false)
+                .open(&mut open)
+                .show(ctx, |ui| {
+                    egui::Grid::new("fill_grid").num_columns(2).show(ui, |ui| {
+                        for (label, value) in inputs.iter_mut() {
+                            ui.label(label.as_str());
+                            ui.text_edit_singleline(value);
+                            ui.end_row();
+                        }
+                    });
+                    if ui.button("ok").clicked() {
+                        ok = true;
+                    }
+                });
+            if ok && let Some(s) = self.list.get(*index) {
+                let inputs = inputs.iter().cloned().collect();
+                res = Some(Ok(fill(&s.body, &inputs, clipboard)));
+            }
+            if ok || !open {
+                self.filling = None;
+            }
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fill_with(body: &str, inputs: &[(&str, &str)]) -> String {
+        let inputs = inputs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        fill(body, &inputs, &|| "clip".to_string())
+    }
+
+    #[test]
+    fn placeholders() {
+        assert_eq!(
+            fill_with("hi {input:name}, {clipboard}!", &[("name", "bob")]),
+            "hi bob, clip!"
+        );
+        // 没有输入时原样保留
+        assert_eq!(fill_with("{input:name}", &[]), "{input:name}");
+        let date = fill_with("{date} {time}", &[]);
+        assert_eq!((date.len(), &date[4..5], &date[13..14]), (19, "-", ":"));
+        let uuid = fill_with("{uuid}", &[]);
+        assert_eq!((uuid.len(), &uuid[14..15]), (36, "4"));
+        assert_ne!(uuid, uuid_v4());
+    }
+
+    #[test]
+    fn literal_braces() {
+        let json = r#"{"a": {"b": [1, 2]}, "c": "{x}"}"#;
+        assert_eq!(fill_with(json, &[]), json);
+        assert_eq!(
+            fill_with(r#"{"user": "{input:user}"}"#, &[("user", "bob")]),
+            r#"{"user": "bob"}"#
+        );
+        assert_eq!(fill_with("\\{date} {", &[]), "{date} {");
+        assert_eq!(fill_with("a\\b {date", &[]), "a\\b {date");
+        assert_eq!(fill_with("中{文}", &[]), "中{文}");
+    }
+
+    #[test]
+    fn prompt_order() {
+        assert_eq!(
+            prompts("{input:b} {input:a} {input:b} \\{input:c} {\"input:d\": 1}"),
+            ["b", "a"]
+        );
+        assert!(prompts("{date} {uuid}").is_empty());
+    }
+
+    #[test]
+    fn search_fields() {
+        let snippet = |name: &str, folder: &str, tag: &str| Snippet {
+            name: name.to_string(),
+            folder: folder.to_string(),
+            tags: vec![tag.to_string()],
+            body: "body".to_string(),
+        };
+        let list = [
+            snippet("Reply", "mail", "work"),
+            snippet("sig", "Mail/Personal", "home"),
+            snippet("ticket", "jira", "Work"),
+        ];
+        assert_eq!(search(&list, "reply"), [0]);
+        assert_eq!(search(&list, "mail"), [0, 1]);
+        assert_eq!(search(&list, "WORK"), [0, 2]);
+        assert_eq!(search(&list, "BODY"), [0, 1, 2]);
+        assert!(search(&list, " ").is_empty());
+        assert!(search(&list, "none").is_empty());
+    }
+}
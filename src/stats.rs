@@ -0,0 +1,209 @@
+//! 统计面板
+//!
+//! 数据来自 store 中的计数，图表直接使用 painter 绘制
+
+use eframe::egui::{self, Color32, Pos2, Rect, Sense, Stroke, Vec2};
+
+use crate::{
+    Clip, human_size,
+    store::Store,
+    time::{day, day_display, now},
+};
+
+/// 图表显示的天数
+const DAYS: u64 = 30;
+/// 显示的重复复制最多的记录数
+const TOP: usize = 10;
+
+/// 按记录计算的部分，只在记录变化时重新计算
+struct Summary {
+    /// (类型, 数量, 字节数)
+    kinds: Vec<(&'static str, usize, usize)>,
+    /// (次数, 摘要)
+    top: Vec<(u32, String)>,
+}
+
+#[derive(Default)]
+pub(crate) struct Dashboard {
+    cache: Option<(u64, Summary)>,
+}
+
+fn summary(store: &Store) -> Summary {
+    let mut kinds: Vec<(&'static str, usize, usize)> = Vec::new();
+    for e in store.iter() {
+        let k = e.kind.name();
+        match kinds.iter_mut().find(|(n, _, _)| *n == k) {
+            Some(v) => {
+                v.1 += 1;
+                v.2 += e.size();
+            }
+            None => kinds.push((k, 1, e.size())),
+        }
+    }
+    kinds.sort_by_key(|k| std::cmp::Reverse(k.1));
+    let mut top: Vec<(u32, String)> = store
+        .iter()
+        .filter(|e| e.copies > 0)
+        .map(|e| {
+            let preview = match &e.clip {
+                Clip::Img(_) => "[image]".to_string(),
+                _ => e.preview.replace('\n', " "),
+            };
+            (e.copies, preview)
+        })
+        .collect();
+    top.sort_by_key(|t| std::cmp::Reverse(t.0));
+    top.truncate(TOP);
+    Summary { kinds, top }
+}
+
+impl Dashboard {
+    pub fn show(&mut self, ui: &mut egui::Ui, store: &Store) {
+        if self.cache.as_ref().map(|(v, _)| *v) != Some(store.version()) {
+            self.cache = Some((store.version(), summary(store)));
+        }
+        let Some((_, summary)) = &self.cache else {
+            return;
+        };
+        let stats = store.stats();
+        let limits = store.limits();
+        let today = day(now());
+        let days: Vec<(u64, u32, u32)> = (today + 1 - DAYS..=today)
+            .map(|d| {
+                (
+                    d,
+                    stats.added.get(&d).copied().unwrap_or(0),
+                    stats.evicted.get(&d).copied().unwrap_or(0),
+                )
+            })
+            .collect();
+
+        egui::ScrollArea::vertical()
+            .auto_shrink(false)
+            .show(ui, |ui| {
+                ui.heading("storage");
+                ui.add(
+                    egui::ProgressBar::new(store.len() as f32 / limits.max_entries as f32)
+                        .text(format!("{} / {} entries", store.len(), limits.max_entries)),
+                );
+                ui.add(
+                    egui::ProgressBar::new(store.bytes() as f32 / limits.max_bytes as f32).text(
+                        format!(
+                            "{} / {}",
+                            human_size(store.bytes()),
+                            human_size(limits.max_bytes)
+                        ),
+                    ),
+                );
+                ui.label(format!("evicted {} in total", stats.evicted_total()));
+
+                ui.add_space(8.0);
+                ui.heading(format!("last {} days", DAYS));
+                let added = ui.visuals().selection.bg_fill;
+                let evicted = ui.visuals().warn_fg_color;
+                ui.horizontal(|ui| {
+                    ui.colored_label(added, "■ copied");
+                    ui.colored_label(evicted, "— evicted");
+                });
+                chart(ui, &days, added, evicted);
+
+                ui.add_space(8.0);
+                ui.heading("content types");
+                let max = summary.kinds.first().map_or(1, |k| k.1);
+                for (k, count, bytes) in &summary.kinds {
+                    ui.horizontal(|ui| {
+                        ui.add_sized([60.0, 16.0], egui::Label::new(*k));
+                        let (rect, _) =
+                            ui.allocate_exact_size(Vec2::new(120.0, 12.0), Sense::hover());
+                        let w = rect.width() * *count as f32 / max as f32;
+                        ui.painter().rect_filled(
+                            Rect::from_min_size(rect.min, Vec2::new(w, rect.height())),
+                            2.0,
+                            added,
+                        );
+                        ui.label(format!("{} · {}", count, human_size(*bytes)));
+                    });
+                }
+
+                ui.add_space(8.0);
+                ui.heading("most re-copied");
+                if summary.top.is_empty() {
+                    ui.weak("nothing yet");
+                }
+                for (copies, preview) in &summary.top {
+                    ui.horizontal(|ui| {
+                        ui.strong(format!("×{}", copies + 1));
+                        ui.add(egui::Label::new(preview).truncate());
+                    });
+                }
+            });
+    }
+}
+
+/// 柱状图加折线图，(天, 柱, 线)
+fn chart(ui: &mut egui::Ui, days: &[(u64, u32, u32)], bar: Color32, line: Color32) {
+    let size = Vec2::new(ui.available_width(), 120.0);
+    let (rect, resp) = ui.allocate_exact_size(size, Sense::hover());
+    let painter = ui.painter_at(rect);
+    let text = ui.visuals().weak_text_color();
+    let font = egui::FontId::proportional(10.0);
+    // 留出底部的日期
+    let plot = Rect::from_min_max(rect.min, Pos2::new(rect.max.x, rect.max.y - 14.0));
+    painter.line_segment(
+        [plot.left_bottom(), plot.right_bottom()],
+        Stroke::new(1.0, text),
+    );
+    let max = days.iter().map(|d| d.1.max(d.2)).max().unwrap_or(0).max(1);
+    let step = plot.width() / days.len().max(1) as f32;
+    let y = |v: u32| plot.bottom() - plot.height() * v as f32 / max as f32;
+    let mut points = Vec::with_capacity(days.len());
+    for (i, (_, added, evicted)) in days.iter().enumerate() {
+        let x = plot.left() + step * i as f32;
+        if *added > 0 {
+            painter.rect_filled(
+                Rect::from_min_max(
+                    Pos2::new(x + step * 0.15, y(*added)),
+                    Pos2::new(x + step * 0.85, plot.bottom()),
+                ),
+                1.0,
+                bar,
+            );
+        }
+        points.push(Pos2::new(x + step * 0.5, y(*evicted)));
+    }
+    painter.add(egui::Shape::line(points, Stroke::new(1.5, line)));
+    painter.text(
+        plot.left_top(),
+        egui::Align2::LEFT_TOP,
+        max.to_string(),
+        font.clone(),
+        text,
+    );
+    if let (Some(first), Some(last)) = (days.first(), days.last()) {
+        painter.text(
+            rect.left_bottom(),
+            egui::Align2::LEFT_BOTTOM,
+            day_display(first.0),
+            font.clone(),
+            text,
+        );
+        painter.text(
+            rect.right_bottom(),
+            egui::Align2::RIGHT_BOTTOM,
+            day_display(last.0),
+            font,
+            text,
+        );
+    }
+    if let Some(pos) = resp.hover_pos() {
+        let i = (((pos.x - plot.left()) / step) as usize).min(days.len().saturating_sub(1));
+        if let Some((d, added, evicted)) = days.get(i) {
+            resp.on_hover_text_at_pointer(format!(
+                "{}\ncopied {}\nevicted {}",
+                day_display(*d),
+                added,
+                evicted
+            ));
+        }
+    }
+}
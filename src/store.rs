@@ -0,0 +1,922 @@
+//! 历史记录存储
+//!
+//! 使用环形缓冲区保存记录，按 (时间, Lamport 时钟) 排序，其他设备和文件中的记录插入到对应的位置，
+//! 哈希索引用于去重，支持按条数和总字节数限制，
+//! 单条超过上限时按配置截断或拒绝。同时记录每天新增、淘汰的数量和重复复制的次数。
+//! 图片原图保存在 blob 中，记录里只有缩略图
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    hash::{DefaultHasher, Hash, Hasher},
+    path::PathBuf,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Clip,
+    blob::{self, Blobs, Image},
+    classify::{Kind, classify},
+};
+
+/// 单条记录超过大小上限时的处理方式
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Oversize {
+    /// 文本截断到上限，图片无法截断，直接拒绝
+    Truncate,
+    /// 直接拒绝
+    Reject,
+}
+
+/// 存储上限，保存在配置中
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct Limits {
+    /// 最多保存的条数
+    pub max_entries: usize,
+    /// 所有记录加起来的最大字节数
+    pub max_bytes: usize,
+    /// 单条记录的最大字节数
+    pub max_entry_bytes: usize,
+    pub oversize: Oversize,
+    /// 每条记录最多保留的历史版本，超过时丢弃最旧的
+    pub max_revisions: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_entries: 100,
+            max_bytes: 64 * 1024 * 1024,
+            max_entry_bytes: 8 * 1024 * 1024,
+            oversize: Oversize::Truncate,
+            max_revisions: 10,
+        }
+    }
+}
+
+pub(crate) struct Entry {
+    /// 递增的唯一id，删除后不会复用
+    pub id: u64,
+    /// 图片记录中是缩略图，原图使用 `Store::load` 读取
+    pub clip: Clip,
+    /// 图片的元数据
+    pub image: Option<Image>,
+    /// 列表中显示的摘要
+    pub preview: String,
+    /// 内容类型，和摘要一起生成
+    pub kind: Kind,
+    /// 编辑前的历史版本，旧的在前
+    pub revisions: Vec<String>,
+    /// 复制的时间戳，单位秒
+    pub time: u64,
+    /// Lamport 时钟，时间相同时用于排序
+    pub clock: u64,
+    /// 已存在时再次复制的次数
+    pub copies: u32,
+    /// 图片中识别出的文字，同时作为一条文本记录
+    pub ocr: Option<String>,
+    hash: u64,
+}
+
+impl Entry {
+    /// 占用的字节数，包括历史版本
+    pub fn size(&self) -> usize {
+        let size = match &self.image {
+            Some(img) => img.size,
+            None => clip_size(&self.clip),
+        };
+        size + self.revisions.iter().map(|r| r.len()).sum::<usize>()
+    }
+
+    fn key(&self) -> (u64, u64) {
+        (self.time, self.clock)
+    }
+}
+
+/// 按天计数保留的天数，更早的只保留淘汰的总数
+const STATS_DAYS: u64 = 90;
+
+/// 使用统计，按天计数
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct Stats {
+    /// 天 -> 复制的新记录数量
+    pub added: BTreeMap<u64, u32>,
+    /// 天 -> 因为超过上限被淘汰的数量
+    pub evicted: BTreeMap<u64, u32>,
+    /// 已经删除的按天计数中淘汰的数量
+    pub evicted_before: u32,
+}
+
+impl Stats {
+    fn count(&mut self, evicted: bool) {
+        let day = crate::time::day(crate::time::now());
+        let map = if evicted {
+            &mut self.evicted
+        } else {
+            &mut self.added
+        };
+        *map.entry(day).or_default() += 1;
+        self.prune(day);
+    }
+
+    /// 删除 STATS_DAYS 天之前的计数
+    fn prune(&mut self, today: u64) {
+        let first = today.saturating_sub(STATS_DAYS - 1);
+        self.added = self.added.split_off(&first);
+        let recent = self.evicted.split_off(&first);
+        let old = std::mem::replace(&mut self.evicted, recent);
+        self.evicted_before = self.evicted_before.saturating_add(old.values().sum());
+    }
+
+    /// 总共淘汰的数量
+    pub fn evicted_total(&self) -> u32 {
+        self.evicted_before + self.evicted.values().sum::<u32>()
+    }
+}
+
+/// push 的结果
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Push {
+    /// 新增，附带id
+    Added(u64),
+    /// 已存在相同内容，附带已有记录的id
+    Duplicate(u64),
+    /// 超过大小上限被拒绝
+    Rejected,
+    /// 比所有保留的记录都旧，加入后会立即被淘汰，没有加入
+    Stale,
+}
+
+pub(crate) struct Store {
+    /// 按 (时间, 时钟, id) 排序，旧的在前，新的在后
+    entries: VecDeque<Entry>,
+    /// id -> (时间, 时钟)，用于查找记录的位置
+    keys: HashMap<u64, (u64, u64)>,
+    /// 内容哈希 -> 这个哈希的所有 id，哈希相同时再比较内容
+    index: HashMap<u64, Vec<u64>>,
+    bytes: usize,
+    next_id: u64,
+    /// 本机的 Lamport 时钟，加入其他设备的记录时更新
+    clock: u64,
+    limits: Limits,
+    /// 每次修改都会递增，用于判断缓存是否失效
+    version: u64,
+    stats: Stats,
+    blobs: Blobs,
+}
+
+impl Store {
+    pub fn new(limits: Limits) -> Self {
+        Store {
+            entries: VecDeque::new(),
+            keys: HashMap::new(),
+            index: HashMap::new(),
+            bytes: 0,
+            next_id: 1,
+            clock: 0,
+            limits,
+            version: 0,
+            stats: Stats::default(),
+            blobs: Blobs::memory(),
+        }
+    }
+
+    /// 图片保存到目录中，需要在加入记录之前设置
+    pub fn set_blob_dir(&mut self, dir: PathBuf) {
+        self.blobs = Blobs::disk(dir);
+    }
+
+    /// 复制产生的新记录，计入统计
+    pub fn push(&mut self, clip: Clip) -> Push {
+        let res = self.add(clip, crate::time::now(), self.clock + 1);
+        match res {
+            Push::Added(_) => self.stats.count(false),
+            Push::Duplicate(id) => {
+                if let Some(pos) = self.position(id) {
+                    self.entries[pos].copies += 1;
+                    self.version += 1;
+                }
+            }
+            Push::Rejected | Push::Stale => {}
+        }
+        res
+    }
+
+    /// 从文件或其他设备加入的记录，按时间和时钟插入，不计入统计
+    pub fn insert(&mut self, clip: Clip, time: u64, clock: u64) -> Push {
+        self.add(clip, time, clock)
+    }
+
+    /// 从历史记录文件加入已经保存在 blob 中的图片，不计入统计
+    pub fn insert_blob(&mut self, image: Image, time: u64, clock: u64) -> Result<Push, String> {
+        if image.size > self.limits.max_entry_bytes {
+            s_info!("reject oversize clip");
+            return Ok(Push::Rejected);
+        }
+        if !self.blobs.contains(&image.hash) {
+            return Err(format!("image {} not found", image.hash));
+        }
+        let hash = image_hash(&image.hash);
+        if let Some(id) = self.duplicate(hash, None, Some(&image.hash)) {
+            return Ok(Push::Duplicate(id));
+        }
+        if self.stale(time, clock, image.size) {
+            return Ok(Push::Stale);
+        }
+        let thumb = self.blobs.thumb(&image.hash)?;
+        Ok(self.append(Clip::Img(thumb), Some(image), hash, time, clock))
+    }
+
+    fn add(&mut self, clip: Clip, time: u64, clock: u64) -> Push {
+        let clip = match self.fit(clip) {
+            Some(c) => c,
+            None => return Push::Rejected,
+        };
+        let Clip::Img(data) = clip else {
+            let hash = clip_hash(&clip);
+            if let Some(id) = self.duplicate(hash, Some(&clip), None) {
+                return Push::Duplicate(id);
+            }
+            if self.stale(time, clock, clip_size(&clip)) {
+                return Push::Stale;
+            }
+            return self.append(clip, None, hash, time, clock);
+        };
+        let sha = blob::hash(&data);
+        let hash = image_hash(&sha);
+        if let Some(id) = self.duplicate(hash, None, Some(&sha)) {
+            return Push::Duplicate(id);
+        }
+        if self.stale(time, clock, data.len()) {
+            return Push::Stale;
+        }
+        let (thumb, width, height) = blob::thumbnail(&data);
+        let image = Image {
+            hash: sha,
+            size: data.len(),
+            width,
+            height,
+        };
+        if let Err(e) = self.blobs.put(&image, &data, &thumb) {
+            s_error!("save image fail {}", e);
+            return Push::Rejected;
+        }
+        self.append(Clip::Img(thumb), Some(image), hash, time, clock)
+    }
+
+    /// 加入后是否会立即被淘汰。淘汰从最旧的开始，
+    /// 插入位置之前的记录都淘汰后仍然超过上限时，新记录自己也会被淘汰
+    fn stale(&self, time: u64, clock: u64, size: usize) -> bool {
+        let pos = self.insert_position(time, clock);
+        // 最新的一条总是保留
+        if pos == self.entries.len() {
+            return false;
+        }
+        let before: usize = self.entries.range(..pos).map(Entry::size).sum();
+        self.entries.len() - pos + 1 > self.limits.max_entries
+            || self.bytes - before + size > self.limits.max_bytes
+    }
+
+    /// 新记录的 id 最大，插入到时间和时钟都不大于它的记录之后
+    fn insert_position(&self, time: u64, clock: u64) -> usize {
+        self.entries.partition_point(|e| e.key() <= (time, clock))
+    }
+
+    /// 内容相同的记录，图片按 sha256 比较
+    fn duplicate(&self, hash: u64, clip: Option<&Clip>, sha: Option<&str>) -> Option<u64> {
+        let ids = self.index.get(&hash)?;
+        ids.iter().filter_map(|id| self.get(*id)).find_map(|e| {
+            let same = match (clip, sha, &e.image) {
+                (_, Some(sha), Some(img)) => img.hash == sha,
+                (Some(clip), None, None) => e.clip == clip,
+                _ => false,
+            };
+            same.then_some(e.id)
+        })
+    }
+
+    fn append(
+        &mut self,
+        clip: Clip,
+        image: Option<Image>,
+        hash: u64,
+        time: u64,
+        clock: u64,
+    ) -> Push {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.clock = self.clock.max(clock);
+        self.index.entry(hash).or_default().push(id);
+        self.version += 1;
+        let e = Entry {
+            id,
+            preview: preview(&clip),
+            kind: kind(&clip),
+            revisions: Vec::new(),
+            time,
+            clock,
+            copies: 0,
+            ocr: None,
+            clip,
+            image,
+            hash,
+        };
+        self.bytes += e.size();
+        self.keys.insert(id, e.key());
+        let pos = self.insert_position(time, clock);
+        self.entries.insert(pos, e);
+        self.evict();
+        Push::Added(id)
+    }
+
+    /// 读取完整内容，图片从 blob 中读取原图
+    pub fn load(&self, id: u64) -> Result<Clip, String> {
+        let e = self.get(id).ok_or("entry not found")?;
+        match (&e.clip, &e.image) {
+            (_, Some(img)) => self.blobs.get(&img.hash).map(Clip::Img),
+            (Clip::Text(t), None) => Ok(Clip::Text(t.clone())),
+            _ => Err("nothing to load".to_string()),
+        }
+    }
+
+    /// 图片保存在磁盘上时，用于在其他线程中读取原图
+    pub fn blob_reader(&self) -> Option<blob::Reader> {
+        self.blobs.reader()
+    }
+
+    /// 删除没有记录引用的图片，返回删除的数量，需要在保存历史记录之后调用
+    pub fn gc(&mut self) -> usize {
+        let live: HashSet<&str> = self
+            .entries
+            .iter()
+            .filter_map(|e| e.image.as_ref().map(|i| i.hash.as_str()))
+            .collect();
+        self.blobs.gc(&live)
+    }
+
+    pub fn remove(&mut self, id: u64) -> Option<Entry> {
+        let pos = self.position(id)?;
+        let e = self.entries.remove(pos)?;
+        self.version += 1;
+        self.unlink(&e);
+        Some(e)
+    }
+
+    /// 修改文本记录，旧内容保存为历史版本。和新记录一样按单条上限截断或拒绝，
+    /// 返回是否修改
+    pub fn edit(&mut self, id: u64, text: String) -> Result<bool, String> {
+        let Some(pos) = self.position(id) else {
+            return Ok(false);
+        };
+        if !matches!(self.entries[pos].clip, Clip::Text(_)) {
+            return Ok(false);
+        }
+        let Some(Clip::Text(text)) = self.fit(Clip::Text(text)) else {
+            return Err(format!(
+                "text exceeds max_entry_bytes ({} bytes)",
+                self.limits.max_entry_bytes
+            ));
+        };
+        if matches!(&self.entries[pos].clip, Clip::Text(old) if *old == text) {
+            return Ok(false);
+        }
+        let old = self.replace_text(pos, text);
+        self.entries[pos].revisions.push(old);
+        self.cap_revisions(pos);
+        self.relink(pos);
+        Ok(true)
+    }
+
+    /// 恢复某个历史版本，当前内容同样保存为历史版本
+    pub fn restore(&mut self, id: u64, revision: usize) -> bool {
+        let Some(pos) = self.position(id) else {
+            return false;
+        };
+        if revision >= self.entries[pos].revisions.len() {
+            return false;
+        }
+        // 先按原来的大小扣除，再移除恢复的版本
+        let text = self.entries[pos].revisions[revision].clone();
+        let old = self.replace_text(pos, text);
+        self.entries[pos].revisions.remove(revision);
+        self.entries[pos].revisions.push(old);
+        self.cap_revisions(pos);
+        self.relink(pos);
+        true
+    }
+
+    /// 设置历史版本，用于从文件加载
+    pub fn set_revisions(&mut self, id: u64, revisions: Vec<String>) {
+        if let Some(pos) = self.position(id) {
+            self.bytes -= self.entries[pos].size();
+            self.entries[pos].revisions = revisions;
+            self.cap_revisions(pos);
+            self.version += 1;
+            self.bytes += self.entries[pos].size();
+            self.evict();
+        }
+    }
+
+    /// 设置复制次数，用于从文件加载
+    pub fn set_copies(&mut self, id: u64, copies: u32) {
+        if let Some(pos) = self.position(id) {
+            self.entries[pos].copies = copies;
+            self.version += 1;
+        }
+    }
+
+    pub fn set_ocr(&mut self, id: u64, text: String) {
+        if let Some(pos) = self.position(id) {
+            self.entries[pos].ocr = Some(text);
+            self.version += 1;
+        }
+    }
+
+    /// 按内容查找记录
+    pub fn find(&self, clip: &Clip) -> Option<u64> {
+        match clip {
+            Clip::Img(d) => {
+                let sha = blob::hash(d);
+                self.duplicate(image_hash(&sha), None, Some(&sha))
+            }
+            _ => self.duplicate(clip_hash(clip), Some(clip), None),
+        }
+    }
+
+    pub fn get(&self, id: u64) -> Option<&Entry> {
+        self.position(id).map(|pos| &self.entries[pos])
+    }
+
+    /// 按从旧到新的顺序获取
+    pub fn at(&self, index: usize) -> Option<&Entry> {
+        self.entries.get(index)
+    }
+
+    /// 在从旧到新的顺序中的位置
+    pub fn index_of(&self, id: u64) -> Option<usize> {
+        self.position(id)
+    }
+
+    /// 从旧到新遍历
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Entry> + ExactSizeIterator {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// 当前占用的字节数
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// 从文件加载统计
+    pub fn set_stats(&mut self, stats: Stats) {
+        self.stats = stats;
+        self.stats.prune(crate::time::day(crate::time::now()));
+    }
+
+    fn position(&self, id: u64) -> Option<usize> {
+        let key = (*self.keys.get(&id)?, id);
+        self.entries
+            .binary_search_by_key(&key, |e| (e.key(), e.id))
+            .ok()
+    }
+
+    /// 检查单条大小，必要时截断
+    fn fit(&self, clip: Clip) -> Option<Clip> {
+        let max = self.limits.max_entry_bytes;
+        if clip_size(&clip) <= max {
+            return Some(clip);
+        }
+        match (clip, self.limits.oversize) {
+            (Clip::Text(mut t), Oversize::Truncate) => {
+                let mut end = max;
+                while !t.is_char_boundary(end) {
+                    end -= 1;
+                }
+                t.truncate(end);
+                s_info!("truncate text to {} bytes", end);
+                Some(Clip::Text(t))
+            }
+            _ => {
+                s_info!("reject oversize clip");
+                None
+            }
+        }
+    }
+
+    /// 淘汰最旧的记录直到满足上限，最新的一条总是保留
+    fn evict(&mut self) {
+        while self.entries.len() > 1
+            && (self.entries.len() > self.limits.max_entries || self.bytes > self.limits.max_bytes)
+        {
+            if let Some(e) = self.entries.pop_front() {
+                self.version += 1;
+                self.unlink(&e);
+                self.stats.count(true);
+            }
+        }
+    }
+
+    /// 替换文本内容，返回旧内容，调用后需要 relink
+    fn replace_text(&mut self, pos: usize, text: String) -> String {
+        self.version += 1;
+        let (hash, id, size) = {
+            let e = &self.entries[pos];
+            (e.hash, e.id, e.size())
+        };
+        self.bytes -= size;
+        self.unindex(hash, id);
+        let e = &mut self.entries[pos];
+        let clip = Clip::Text(text);
+        e.hash = clip_hash(&clip);
+        e.preview = preview(&clip);
+        e.kind = kind(&clip);
+        match std::mem::replace(&mut e.clip, clip) {
+            Clip::Text(old) => old,
+            _ => String::new(),
+        }
+    }
+
+    /// 丢弃超过上限的旧版本
+    fn cap_revisions(&mut self, pos: usize) {
+        let revisions = &mut self.entries[pos].revisions;
+        let over = revisions.len().saturating_sub(self.limits.max_revisions);
+        revisions.drain(..over);
+    }
+
+    /// 内容变化后重新建立索引和统计
+    fn relink(&mut self, pos: usize) {
+        let e = &self.entries[pos];
+        self.bytes += e.size();
+        self.index.entry(e.hash).or_default().push(e.id);
+        self.evict();
+    }
+
+    fn unlink(&mut self, e: &Entry) {
+        self.bytes -= e.size();
+        self.keys.remove(&e.id);
+        self.unindex(e.hash, e.id);
+    }
+
+    /// 从哈希索引中移除，这个哈希没有其他记录时删除整项
+    fn unindex(&mut self, hash: u64, id: u64) {
+        if let Some(ids) = self.index.get_mut(&hash) {
+            ids.retain(|v| *v != id);
+            if ids.is_empty() {
+                self.index.remove(&hash);
+            }
+        }
+    }
+}
+
+/// 摘要最多的行数
+const PREVIEW_LINES: usize = 2;
+/// 摘要每行最多的字符数
+const PREVIEW_CHARS: usize = 200;
+
+fn preview(clip: &Clip) -> String {
+    match clip {
+        Clip::Text(t) => preview_text(t),
+        _ => String::new(),
+    }
+}
+
+fn kind(clip: &Clip) -> Kind {
+    match clip {
+        Clip::Text(t) => classify(t),
+        _ => Kind::Image,
+    }
+}
+
+/// 生成摘要，避免每帧都对完整内容排版
+pub(crate) fn preview_text(t: &str) -> String {
+    let mut lines = t.lines().filter(|l| !l.trim().is_empty());
+    let mut res = Vec::with_capacity(PREVIEW_LINES);
+    for line in lines.by_ref().take(PREVIEW_LINES) {
+        let line = line.trim_end();
+        match line.char_indices().nth(PREVIEW_CHARS) {
+            Some((i, _)) => res.push(format!("{}…", &line[..i])),
+            None => res.push(line.to_string()),
+        }
+    }
+    let mut res = res.join("\n");
+    if lines.next().is_some() {
+        res.push('…');
+    }
+    res
+}
+
+fn clip_size(clip: &Clip) -> usize {
+    match clip {
+        Clip::Text(t) => t.len(),
+        Clip::Img(d) => d.len(),
+        Clip::Quit => 0,
+    }
+}
+
+/// 图片按 sha256 建立索引
+fn image_hash(sha: &str) -> u64 {
+    let mut h = DefaultHasher::new();
+    1u8.hash(&mut h);
+    sha.hash(&mut h);
+    h.finish()
+}
+
+fn clip_hash(clip: &Clip) -> u64 {
+    let mut h = DefaultHasher::new();
+    match clip {
+        Clip::Text(t) => {
+            0u8.hash(&mut h);
+            t.hash(&mut h);
+        }
+        Clip::Img(d) => return image_hash(&blob::hash(d)),
+        Clip::Quit => 2u8.hash(&mut h),
+    }
+    h.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(t: &str) -> Clip {
+        Clip::Text(t.to_string())
+    }
+
+    fn added(p: Push) -> u64 {
+        match p {
+            Push::Added(id) => id,
+            p => panic!("not added {:?}", p),
+        }
+    }
+
+    #[test]
+    fn evict_by_count() {
+        let mut s = Store::new(Limits {
+            max_entries: 3,
+            ..Limits::default()
+        });
+        let ids: Vec<u64> = (0..5)
+            .map(|i| added(s.push(text(&i.to_string()))))
+            .collect();
+        assert_eq!(s.len(), 3);
+        assert!(s.get(ids[0]).is_none() && s.get(ids[1]).is_none());
+        assert_eq!(s.iter().map(|e| e.id).collect::<Vec<_>>(), ids[2..]);
+        assert_eq!(s.stats().evicted_total(), 2);
+        // 被淘汰的内容可以重新加入
+        assert!(matches!(s.push(text("0")), Push::Added(_)));
+    }
+
+    #[test]
+    fn insert_by_time() {
+        let mut s = Store::new(Limits {
+            max_entries: 3,
+            ..Limits::default()
+        });
+        let b = added(s.insert(text("b"), 20, 0));
+        let d = added(s.insert(text("d"), 40, 0));
+        // 时间相同时按时钟排序
+        let c2 = added(s.insert(text("c2"), 30, 2));
+        let order = |s: &Store| s.iter().map(|e| e.id).collect::<Vec<_>>();
+        assert_eq!(order(&s), [b, c2, d]);
+        let c1 = added(s.insert(text("c1"), 30, 1));
+        // 只淘汰更旧的记录
+        assert_eq!(order(&s), [c1, c2, d]);
+        assert!(s.get(b).is_none());
+        // 比保留的记录都旧时不加入
+        assert_eq!(s.insert(text("a"), 10, 9), Push::Stale);
+        assert_eq!(order(&s), [c1, c2, d]);
+        // 本机新的记录排在最后，时钟大于收到的记录
+        let e = added(s.push(text("e")));
+        assert_eq!(order(&s), [c2, d, e]);
+        assert_eq!(s.get(e).unwrap().clock, 3);
+        assert!(s.get(c1).is_none());
+        assert!(s.get(c2).is_some() && s.index_of(d) == Some(1));
+    }
+
+    #[test]
+    fn prune_stats() {
+        let mut stats = Stats::default();
+        for day in 0..200 {
+            stats.added.insert(day, 1);
+            stats.evicted.insert(day, 2);
+        }
+        stats.prune(199);
+        assert_eq!(stats.added.len(), STATS_DAYS as usize);
+        assert_eq!(stats.added.keys().next(), Some(&(200 - STATS_DAYS)));
+        assert_eq!(stats.evicted.len(), STATS_DAYS as usize);
+        assert_eq!(stats.evicted_total(), 400);
+    }
+
+    #[test]
+    fn evict_by_bytes() {
+        let mut s = Store::new(Limits {
+            max_bytes: 10,
+            ..Limits::default()
+        });
+        s.push(text("aaaa"));
+        s.push(text("bbbb"));
+        assert_eq!((s.len(), s.bytes()), (2, 8));
+        s.push(text("cccc"));
+        assert_eq!((s.len(), s.bytes()), (2, 8));
+        assert!(s.find(&text("aaaa")).is_none());
+        // 最新的一条总是保留
+        s.push(text("x".repeat(20).as_str()));
+        assert_eq!((s.len(), s.bytes()), (1, 20));
+    }
+
+    #[test]
+    fn oversize() {
+        let limits = Limits {
+            max_entry_bytes: 5,
+            ..Limits::default()
+        };
+        let mut s = Store::new(limits);
+        // 截断到字符边界
+        let id = added(s.push(text("abc中文")));
+        assert!(s.get(id).unwrap().clip == text("abc"));
+        let id = added(s.push(text("ab中文")));
+        assert!(s.get(id).unwrap().clip == text("ab中"));
+
+        let mut s = Store::new(Limits {
+            oversize: Oversize::Reject,
+            ..limits
+        });
+        assert_eq!(s.push(text("abcdef")), Push::Rejected);
+        assert!(matches!(s.push(text("abcde")), Push::Added(_)));
+        assert_eq!(s.insert(Clip::Img(vec![0; 6]), 1, 0), Push::Rejected);
+    }
+
+    #[test]
+    fn dedupe_and_lookup() {
+        let mut s = Store::new(Limits::default());
+        let a = added(s.push(text("a")));
+        let b = added(s.push(text("b")));
+        assert_eq!(s.push(text("a")), Push::Duplicate(a));
+        assert_eq!(s.insert(text("a"), 1, 0), Push::Duplicate(a));
+        // 只有复制计入次数
+        assert_eq!(s.get(a).unwrap().copies, 1);
+        assert_eq!(s.len(), 2);
+        assert_eq!(s.find(&text("b")), Some(b));
+
+        assert!(s.remove(a).is_some());
+        assert!(s.get(a).is_none() && s.remove(a).is_none());
+        assert_eq!((s.index_of(b), s.at(0).map(|e| e.id)), (Some(0), Some(b)));
+        // 删除后 id 不复用
+        let c = added(s.push(text("a")));
+        assert!(c > b);
+        assert_eq!(s.index_of(c), Some(1));
+
+        // 编辑后按新内容去重
+        assert!(s.edit(b, "c".to_string()).unwrap());
+        assert_eq!(s.find(&text("c")), Some(b));
+        assert!(s.find(&text("b")).is_none());
+        assert_eq!(s.push(text("c")), Push::Duplicate(b));
+    }
+
+    #[test]
+    fn hash_collision() {
+        let mut s = Store::new(Limits::default());
+        // 两条不同的内容使用相同的哈希，都能按内容找到
+        let a = added(s.append(text("a"), None, 7, 1, 1));
+        let b = added(s.append(text("b"), None, 7, 2, 2));
+        assert_eq!(s.duplicate(7, Some(&text("a")), None), Some(a));
+        assert_eq!(s.duplicate(7, Some(&text("b")), None), Some(b));
+        assert_eq!(s.duplicate(7, Some(&text("c")), None), None);
+        s.remove(a);
+        assert_eq!(s.duplicate(7, Some(&text("a")), None), None);
+        assert_eq!(s.duplicate(7, Some(&text("b")), None), Some(b));
+        s.remove(b);
+        assert!(s.index.is_empty());
+    }
+
+    #[test]
+    fn edit_oversize() {
+        let mut s = Store::new(Limits {
+            max_entry_bytes: 4,
+            ..Limits::default()
+        });
+        let id = added(s.push(text("a")));
+        assert!(s.edit(id, "hello".to_string()).unwrap());
+        assert!(s.get(id).unwrap().clip == text("hell"));
+        assert_eq!(s.bytes(), 5);
+        // 截断后和当前内容相同时不修改
+        assert!(!s.edit(id, "hello world".to_string()).unwrap());
+
+        let mut s = Store::new(Limits {
+            max_entry_bytes: 4,
+            oversize: Oversize::Reject,
+            ..Limits::default()
+        });
+        let id = added(s.push(text("a")));
+        assert!(s.edit(id, "hello".to_string()).is_err());
+        assert!(s.get(id).unwrap().clip == text("a"));
+        assert!(s.get(id).unwrap().revisions.is_empty());
+    }
+
+    #[test]
+    fn revisions_capped() {
+        let mut s = Store::new(Limits {
+            max_revisions: 2,
+            ..Limits::default()
+        });
+        let id = added(s.push(text("0")));
+        for i in 1..5 {
+            assert!(s.edit(id, i.to_string()).unwrap());
+        }
+        let e = s.get(id).unwrap();
+        assert_eq!(e.revisions, ["2", "3"]);
+        assert_eq!(s.bytes(), 3);
+        // 恢复时当前内容成为最新的版本
+        assert!(s.restore(id, 0));
+        assert_eq!(s.get(id).unwrap().revisions, ["3", "4"]);
+        s.set_revisions(id, vec!["a".to_string(), "b".to_string(), "c".to_string()]);
+        assert_eq!(s.get(id).unwrap().revisions, ["b", "c"]);
+        assert_eq!(s.bytes(), 3);
+    }
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let img = image::DynamicImage::ImageRgb8(image::RgbImage::new(width, height));
+        crate::imaging::encode(&img, crate::imaging::Format::Png, 0).unwrap()
+    }
+
+    #[test]
+    fn image_blobs() {
+        let dir = crate::testdir::TempDir::new("store");
+        let limits = Limits {
+            max_entries: 2,
+            ..Limits::default()
+        };
+        let mut s = Store::new(limits);
+        s.set_blob_dir(dir.to_path_buf());
+        let big = png(600, 300);
+        let id = added(s.push(Clip::Img(big.clone())));
+        // 记录中只有缩略图，大小按原图计算
+        let e = s.get(id).unwrap();
+        let Clip::Img(thumb) = &e.clip else {
+            panic!("not an image");
+        };
+        let thumb = image::load_from_memory(thumb).unwrap();
+        assert_eq!(
+            (thumb.width(), thumb.height()),
+            (blob::THUMB, blob::THUMB / 2)
+        );
+        let img = e.image.clone().unwrap();
+        assert_eq!((img.width, img.height, img.size), (600, 300, big.len()));
+        assert_eq!((e.size(), s.bytes()), (big.len(), big.len()));
+        assert!(s.load(id) == Ok(Clip::Img(big.clone())));
+
+        // 相同的图片按 sha256 去重
+        assert_eq!(s.push(Clip::Img(big.clone())), Push::Duplicate(id));
+        assert_eq!(s.get(id).unwrap().copies, 1);
+
+        // 从历史记录恢复，缩略图从 blob 读取
+        let mut restored = Store::new(limits);
+        restored.set_blob_dir(dir.to_path_buf());
+        let copy = match restored.insert_blob(img.clone(), 1, 0) {
+            Ok(Push::Added(id)) => id,
+            _ => panic!("blob not inserted"),
+        };
+        assert!(restored.load(copy) == Ok(Clip::Img(big.clone())));
+        assert_eq!(
+            restored.insert_blob(img.clone(), 1, 0),
+            Ok(Push::Duplicate(copy))
+        );
+
+        // 删除后保存历史记录前文件仍然存在，gc 后删除原图和缩略图
+        assert!(s.remove(id).is_some());
+        assert!(dir.join(&img.hash).exists());
+        assert_eq!(s.gc(), 2);
+        assert!(!dir.join(&img.hash).exists());
+        assert!(restored.load(copy).is_err());
+        assert!(restored.insert_blob(img, 1, 0).is_err());
+
+        // 淘汰的图片同样在 gc 后删除
+        let small = added(s.push(Clip::Img(png(8, 8))));
+        let hash = s.get(small).unwrap().image.clone().unwrap().hash;
+        s.push(text("a"));
+        assert_eq!(s.gc(), 0);
+        s.push(text("b"));
+        assert!(s.get(small).is_none() && dir.join(&hash).exists());
+        assert_eq!(s.gc(), 2);
+        assert!(!dir.join(&hash).exists());
+    }
+}
@@ -0,0 +1,726 @@
+//! 局域网同步
+//!
+//! 使用相同密钥的设备互相同步新的记录。
+//! 通过 UDP 广播发现设备，记录通过 TCP 发送，每个包使用 ChaCha20-Poly1305 加密。
+//! 每个设备启动时生成随机的盐，用 PBKDF2 从共享密钥派生自己的密钥，盐明文附在包的开头，
+//! 收到其他设备的包时按盐派生对应的密钥，无法解密的包直接丢弃。
+//! 包中带有发送时间，时间相差太大或者重复收到的包会被丢弃，防止重放。
+//! TCP 连接的第一个包必须是认证过的 hello，之后才接收较大的包，一批记录按大小拆分成多个包。
+//! 记录带有发送方 store 中的时间和 Lamport 时钟，合并时按 (时间, 时钟) 插入到历史记录中对应的位置
+
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream, UdpSocket},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use chacha20poly1305::{
+    ChaCha20Poly1305, Key, KeyInit, Nonce,
+    aead::{Aead, AeadCore, OsRng, rand_core::RngCore},
+};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::{blob::Reader, persist::Record};
+
+/// 认证前第一个包的大小上限
+const MAX_HELLO: usize = 64 * 1024;
+/// 单个包的大小上限
+const MAX_FRAME: usize = 32 * 1024 * 1024;
+/// 一批记录拆分后每个包的大致大小
+const CHUNK: usize = 4 * 1024 * 1024;
+/// 同时处理的连接数，超过时直接关闭新连接
+const MAX_CONNECTIONS: usize = 8;
+/// 新设备上线时发送的最近记录数
+const BACKLOG: usize = 200;
+/// 广播间隔
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(5);
+/// 包中的时间和本机相差超过这个秒数时丢弃，设备之间的时钟需要大致同步
+const REPLAY_WINDOW: u64 = 120;
+/// 密钥派生的迭代次数
+const ROUNDS: u32 = if cfg!(test) { 1_000 } else { 100_000 };
+/// 同一个地址两次为未知的盐派生密钥的最小间隔，避免随机的包占满 cpu
+const DERIVE_INTERVAL: Duration = Duration::from_millis(200);
+/// 同时限制派生频率的地址数，超过时拒绝新的地址
+const MAX_SOURCES: usize = 1024;
+/// 缓存的其他设备的密钥数
+const MAX_KEYS: usize = 64;
+const SALT: usize = 16;
+const NONCE: usize = 12;
+
+/// 同步的一条记录
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct Item {
+    /// 产生记录的设备
+    pub device: String,
+    /// 带有时间和 Lamport 时钟
+    #[serde(flatten)]
+    pub record: Record,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Message {
+    /// 广播自己的 tcp 端口
+    Announce {
+        device: String,
+        port: u16,
+    },
+    /// tcp 连接的第一个包
+    Hello {
+        device: String,
+    },
+    Items {
+        items: Vec<Item>,
+    },
+}
+
+/// 加密的内容
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    /// 发送时间
+    time: u64,
+    msg: Message,
+}
+
+/// PBKDF2-HMAC-SHA256
+fn derive(secret: &[u8], salt: &[u8], rounds: u32) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(secret, salt, rounds, &mut key);
+    key
+}
+
+fn cipher(secret: &str, salt: &[u8]) -> ChaCha20Poly1305 {
+    ChaCha20Poly1305::new(Key::from_slice(&derive(secret.as_bytes(), salt, ROUNDS)))
+}
+
+/// 加密，包的格式为 盐 nonce 加密文，共享目录同步也使用
+pub(crate) struct Cipher {
+    secret: String,
+    salt: [u8; SALT],
+    own: ChaCha20Poly1305,
+    /// 其他设备的盐 -> 密钥，只缓存解密成功过的
+    keys: Mutex<HashMap<[u8; SALT], ChaCha20Poly1305>>,
+    /// 地址 -> 上次为未知的盐派生密钥的时间，每个地址单独限制，其他主机发送的包不会影响新设备
+    derived: Mutex<HashMap<IpAddr, Instant>>,
+    /// 最近收到的 nonce 和包中的时间
+    seen: Mutex<HashMap<[u8; NONCE], u64>>,
+}
+
+impl Cipher {
+    pub fn new(secret: &str) -> Self {
+        let mut salt = [0u8; SALT];
+        OsRng.fill_bytes(&mut salt);
+        Cipher {
+            secret: secret.to_string(),
+            salt,
+            own: cipher(secret, &salt),
+            keys: Mutex::new(HashMap::new()),
+            derived: Mutex::new(HashMap::new()),
+            seen: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn seal(&self, msg: Message) -> Result<Vec<u8>, String> {
+        self.seal_at(msg, crate::time::now())
+    }
+
+    fn seal_at(&self, msg: Message, time: u64) -> Result<Vec<u8>, String> {
+        let plain = serde_json::to_vec(&Envelope { time, msg }).map_err(|e| e.to_string())?;
+        self.encrypt(&plain)
+    }
+
+    /// 加密任意数据，不带时间
+    pub fn encrypt(&self, plain: &[u8]) -> Result<Vec<u8>, String> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let mut res = self.salt.to_vec();
+        res.extend_from_slice(&nonce);
+        res.extend(self.own.encrypt(&nonce, plain).map_err(|e| e.to_string())?);
+        Ok(res)
+    }
+
+    /// 拆分 盐 nonce 加密文 后解密。
+    /// from 为包的来源地址，网络上收到的包按地址限制为未知的盐派生密钥的频率
+    fn decrypt(&self, data: &[u8], from: Option<IpAddr>) -> Result<Vec<u8>, String> {
+        if data.len() < SALT + NONCE {
+            return Err("short packet".to_string());
+        }
+        let (salt, rest) = data.split_at(SALT);
+        let (nonce, body) = rest.split_at(NONCE);
+        let salt: &[u8; SALT] = salt.try_into().map_err(|_| "short packet")?;
+        let fail = |_| "decrypt fail, secret mismatch".to_string();
+        let nonce = Nonce::from_slice(nonce);
+        if *salt == self.salt {
+            return self.own.decrypt(nonce, body).map_err(fail);
+        }
+        if let Some(c) = self.keys.lock().map_err(|e| e.to_string())?.get(salt) {
+            return c.decrypt(nonce, body).map_err(fail);
+        }
+        if let Some(from) = from {
+            let mut derived = self.derived.lock().map_err(|e| e.to_string())?;
+            derived.retain(|_, t| t.elapsed() < DERIVE_INTERVAL);
+            if derived.contains_key(&from) {
+                return Err(format!("derive key too often for {}", from));
+            }
+            if derived.len() >= MAX_SOURCES {
+                return Err("derive key for too many sources".to_string());
+            }
+            derived.insert(from, Instant::now());
+        }
+        let c = cipher(&self.secret, salt);
+        let plain = c.decrypt(nonce, body).map_err(fail);
+        // 网络上的包解密成功才缓存，共享目录中的文件总是缓存，避免同一个文件的每行都重新派生
+        if plain.is_ok() || from.is_none() {
+            let mut keys = self.keys.lock().map_err(|e| e.to_string())?;
+            if keys.len() >= MAX_KEYS {
+                keys.clear();
+            }
+            keys.insert(*salt, c);
+        }
+        plain
+    }
+
+    /// 解密 encrypt 的结果，不检查重放
+    pub fn decrypt_data(&self, data: &[u8]) -> Result<Vec<u8>, String> {
+        self.decrypt(data, None)
+    }
+
+    fn open(&self, data: &[u8], from: IpAddr) -> Result<Message, String> {
+        let plain = self.decrypt(data, Some(from))?;
+        let env: Envelope = serde_json::from_slice(&plain).map_err(|e| e.to_string())?;
+        let now = crate::time::now();
+        if env.time.abs_diff(now) > REPLAY_WINDOW {
+            return Err(format!("stale packet {}", env.time));
+        }
+        let mut seen = self.seen.lock().map_err(|e| e.to_string())?;
+        let nonce: [u8; NONCE] = data[SALT..SALT + NONCE]
+            .try_into()
+            .map_err(|_| "short packet")?;
+        if seen.insert(nonce, env.time).is_some() {
+            return Err("replayed packet".to_string());
+        }
+        // 超出时间范围的包不会被接受，不需要再记住
+        seen.retain(|_, t| t.abs_diff(now) <= REPLAY_WINDOW);
+        Ok(env.msg)
+    }
+}
+
+/// 收到记录时的回调，参数已经按顺序排好
+pub(crate) type OnReceive = Box<dyn Fn(Vec<Record>) + Send + Sync>;
+
+struct Inner {
+    device: String,
+    cipher: Cipher,
+    /// 设备 -> tcp 地址
+    peers: Mutex<HashMap<String, SocketAddr>>,
+    /// 本机产生的最近记录，发送给新上线的设备，图片只保存引用
+    backlog: Mutex<Vec<Item>>,
+    /// 发送时读取图片
+    reader: Option<Reader>,
+    /// 正在处理的连接数
+    connections: AtomicUsize,
+    on_receive: OnReceive,
+}
+
+/// 同步节点
+#[derive(Clone)]
+pub(crate) struct Node {
+    inner: Arc<Inner>,
+    addr: SocketAddr,
+}
+
+impl Node {
+    /// 监听 tcp，bind 的端口为 0 时随机分配。
+    /// 发布的记录可以只带图片引用，发送时用 reader 读取
+    pub fn start(
+        device: String,
+        secret: &str,
+        bind: SocketAddr,
+        reader: Option<Reader>,
+        on_receive: OnReceive,
+    ) -> Result<Node, String> {
+        if secret.is_empty() {
+            return Err("empty sync secret".to_string());
+        }
+        let listener = TcpListener::bind(bind).map_err(|e| e.to_string())?;
+        let addr = listener.local_addr().map_err(|e| e.to_string())?;
+        let node = Node {
+            inner: Arc::new(Inner {
+                device,
+                cipher: Cipher::new(secret),
+                peers: Mutex::new(HashMap::new()),
+                backlog: Mutex::new(Vec::new()),
+                reader,
+                connections: AtomicUsize::new(0),
+                on_receive,
+            }),
+            addr,
+        };
+        let n = node.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(s) => {
+                        let connections = &n.inner.connections;
+                        if connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+                            connections.fetch_sub(1, Ordering::SeqCst);
+                            s_error!("sync too many connections, drop {:?}", s.peer_addr());
+                            continue;
+                        }
+                        let n = n.clone();
+                        thread::spawn(move || {
+                            if let Err(e) = n.handle(s) {
+                                s_error!("sync recv fail {}", e);
+                            }
+                            n.inner.connections.fetch_sub(1, Ordering::SeqCst);
+                        });
+                    }
+                    Err(e) => {
+                        s_error!("sync accept fail {}", e);
+                    }
+                }
+            }
+        });
+        s_info!("sync listen {}", addr);
+        Ok(node)
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// 在 udp 端口上广播和接收设备信息
+    pub fn discover(&self, port: u16) -> Result<(), String> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port)).map_err(|e| e.to_string())?;
+        socket.set_broadcast(true).map_err(|e| e.to_string())?;
+        let sender = socket.try_clone().map_err(|e| e.to_string())?;
+        let n = self.clone();
+        thread::spawn(move || {
+            loop {
+                // 每次重新加密，带上新的时间
+                let res = n
+                    .inner
+                    .cipher
+                    .seal(Message::Announce {
+                        device: n.inner.device.clone(),
+                        port: n.addr.port(),
+                    })
+                    .and_then(|announce| {
+                        sender
+                            .send_to(&announce, (Ipv4Addr::BROADCAST, port))
+                            .map_err(|e| e.to_string())
+                    });
+                if let Err(e) = res {
+                    s_error!("sync announce fail {}", e);
+                }
+                thread::sleep(ANNOUNCE_INTERVAL);
+            }
+        });
+        let n = self.clone();
+        thread::spawn(move || {
+            let mut buf = vec![0u8; 2048];
+            loop {
+                let Ok((len, from)) = socket.recv_from(&mut buf) else {
+                    continue;
+                };
+                // 其他密钥的设备直接忽略
+                if let Ok(Message::Announce { device, port }) =
+                    n.inner.cipher.open(&buf[..len], from.ip())
+                {
+                    n.add_peer(device, SocketAddr::new(from.ip(), port));
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// 添加设备，新设备会收到最近的记录
+    pub fn add_peer(&self, device: String, addr: SocketAddr) {
+        if device == self.inner.device {
+            return;
+        }
+        let new = match self.inner.peers.lock() {
+            Ok(mut peers) => peers.insert(device.clone(), addr) != Some(addr),
+            Err(_) => false,
+        };
+        if new {
+            s_info!("sync peer {} {}", device, addr);
+            let items = self
+                .inner
+                .backlog
+                .lock()
+                .map(|b| b.clone())
+                .unwrap_or_default();
+            if !items.is_empty() {
+                send_to(Arc::clone(&self.inner), addr, items);
+            }
+        }
+    }
+
+    /// 发送本机新的记录给所有设备
+    pub fn publish(&self, records: Vec<Record>) {
+        if records.is_empty() {
+            return;
+        }
+        let items: Vec<Item> = records
+            .into_iter()
+            .map(|record| Item {
+                device: self.inner.device.clone(),
+                record,
+            })
+            .collect();
+        if let Ok(mut backlog) = self.inner.backlog.lock() {
+            backlog.extend(items.iter().cloned());
+            let over = backlog.len().saturating_sub(BACKLOG);
+            backlog.drain(..over);
+        }
+        let peers: Vec<SocketAddr> = self
+            .inner
+            .peers
+            .lock()
+            .map(|p| p.values().copied().collect())
+            .unwrap_or_default();
+        for addr in peers {
+            send_to(Arc::clone(&self.inner), addr, items.clone());
+        }
+    }
+
+    fn handle(&self, mut s: TcpStream) -> Result<(), String> {
+        s.set_read_timeout(Some(Duration::from_secs(10)))
+            .map_err(|e| e.to_string())?;
+        let from = s.peer_addr().map_err(|e| e.to_string())?.ip();
+        // 认证之前只接收很小的包
+        let hello = read_frame(&mut s, MAX_HELLO)?.ok_or("closed before hello")?;
+        let Message::Hello { device } = self.inner.cipher.open(&hello, from)? else {
+            return Err("expect hello".to_string());
+        };
+        while let Some(frame) = read_frame(&mut s, MAX_FRAME)? {
+            let Message::Items { items } = self.inner.cipher.open(&frame, from)? else {
+                continue;
+            };
+            s_info!("sync recv {} items from {}", items.len(), device);
+            self.receive(items);
+        }
+        Ok(())
+    }
+
+    fn receive(&self, mut items: Vec<Item>) {
+        items.retain(|i| i.device != self.inner.device);
+        // 时间和时钟都相同时按设备排序，每个设备合并的顺序一致
+        items.sort_by(|a, b| {
+            (a.record.time, a.record.clock, &a.device).cmp(&(
+                b.record.time,
+                b.record.clock,
+                &b.device,
+            ))
+        });
+        (self.inner.on_receive)(items.into_iter().map(|i| i.record).collect());
+    }
+}
+
+/// 读取一个包，连接关闭时返回 None。
+/// 按实际收到的数据分配内存，不会预先分配包头中声明的长度
+fn read_frame(s: &mut impl Read, max: usize) -> Result<Option<Vec<u8>>, String> {
+    let mut len = [0u8; 4];
+    match s.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.to_string()),
+    }
+    let len = u32::from_be_bytes(len) as usize;
+    if len > max {
+        return Err(format!("frame too large {}", len));
+    }
+    let mut data = Vec::new();
+    s.take(len as u64)
+        .read_to_end(&mut data)
+        .map_err(|e| e.to_string())?;
+    if data.len() != len {
+        return Err("incomplete frame".to_string());
+    }
+    Ok(Some(data))
+}
+
+/// 按序列化后的大小把记录分成多个包依次发送。
+/// 图片在这里才读取，同一时间只有一个包的图片在内存中，读取失败或者超过包大小上限的记录跳过
+fn chunks(
+    items: Vec<Item>,
+    reader: Option<&Reader>,
+    mut send: impl FnMut(Vec<Item>) -> Result<(), String>,
+) -> Result<(), String> {
+    let mut chunk = Vec::new();
+    let mut size = 0;
+    for mut item in items {
+        item.record = match item.record.resolve(reader) {
+            Ok(r) => r,
+            Err(e) => {
+                s_error!("skip sync item {}", e);
+                continue;
+            }
+        };
+        let n = serde_json::to_vec(&item).map_or(usize::MAX, |v| v.len());
+        // 留出加密和包头的空间
+        if n > MAX_FRAME - 1024 {
+            s_error!("skip oversize sync item {}", n);
+            continue;
+        }
+        if !chunk.is_empty() && size + n > CHUNK {
+            send(std::mem::take(&mut chunk))?;
+            size = 0;
+        }
+        size += n;
+        chunk.push(item);
+    }
+    if !chunk.is_empty() {
+        send(chunk)?;
+    }
+    Ok(())
+}
+
+fn write_frame(s: &mut impl Write, frame: &[u8]) -> Result<(), String> {
+    s.write_all(&(frame.len() as u32).to_be_bytes())
+        .and_then(|_| s.write_all(frame))
+        .map_err(|e| e.to_string())
+}
+
+/// 在新线程中发送记录，先发送 hello，再按大小分成多个包发送
+fn send_to(inner: Arc<Inner>, addr: SocketAddr, items: Vec<Item>) {
+    thread::spawn(move || {
+        let res = (|| {
+            let mut s = TcpStream::connect_timeout(&addr, Duration::from_secs(3))
+                .map_err(|e| e.to_string())?;
+            let hello = inner.cipher.seal(Message::Hello {
+                device: inner.device.clone(),
+            })?;
+            write_frame(&mut s, &hello)?;
+            chunks(items, inner.reader.as_ref(), |items| {
+                write_frame(&mut s, &inner.cipher.seal(Message::Items { items })?)
+            })
+        })();
+        if let Err(e) = res {
+            s_error!("sync send {} fail {}", addr, e);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::{Receiver, channel};
+
+    use super::*;
+    use crate::{
+        Clip,
+        persist::Content,
+        store::{Limits, Push, Store},
+    };
+
+    fn node(device: &str, secret: &str) -> (Node, Receiver<Vec<Record>>) {
+        node_with(device, secret, None)
+    }
+
+    fn node_with(
+        device: &str,
+        secret: &str,
+        reader: Option<Reader>,
+    ) -> (Node, Receiver<Vec<Record>>) {
+        let (tx, rx) = channel();
+        let tx = Mutex::new(tx);
+        let node = Node::start(
+            device.to_string(),
+            secret,
+            (Ipv4Addr::LOCALHOST, 0).into(),
+            reader,
+            Box::new(move |r| {
+                let _ = tx.lock().unwrap().send(r);
+            }),
+        )
+        .unwrap();
+        (node, rx)
+    }
+
+    fn text(r: Record) -> String {
+        match r.into_clip().unwrap().0 {
+            Clip::Text(t) => t,
+            _ => String::new(),
+        }
+    }
+
+    #[test]
+    fn loopback() {
+        let (a, rx_a) = node("a", "secret");
+        let (b, rx_b) = node("b", "secret");
+        a.publish(vec![Record::new(Clip::Text("before".to_string()), 1)]);
+        // 新设备会先收到之前的记录
+        a.add_peer("b".to_string(), b.addr());
+        b.add_peer("a".to_string(), a.addr());
+        let got = rx_b.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].time, 1);
+        assert_eq!(text(got.into_iter().next().unwrap()), "before");
+
+        let mut one = Record::new(Clip::Text("one".to_string()), 2);
+        one.clock = 5;
+        // 按时间排序后合并
+        b.publish(vec![Record::new(Clip::Img(vec![1, 2, 3]), 3), one]);
+        let got = rx_a.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(got.len(), 2);
+        assert_eq!((got[0].time, got[0].clock), (2, 5));
+        assert_eq!(text(got.into_iter().next().unwrap()), "one");
+    }
+
+    #[test]
+    fn wrong_secret() {
+        let (a, _) = node("a", "secret");
+        let (b, rx_b) = node("b", "other");
+        a.add_peer("b".to_string(), b.addr());
+        a.publish(vec![Record::new(Clip::Text("hidden".to_string()), 1)]);
+        assert!(rx_b.recv_timeout(Duration::from_millis(500)).is_err());
+    }
+
+    #[test]
+    fn pbkdf2_vectors() {
+        let hex = |b: [u8; 32]| b.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+        assert_eq!(
+            hex(derive(b"password", b"salt", 1)),
+            "120fb6cffcf8b32c43e7225256c4f837a86548c92ccc35480805987cb70be17b"
+        );
+        assert_eq!(
+            hex(derive(b"password", b"salt", 4096)),
+            "c5e478d59288c841aa530db6845c4c8d962893a001ce4e11a4963873aa98134a"
+        );
+    }
+
+    #[test]
+    fn replay() {
+        let a = Cipher::new("secret");
+        let b = Cipher::new("secret");
+        // 不同设备的盐不同，密钥也不同
+        assert_ne!(a.salt, b.salt);
+        let hello = || Message::Hello {
+            device: "a".to_string(),
+        };
+        let local = IpAddr::from(Ipv4Addr::LOCALHOST);
+        let packet = a.seal(hello()).unwrap();
+        assert!(matches!(b.open(&packet, local), Ok(Message::Hello { .. })));
+        assert!(
+            b.open(&packet, local)
+                .is_err_and(|e| e.contains("replayed"))
+        );
+        let old = a.seal_at(hello(), crate::time::now() - 600).unwrap();
+        assert!(b.open(&old, local).is_err_and(|e| e.contains("stale")));
+        assert!(Cipher::new("other").open(&packet, local).is_err());
+    }
+
+    #[test]
+    fn derive_limit_per_source() {
+        let a = Cipher::new("secret");
+        let b = Cipher::new("secret");
+        let attacker = IpAddr::from(Ipv4Addr::new(10, 0, 0, 1));
+        let peer = IpAddr::from(Ipv4Addr::new(10, 0, 0, 2));
+        let hello = || Message::Hello {
+            device: "a".to_string(),
+        };
+        // 其他地址不断发送未知的盐时，新设备的包仍然可以解密
+        let mut junk = Cipher::new("other").seal(hello()).unwrap();
+        assert!(b.open(&junk, attacker).is_err());
+        junk[0] ^= 1;
+        assert!(
+            b.open(&junk, attacker)
+                .is_err_and(|e| e.contains("too often"))
+        );
+        assert!(matches!(
+            b.open(&a.seal(hello()).unwrap(), peer),
+            Ok(Message::Hello { .. })
+        ));
+    }
+
+    #[test]
+    fn unauthenticated_frames() {
+        let (a, rx_a) = node("a", "secret");
+        // 认证前声明很大的包直接断开
+        let mut s = TcpStream::connect(a.addr()).unwrap();
+        s.write_all(&(MAX_FRAME as u32).to_be_bytes()).unwrap();
+        let mut buf = [0u8; 1];
+        assert!(matches!(s.read(&mut buf), Ok(0) | Err(_)));
+        // 没有 hello 时不接收记录
+        let items = a.inner.cipher.seal(Message::Items { items: Vec::new() });
+        let mut s = TcpStream::connect(a.addr()).unwrap();
+        write_frame(&mut s, &items.unwrap()).unwrap();
+        drop(s);
+        assert!(rx_a.recv_timeout(Duration::from_millis(300)).is_err());
+
+        let mut frame = Vec::new();
+        write_frame(&mut frame, &[7; 10]).unwrap();
+        frame.truncate(8);
+        assert!(read_frame(&mut frame.as_slice(), 64).is_err());
+        assert!(read_frame(&mut [].as_slice(), 64).unwrap().is_none());
+    }
+
+    #[test]
+    fn chunked() {
+        let item = |n: usize| Item {
+            device: "a".to_string(),
+            record: Record::new(Clip::Text("x".repeat(n)), 0),
+        };
+        let sizes = |items: Vec<Item>| {
+            let mut res = Vec::new();
+            chunks(items, None, |c| {
+                res.push(c.len());
+                Ok(())
+            })
+            .unwrap();
+            res
+        };
+        assert_eq!(sizes(vec![item(10), item(10)]), [2]);
+        assert_eq!(
+            sizes(vec![item(CHUNK / 2), item(CHUNK / 2), item(1)]),
+            [1, 2]
+        );
+        assert!(sizes(vec![item(MAX_FRAME)]).is_empty());
+    }
+
+    #[test]
+    fn lazy_images() {
+        let dir = crate::testdir::TempDir::new("sync");
+        let mut store = Store::new(Limits::default());
+        store.set_blob_dir(dir.join("blobs"));
+        let img = image::DynamicImage::ImageRgb8(image::RgbImage::new(300, 300));
+        let png = crate::imaging::encode(&img, crate::imaging::Format::Png, 0).unwrap();
+        let Push::Added(id) = store.push(Clip::Img(png.clone())) else {
+            panic!("image not added");
+        };
+        let lazy = Record::lazy(store.get(id).unwrap(), &store).unwrap();
+        assert!(matches!(lazy.content, Content::Blob { .. }));
+        let mut missing = lazy.clone();
+        if let Content::Blob { blob, .. } = &mut missing.content {
+            *blob = "0".repeat(64);
+        }
+
+        let (a, _) = node_with("a", "secret", store.blob_reader());
+        let (b, rx_b) = node("b", "secret");
+        a.publish(vec![lazy, missing]);
+        // 等待发送的记录只带引用
+        assert!(
+            a.inner
+                .backlog
+                .lock()
+                .unwrap()
+                .iter()
+                .all(|i| matches!(i.record.content, Content::Blob { .. }))
+        );
+        a.add_peer("b".to_string(), b.addr());
+        // 读取失败的记录跳过
+        let got = rx_b.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(got.len(), 1);
+        assert!(matches!(
+            got.into_iter().next().unwrap().into_clip(),
+            Ok((Clip::Img(d), _)) if d == png
+        ));
+    }
+}
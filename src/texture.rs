@@ -0,0 +1,147 @@
+//! 图片记录的纹理缓存
+//!
+//! 按记录 id 缓存，每张图片只解码一次。记录删除或淘汰后释放对应的纹理，
+//! 窗口隐藏时全部释放
+
+use std::collections::HashMap;
+
+use eframe::egui::{self, load::SizedTexture};
+
+use crate::{Clip, store::Store};
+
+/// 解码结果，无法解码时保存错误，避免每帧重试
+type Slot = Result<egui::TextureHandle, String>;
+
+#[derive(Default)]
+pub(crate) struct Cache {
+    /// id -> 缩略图
+    thumbs: HashMap<u64, Slot>,
+    /// 正在预览的原图
+    full: Option<(u64, Slot)>,
+    /// 上次清理时 store 的版本
+    version: u64,
+}
+
+impl Cache {
+    /// 列表中的缩略图
+    pub fn thumb(
+        &mut self,
+        ctx: &egui::Context,
+        id: u64,
+        clip: &Clip,
+    ) -> Result<SizedTexture, String> {
+        let slot = self.thumbs.entry(id).or_insert_with(|| match clip {
+            Clip::Img(d) => load(ctx, &format!("clip {}", id), d),
+            _ => Err("not an image".to_string()),
+        });
+        sized(slot)
+    }
+
+    /// 预览的原图，只保留一张，切换时才读取
+    pub fn full(
+        &mut self,
+        ctx: &egui::Context,
+        id: u64,
+        read: impl FnOnce() -> Result<Clip, String>,
+    ) -> Result<SizedTexture, String> {
+        if self.full.as_ref().is_none_or(|(i, _)| *i != id) {
+            let slot = match read() {
+                Ok(Clip::Img(d)) => load(ctx, &format!("clip {} full", id), &d),
+                Ok(_) => Err("not an image".to_string()),
+                Err(e) => Err(e),
+            };
+            self.full = Some((id, slot));
+        }
+        match &self.full {
+            Some((_, slot)) => sized(slot),
+            None => Err("not an image".to_string()),
+        }
+    }
+
+    /// 不再预览时释放原图
+    pub fn forget_full(&mut self) {
+        self.full = None;
+    }
+
+    /// 释放已经不在 store 中的记录的纹理
+    pub fn prune(&mut self, store: &Store) {
+        if self.version == store.version() {
+            return;
+        }
+        self.version = store.version();
+        self.thumbs.retain(|id, _| store.get(*id).is_some());
+        if self
+            .full
+            .as_ref()
+            .is_some_and(|(id, _)| store.get(*id).is_none())
+        {
+            self.full = None;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.thumbs.clear();
+        self.full = None;
+    }
+}
+
+fn sized(slot: &Slot) -> Result<SizedTexture, String> {
+    match slot {
+        Ok(t) => Ok(SizedTexture::from_handle(t)),
+        Err(e) => Err(e.clone()),
+    }
+}
+
+/// 解码并上传纹理，超过显卡支持的尺寸时缩小
+fn load(ctx: &egui::Context, name: &str, data: &[u8]) -> Slot {
+    let img = image::load_from_memory(data).map_err(|e| e.to_string())?;
+    let max = ctx.input(|i| i.max_texture_side) as u32;
+    let img = if img.width() > max || img.height() > max {
+        img.thumbnail(max, max)
+    } else {
+        img
+    };
+    let rgba = img.to_rgba8();
+    let size = [rgba.width() as usize, rgba.height() as usize];
+    Ok(ctx.load_texture(
+        name,
+        egui::ColorImage::from_rgba_unmultiplied(size, rgba.as_raw()),
+        egui::TextureOptions::LINEAR,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::{Limits, Push};
+
+    #[test]
+    fn evict() {
+        let ctx = egui::Context::default();
+        let img = image::DynamicImage::ImageRgb8(image::RgbImage::new(8, 4));
+        let png = crate::imaging::encode(&img, crate::imaging::Format::Png, 0).unwrap();
+        let mut store = Store::new(Limits::default());
+        let Push::Added(id) = store.push(Clip::Img(png)) else {
+            panic!("image not added");
+        };
+        let mut cache = Cache::default();
+        let e = store.get(id).unwrap();
+        let a = cache.thumb(&ctx, id, &e.clip).unwrap();
+        // 第二次直接使用缓存
+        let b = cache.thumb(&ctx, id, &e.clip).unwrap();
+        assert_eq!((a.id, a.size), (b.id, egui::vec2(8.0, 4.0)));
+        assert!(cache.full(&ctx, id, || store.load(id)).is_ok());
+
+        cache.prune(&store);
+        assert_eq!(cache.thumbs.len(), 1);
+        store.remove(id);
+        cache.prune(&store);
+        assert!(cache.thumbs.is_empty() && cache.full.is_none());
+
+        cache
+            .thumb(&ctx, id, &Clip::Text("x".to_string()))
+            .unwrap_err();
+        cache.clear();
+        assert!(cache.thumbs.is_empty());
+    }
+}
@@ -0,0 +1,219 @@
+//! 时间相关，显示使用本地时区
+
+/// 时间戳转换，从1970年开始
+pub(crate) fn time_display(value: u64) -> String {
+    do_time_display(value, 1970, local_offset(value))
+}
+
+/// 时间戳转换，支持从不同年份开始计算，offset 是时区的偏移秒数
+pub(crate) fn do_time_display(value: u64, start_year: u64, offset: i64) -> String {
+    // 先粗略定位到哪一年
+    // 以 365 来计算，年通常只会相比正确值更晚，剩下的秒数也就更多，并且有可能出现需要往前一年的情况
+    let value = value.saturating_add_signed(offset);
+
+    let per_year_sec = 365 * 24 * 60 * 60; // 平年的秒数
+
+    let mut year = value / per_year_sec;
+    // 剩下的秒数，如果这些秒数 不够填补闰年，比如粗略计算是 2024年，还有 86300秒，不足一天，那么中间有很多闰年，所以 年应该-1，只有-1，因为-2甚至更多 需要 last_sec > 365 * 86400，然而这是不可能的
+    let last_sec = value - (year) * per_year_sec;
+    year += start_year;
+
+    let mut leap_year_sec = 0;
+    // 计算中间有多少闰年，当前年是否是闰年不影响回退，只会影响后续具体月份计算
+    for y in start_year..year {
+        if is_leap(y) {
+            // 出现了闰年
+            leap_year_sec += 86400;
+        }
+    }
+    if last_sec < leap_year_sec {
+        // 不够填补闰年，年份应该-1
+        year -= 1;
+        // 上一年是闰年，所以需要补一天
+        if is_leap(year) {
+            leap_year_sec -= 86400;
+        }
+    }
+    // 剩下的秒数
+    let mut time = value - leap_year_sec - (year - start_year) * per_year_sec;
+
+    // 平年的月份天数累加
+    let mut day_of_year: [u64; 12] = [31, 28, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+
+    // 找到了 计算日期
+    let sec = time % 60;
+    time /= 60;
+    let min = time % 60;
+    time /= 60;
+    let hour = time % 24;
+    time /= 24;
+
+    // 计算是哪天，因为每个月不一样多，所以需要修改
+    if is_leap(year) {
+        day_of_year[1] += 1;
+    }
+    let mut month = 0;
+    for (index, ele) in day_of_year.iter().enumerate() {
+        if &time < ele {
+            month = index + 1;
+            time += 1; // 日期必须加一，否则 每年的 第 1 秒就成了第0天了
+            break;
+        }
+        time -= ele;
+    }
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year, month, time, hour, min, sec
+    )
+}
+//
+// 判断是否是闰年
+//
+fn is_leap(year: u64) -> bool {
+    year % 4 == 0 && ((year % 100) != 0 || year % 400 == 0)
+}
+///
+/// 输出当前时间格式化，使用本地时区
+///
+/// 例如：
+/// 2023-09-28 17:32:24
+///
+pub(crate) fn time_format() -> String {
+    time_display(now())
+}
+
+/// 用于文件名，使用本地时区，例如 20230928-173224
+pub(crate) fn file_stamp(value: u64) -> String {
+    time_display(value)
+        .chars()
+        .filter_map(|c| match c {
+            '-' | ':' => None,
+            ' ' => Some('-'),
+            c => Some(c),
+        })
+        .collect()
+}
+
+/// 解析本地时间 `YYYY-MM-DD hh:mm:ss`，返回时间戳
+pub(crate) fn parse_local(s: &str) -> Option<u64> {
+    let local = do_parse(s, 0)?;
+    // 先按当前的偏移估算，再使用那个时间的偏移，夏令时前后也能得到正确的结果
+    let guess = local.saturating_add_signed(-local_offset(local));
+    Some(local.saturating_add_signed(-local_offset(guess)))
+}
+
+/// 解析 `YYYY-MM-DD hh:mm:ss`，offset 是时区的偏移秒数
+fn do_parse(s: &str, offset: i64) -> Option<u64> {
+    let (date, time) = s.trim().split_once(' ')?;
+    let mut date = date.split('-').map(|v| v.parse::<u64>().ok());
+    let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+    let mut time = time.split(':').map(|v| v.parse::<u64>().ok());
+    let (hour, min, sec) = (time.next()??, time.next()??, time.next()??);
+    if year < 1970 || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    if date.next().is_some() || time.next().is_some() || hour > 23 || min > 59 || sec > 60 {
+        return None;
+    }
+    let month_days = [31, 28, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+    let days = (1970..year)
+        .map(|y| if is_leap(y) { 366 } else { 365 })
+        .sum::<u64>()
+        + month_days[..month as usize - 1].iter().sum::<u64>()
+        + u64::from(month > 2 && is_leap(year))
+        + day
+        - 1;
+    let value = ((days * 24 + hour) * 60 + min) * 60 + sec;
+    value.checked_add_signed(-offset)
+}
+
+/// 时间戳所在的天，和显示使用相同的时区
+pub(crate) fn day(value: u64) -> u64 {
+    do_day(value, local_offset(value))
+}
+
+/// 时间戳加上时区偏移后所在的天
+fn do_day(value: u64, offset: i64) -> u64 {
+    value.saturating_add_signed(offset) / (24 * 60 * 60)
+}
+
+/// 天转换成 MM-DD，天已经按本地时区划分，不再换算
+pub(crate) fn day_display(day: u64) -> String {
+    do_time_display(day * 24 * 60 * 60, 1970, 0)[5..10].to_string()
+}
+
+/// 本地时区在某个时间相对 UTC 的偏移秒数，包括夏令时
+#[cfg(unix)]
+pub(crate) fn local_offset(value: u64) -> i64 {
+    let t = value as libc::time_t;
+    // SAFETY: localtime_r 只写入传入的 tm
+    unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&t, &mut tm).is_null() {
+            return 0;
+        }
+        tm.tm_gmtoff as i64
+    }
+}
+
+/// 本地时区在某个时间相对 UTC 的偏移秒数，包括夏令时
+#[cfg(not(unix))]
+pub(crate) fn local_offset(value: u64) -> i64 {
+    ::time::OffsetDateTime::from_unix_timestamp(value as i64)
+        .ok()
+        .and_then(|t| ::time::UtcOffset::local_offset_at(t).ok())
+        .map_or(0, |o| o.whole_seconds() as i64)
+}
+
+/// 当前时间戳，单位秒
+pub(crate) fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|v| v.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_with_offset() {
+        // 2024-02-29 23:30:00 UTC
+        let t = 1709249400;
+        assert_eq!(do_time_display(t, 1970, 0), "2024-02-29 23:30:00");
+        assert_eq!(do_time_display(t, 1970, 8 * 3600), "2024-03-01 07:30:00");
+        assert_eq!(do_time_display(t, 1970, -5 * 3600), "2024-02-29 18:30:00");
+    }
+
+    #[test]
+    fn parse_with_offset() {
+        // 2024-02-29 23:30:00 UTC
+        let t = 1709249400;
+        assert_eq!(do_parse("2024-02-29 23:30:00", 0), Some(t));
+        assert_eq!(do_parse("2024-03-01 07:30:00", 8 * 3600), Some(t));
+        assert_eq!(do_parse("2024-02-29 18:30:00", -5 * 3600), Some(t));
+        assert_eq!(do_parse("1970-01-01 00:00:00", 0), Some(0));
+        assert_eq!(
+            do_time_display(do_parse("2023-12-31 23:59:59", 0).unwrap(), 1970, 0),
+            "2023-12-31 23:59:59"
+        );
+        assert_eq!(do_parse("2024-13-01 00:00:00", 0), None);
+        assert_eq!(do_parse("2024-02-29", 0), None);
+        assert_eq!(do_parse("2024-02-29 23:30", 0), None);
+        assert_eq!(parse_local(&time_display(t)), Some(t));
+    }
+
+    #[test]
+    fn day_with_offset() {
+        // 2024-02-29 23:30:00 UTC
+        let t = 1709249400;
+        assert_eq!(day_display(do_day(t, 0)), "02-29");
+        assert_eq!(day_display(do_day(t, 8 * 3600)), "03-01");
+        assert_eq!(day_display(do_day(t, -5 * 3600)), "02-29");
+        // 2024-03-01 02:00:00 UTC，在 UTC-5 仍然是前一天
+        assert_eq!(day_display(do_day(t + 9000, -5 * 3600)), "02-29");
+        assert_eq!(do_day(t, 0) + 1, do_day(t, 8 * 3600));
+    }
+}
@@ -0,0 +1,455 @@
+//! 文本转换
+//!
+//! 大小写、json 格式化、编解码、行处理等
+
+use base64::{
+    Engine,
+    engine::general_purpose::{STANDARD, STANDARD_NO_PAD, URL_SAFE_NO_PAD},
+};
+
+use crate::config::{LineEnding, PlainText};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Transform {
+    Trim,
+    Upper,
+    Lower,
+    Title,
+    Snake,
+    Camel,
+    JsonPretty,
+    JsonMinify,
+    Base64Encode,
+    Base64Decode,
+    UrlEncode,
+    UrlDecode,
+    StripHtml,
+    SortLines,
+    DedupeLines,
+    EscapeShell,
+    EscapeJson,
+}
+
+impl Transform {
+    pub const ALL: [Transform; 17] = [
+        Transform::Trim,
+        Transform::Upper,
+        Transform::Lower,
+        Transform::Title,
+        Transform::Snake,
+        Transform::Camel,
+        Transform::JsonPretty,
+        Transform::JsonMinify,
+        Transform::Base64Encode,
+        Transform::Base64Decode,
+        Transform::UrlEncode,
+        Transform::UrlDecode,
+        Transform::StripHtml,
+        Transform::SortLines,
+        Transform::DedupeLines,
+        Transform::EscapeShell,
+        Transform::EscapeJson,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Transform::Trim => "trim whitespace",
+            Transform::Upper => "UPPER CASE",
+            Transform::Lower => "lower case",
+            Transform::Title => "Title Case",
+            Transform::Snake => "snake_case",
+            Transform::Camel => "camelCase",
+            Transform::JsonPretty => "json pretty",
+            Transform::JsonMinify => "json minify",
+            Transform::Base64Encode => "base64 encode",
+            Transform::Base64Decode => "base64 decode",
+            Transform::UrlEncode => "url encode",
+            Transform::UrlDecode => "url decode",
+            Transform::StripHtml => "strip html tags",
+            Transform::SortLines => "sort lines",
+            Transform::DedupeLines => "dedupe lines",
+            Transform::EscapeShell => "escape for shell",
+            Transform::EscapeJson => "escape for json",
+        }
+    }
+
+    pub fn apply(&self, text: &str) -> Result<String, String> {
+        match self {
+            Transform::Trim => Ok(text.trim().to_string()),
+            Transform::Upper => Ok(text.to_uppercase()),
+            Transform::Lower => Ok(text.to_lowercase()),
+            Transform::Title => Ok(title_case(text)),
+            Transform::Snake => Ok(words(text)
+                .iter()
+                .map(|w| w.to_lowercase())
+                .collect::<Vec<_>>()
+                .join("_")),
+            Transform::Camel => Ok(camel_case(text)),
+            Transform::JsonPretty => {
+                let v: serde_json::Value =
+                    serde_json::from_str(text).map_err(|e| format!("invalid json: {}", e))?;
+                serde_json::to_string_pretty(&v).map_err(|e| e.to_string())
+            }
+            Transform::JsonMinify => {
+                let v: serde_json::Value =
+                    serde_json::from_str(text).map_err(|e| format!("invalid json: {}", e))?;
+                serde_json::to_string(&v).map_err(|e| e.to_string())
+            }
+            Transform::Base64Encode => Ok(STANDARD.encode(text)),
+            Transform::Base64Decode => base64_decode(text),
+            Transform::UrlEncode => Ok(url_encode(text)),
+            Transform::UrlDecode => url_decode(text),
+            Transform::StripHtml => Ok(strip_html(text)),
+            Transform::SortLines => {
+                let mut lines: Vec<&str> = text.lines().collect();
+                lines.sort();
+                Ok(lines.join("\n"))
+            }
+            Transform::DedupeLines => {
+                let mut seen = std::collections::HashSet::new();
+                Ok(text
+                    .lines()
+                    .filter(|l| seen.insert(*l))
+                    .collect::<Vec<_>>()
+                    .join("\n"))
+            }
+            Transform::EscapeShell => Ok(format!("'{}'", text.replace('\'', r"'\''"))),
+            Transform::EscapeJson => serde_json::to_string(text).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// 转换成纯文本，统一空白字符和换行符
+pub(crate) fn plain_text(text: &str, cfg: &PlainText) -> String {
+    let eol = match cfg.line_ending {
+        LineEnding::Crlf => "\r\n",
+        LineEnding::Lf | LineEnding::Keep => "\n",
+    };
+    let mut res = String::with_capacity(text.len());
+    let mut rest = text;
+    while !rest.is_empty() {
+        let (line, ending, next) = match rest.find('\n') {
+            Some(i) if i > 0 && rest.as_bytes()[i - 1] == b'\r' => {
+                (&rest[..i - 1], "\r\n", &rest[i + 1..])
+            }
+            Some(i) => (&rest[..i], "\n", &rest[i + 1..]),
+            None => (rest, "", ""),
+        };
+        let mut line: String = line
+            .chars()
+            .filter(|c| !matches!(c, '\u{200b}' | '\u{200c}' | '\u{200d}' | '\u{feff}'))
+            .map(|c| {
+                if c == '\u{a0}' || c == '\u{3000}' {
+                    ' '
+                } else {
+                    c
+                }
+            })
+            .collect();
+        if cfg.collapse_spaces {
+            let mut collapsed = String::with_capacity(line.len());
+            for c in line.chars() {
+                let space = c == ' ' || c == '\t';
+                if !(space && collapsed.ends_with(' ')) {
+                    collapsed.push(if space { ' ' } else { c });
+                }
+            }
+            line = collapsed;
+        }
+        if cfg.trim_lines {
+            line.truncate(line.trim_end().len());
+        }
+        res.push_str(&line);
+        if !ending.is_empty() {
+            res.push_str(if cfg.line_ending == LineEnding::Keep {
+                ending
+            } else {
+                eol
+            });
+        }
+        rest = next;
+    }
+    if cfg.trim {
+        res.trim().to_string()
+    } else {
+        res
+    }
+}
+
+/// 拆分单词，支持空格、标点、下划线分隔以及驼峰
+fn words(text: &str) -> Vec<String> {
+    let mut res = Vec::new();
+    let mut cur = String::new();
+    let chars: Vec<char> = text.chars().collect();
+    for (i, c) in chars.iter().enumerate() {
+        if !c.is_alphanumeric() {
+            if !cur.is_empty() {
+                res.push(std::mem::take(&mut cur));
+            }
+            continue;
+        }
+        if c.is_uppercase() && !cur.is_empty() {
+            let prev = chars[i - 1];
+            let next_lower = chars.get(i + 1).is_some_and(|n| n.is_lowercase());
+            // fooBar 以及 HTTPServer 中的 Server
+            if prev.is_lowercase() || prev.is_numeric() || (prev.is_uppercase() && next_lower) {
+                res.push(std::mem::take(&mut cur));
+            }
+        }
+        cur.push(*c);
+    }
+    if !cur.is_empty() {
+        res.push(cur);
+    }
+    res
+}
+
+/// 首字母大写，其余小写
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(f) => f
+            .to_uppercase()
+            .chain(chars.flat_map(|c| c.to_lowercase()))
+            .collect(),
+        None => String::new(),
+    }
+}
+
+fn camel_case(text: &str) -> String {
+    let mut res = String::new();
+    for (i, w) in words(text).iter().enumerate() {
+        if i == 0 {
+            res.push_str(&w.to_lowercase());
+        } else {
+            res.push_str(&capitalize(w));
+        }
+    }
+    res
+}
+
+/// 每个单词首字母大写，保留原有的分隔符
+fn title_case(text: &str) -> String {
+    let mut res = String::with_capacity(text.len());
+    let mut start = true;
+    for c in text.chars() {
+        if c.is_alphanumeric() {
+            if start {
+                res.extend(c.to_uppercase());
+            } else {
+                res.extend(c.to_lowercase());
+            }
+            start = false;
+        } else {
+            res.push(c);
+            start = c != '\'';
+        }
+    }
+    res
+}
+
+fn base64_decode(text: &str) -> Result<String, String> {
+    let s: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    let s = s.trim_end_matches('=');
+    let bytes = STANDARD_NO_PAD
+        .decode(s)
+        .or_else(|_| URL_SAFE_NO_PAD.decode(s))
+        .map_err(|e| format!("invalid base64: {}", e))?;
+    String::from_utf8(bytes).map_err(|_| "decoded data is not utf-8 text".to_string())
+}
+
+fn url_encode(text: &str) -> String {
+    let mut res = String::with_capacity(text.len());
+    for b in text.bytes() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b'~') {
+            res.push(b as char);
+        } else {
+            res.push_str(&format!("%{:02X}", b));
+        }
+    }
+    res
+}
+
+fn url_decode(text: &str) -> Result<String, String> {
+    let bytes = text.as_bytes();
+    let mut res = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = text
+                .get(i + 1..i + 3)
+                .and_then(|h| u8::from_str_radix(h, 16).ok())
+                .ok_or_else(|| format!("invalid escape at {}", i))?;
+            res.push(hex);
+            i += 3;
+        } else {
+            res.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(res).map_err(|_| "decoded data is not utf-8 text".to_string())
+}
+
+/// 去掉标签，script 和 style 的内容一起去掉，并还原常见的实体
+fn strip_html(text: &str) -> String {
+    let mut res = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        res.push_str(&rest[..start]);
+        let tag = &rest[start..];
+        let end = match tag.find('>') {
+            Some(e) => e,
+            None => {
+                // 没有闭合，当作普通文本
+                res.push_str(tag);
+                rest = "";
+                break;
+            }
+        };
+        let name: String = tag[1..end]
+            .chars()
+            .take_while(|c| c.is_alphanumeric())
+            .collect::<String>()
+            .to_ascii_lowercase();
+        rest = &tag[end + 1..];
+        if name == "script" || name == "style" {
+            let close = format!("</{}", name);
+            match rest.to_ascii_lowercase().find(&close) {
+                Some(p) => {
+                    rest = &rest[p..];
+                    rest = rest.find('>').map(|e| &rest[e + 1..]).unwrap_or("");
+                }
+                None => rest = "",
+            }
+        }
+    }
+    res.push_str(rest);
+    decode_entities(&res)
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut res = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        res.push_str(&rest[..start]);
+        let after = &rest[start..];
+        let decoded = after.find(';').filter(|e| *e <= 10).and_then(|e| {
+            let c = match &after[1..e] {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some(' '),
+                n if n.starts_with("#x") || n.starts_with("#X") => u32::from_str_radix(&n[2..], 16)
+                    .ok()
+                    .and_then(char::from_u32),
+                n if n.starts_with('#') => n[1..].parse().ok().and_then(char::from_u32),
+                _ => None,
+            };
+            c.map(|c| (c, e))
+        });
+        match decoded {
+            Some((c, e)) => {
+                res.push(c);
+                rest = &after[e + 1..];
+            }
+            None => {
+                res.push('&');
+                rest = &after[1..];
+            }
+        }
+    }
+    res.push_str(rest);
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Transform::{self, *};
+    use crate::config::{LineEnding, PlainText};
+
+    fn check(t: Transform, input: &str, expected: &str) {
+        assert_eq!(t.apply(input).as_deref(), Ok(expected), "{}", t.name());
+    }
+
+    #[test]
+    fn case() {
+        check(Trim, "  a b \n", "a b");
+        check(Upper, "abc Äb", "ABC ÄB");
+        check(Lower, "ABC", "abc");
+        check(Title, "hello wORLD-foo it's", "Hello World-Foo It's");
+        check(Snake, "fooBar HTTPServer-x", "foo_bar_http_server_x");
+        check(Snake, "some text 2", "some_text_2");
+        check(Camel, "foo_bar baz", "fooBarBaz");
+        check(Camel, "XMLHttpRequest", "xmlHttpRequest");
+    }
+
+    #[test]
+    fn json() {
+        check(
+            JsonPretty,
+            r#"{"b":1,"a":[1,2]}"#,
+            "{\n  \"b\": 1,\n  \"a\": [\n    1,\n    2\n  ]\n}",
+        );
+        check(
+            JsonMinify,
+            "{ \"b\" : 1 ,\n \"a\": null }",
+            r#"{"b":1,"a":null}"#,
+        );
+        assert!(JsonPretty.apply("{a:1}").is_err());
+    }
+
+    #[test]
+    fn encode() {
+        check(Base64Encode, "中文 a", "5Lit5paHIGE=");
+        check(Base64Decode, "5Lit5paHIGE=", "中文 a");
+        check(Base64Decode, "5Lit5paH\nIGE", "中文 a");
+        assert!(Base64Decode.apply("/w==").is_err());
+        check(UrlEncode, "a b/中?x=1", "a%20b%2F%E4%B8%AD%3Fx%3D1");
+        check(UrlDecode, "a%20b%2F%E4%B8%AD%3Fx%3D1", "a b/中?x=1");
+        assert!(UrlDecode.apply("%zz").is_err());
+    }
+
+    #[test]
+    fn html() {
+        check(
+            StripHtml,
+            "<p class=\"x\">a &amp; <b>b</b></p><script>alert(1)</script>&#20013;&lt;",
+            "a & b中<",
+        );
+        check(StripHtml, "1 < 2", "1 < 2");
+    }
+
+    #[test]
+    fn lines() {
+        check(SortLines, "b\nc\na", "a\nb\nc");
+        check(DedupeLines, "b\na\nb\na\nc", "b\na\nc");
+    }
+
+    #[test]
+    fn escape() {
+        check(EscapeShell, "it's $HOME", r"'it'\''s $HOME'");
+        check(EscapeJson, "a\"b\n", r#""a\"b\n""#);
+    }
+
+    #[test]
+    fn plain() {
+        let text = "a\u{a0}b  \t c \r\n\u{200b}d\ne ";
+        let mut cfg = PlainText {
+            line_ending: LineEnding::Lf,
+            trim_lines: true,
+            collapse_spaces: false,
+            trim: false,
+        };
+        assert_eq!(super::plain_text(text, &cfg), "a b  \t c\nd\ne");
+        cfg.line_ending = LineEnding::Crlf;
+        cfg.collapse_spaces = true;
+        assert_eq!(super::plain_text(text, &cfg), "a b c\r\nd\r\ne");
+        cfg.line_ending = LineEnding::Keep;
+        cfg.trim_lines = false;
+        assert_eq!(super::plain_text(text, &cfg), "a b c \r\nd\ne ");
+        cfg.trim = true;
+        assert_eq!(super::plain_text(" \n x \n", &cfg), "x");
+    }
+}